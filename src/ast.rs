@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum AstNode {
     Command(CommandNode),
@@ -6,11 +8,13 @@ pub enum AstNode {
         node: Box<AstNode>,
         kind: RedirectKind,
         file: String,
+        span: Option<(usize, usize)>, // Of the word (or here-document body) in the source
     },
     Sequence(Vec<AstNode>),
     And(Box<AstNode>, Box<AstNode>),
     Or(Box<AstNode>, Box<AstNode>),
     Subshell(Box<AstNode>),
+    Background(Box<AstNode>), // cmd &
     Compound(CompoundNode),
 }
 
@@ -19,6 +23,9 @@ pub struct CommandNode {
     pub name: String,
     pub args: Vec<String>,
     pub kind: CommandKind,
+    // Byte ranges of the name and the arguments in the source, for error messages;
+    // empty for commands that were not parsed from it
+    pub spans: Vec<(usize, usize)>,
     // pub assignments: Vec<(String, String)>, // FOO=bar cmd
     // heredoc
 }
@@ -30,11 +37,48 @@ pub enum CommandKind {
     External,
 }
 
+// The number is the fd being redirected (e.g. 2 in `2>file`)
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RedirectKind {
-    In,
-    Out,
-    Append,
+    In(i32),        // [n]<file
+    Out(i32),       // [n]>file, refused by `noclobber` if the file exists
+    Append(i32),    // [n]>>file
+    Clobber(i32),   // [n]>|file, overwrites even with `noclobber`
+    ReadWrite(i32), // [n]<>file
+    DupIn(i32),     // [n]<&m, or [n]<&- to close
+    DupOut(i32),    // [n]>&m, or [n]>&- to close
+    OutErr,         // &>file: stdout and stderr
+    // [n]<<word or [n]<<-word (leading tabs stripped); the redirect's `file` is the body
+    Heredoc {
+        fd: i32,
+        delimiter: String,
+        strip_tabs: bool,
+    },
+    HereString(i32), // [n]<<<word
+}
+
+impl RedirectKind {
+    // The fds this redirect replaces
+    pub fn fds(&self) -> Vec<i32> {
+        match self {
+            RedirectKind::In(fd)
+            | RedirectKind::Out(fd)
+            | RedirectKind::Append(fd)
+            | RedirectKind::Clobber(fd)
+            | RedirectKind::ReadWrite(fd)
+            | RedirectKind::DupIn(fd)
+            | RedirectKind::DupOut(fd)
+            | RedirectKind::Heredoc { fd, .. }
+            | RedirectKind::HereString(fd) => vec![*fd],
+            RedirectKind::OutErr => vec![1, 2],
+        }
+    }
+
+    // Quoting any part of a here-document's delimiter (`<<'EOF'`, `<<\EOF`) turns off
+    // expansion in the body
+    pub fn is_quoted_heredoc(&self) -> bool {
+        matches!(self, RedirectKind::Heredoc { delimiter, .. } if delimiter.contains(['\'', '"', '\\']))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -49,6 +93,221 @@ pub enum CompoundNode {
         cond: Box<AstNode>,
         body: Vec<AstNode>,
     },
-    // for, function, etc
+    Until {
+        cond: Box<AstNode>,
+        body: Vec<AstNode>,
+    },
+    For {
+        var: String,
+        items: Vec<String>,         // Expanded at execution time
+        spans: Vec<(usize, usize)>, // Of the items in the source, as in CommandNode
+        body: Vec<AstNode>,
+    },
+    Arithmetic(String), // `((expr))`; succeeds when the value is not 0
+                        // function, etc
+}
+
+// Render nodes back into shell syntax (used for job listings)
+impl fmt::Display for AstNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AstNode::Command(cmd) => write!(f, "{}", cmd),
+            AstNode::Pipeline(nodes) => write_joined(f, nodes, " | "),
+            AstNode::Redirect { .. } => {
+                // Redirects apply outermost first, which is also their order in the source
+                let mut node = self;
+                let mut redirects = Vec::new();
+                while let AstNode::Redirect {
+                    node: inner,
+                    kind,
+                    file,
+                    ..
+                } = node
+                {
+                    redirects.push((kind, file));
+                    node = inner;
+                }
+                write!(f, "{}", node)?;
+                for (kind, file) in redirects {
+                    match kind {
+                        RedirectKind::DupIn(_) | RedirectKind::DupOut(_) => {
+                            write!(f, " {}{}", kind, file)?
+                        }
+                        // The body is not shown
+                        RedirectKind::Heredoc { delimiter, .. } => {
+                            write!(f, " {}{}", kind, delimiter)?
+                        }
+                        _ => write!(f, " {} {}", kind, file)?,
+                    }
+                }
+                Ok(())
+            }
+            AstNode::Sequence(nodes) => write_joined(f, nodes, "; "),
+            AstNode::And(left, right) => write!(f, "{} && {}", left, right),
+            AstNode::Or(left, right) => write!(f, "{} || {}", left, right),
+            AstNode::Subshell(inner) => write!(f, "({})", inner),
+            AstNode::Background(inner) => write!(f, "{} &", inner),
+            AstNode::Compound(compound) => write!(f, "{}", compound),
+        }
+    }
+}
+
+impl fmt::Display for CommandNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
 }
 
+impl fmt::Display for RedirectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The fd is only shown when it is not the operator's default
+        let (fd, default, op) = match self {
+            RedirectKind::In(fd) => (*fd, 0, "<"),
+            RedirectKind::Out(fd) => (*fd, 1, ">"),
+            RedirectKind::Append(fd) => (*fd, 1, ">>"),
+            RedirectKind::Clobber(fd) => (*fd, 1, ">|"),
+            RedirectKind::ReadWrite(fd) => (*fd, 0, "<>"),
+            RedirectKind::DupIn(fd) => (*fd, 0, "<&"),
+            RedirectKind::DupOut(fd) => (*fd, 1, ">&"),
+            RedirectKind::Heredoc {
+                fd,
+                strip_tabs: false,
+                ..
+            } => (*fd, 0, "<<"),
+            RedirectKind::Heredoc {
+                fd,
+                strip_tabs: true,
+                ..
+            } => (*fd, 0, "<<-"),
+            RedirectKind::HereString(fd) => (*fd, 0, "<<<"),
+            RedirectKind::OutErr => return write!(f, "&>"),
+        };
+        if fd == default {
+            write!(f, "{}", op)
+        } else {
+            write!(f, "{}{}", fd, op)
+        }
+    }
+}
+
+impl fmt::Display for CompoundNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompoundNode::Group(nodes) => {
+                write!(f, "{{ ")?;
+                write_list(f, nodes)?;
+                write!(f, " }}")
+            }
+            CompoundNode::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                write!(f, "if {}; then ", cond)?;
+                write_list(f, then_branch)?;
+                if let Some(nodes) = else_branch {
+                    write!(f, " else ")?;
+                    write_list(f, nodes)?;
+                }
+                write!(f, " fi")
+            }
+            CompoundNode::While { cond, body } | CompoundNode::Until { cond, body } => {
+                let keyword = if matches!(self, CompoundNode::While { .. }) {
+                    "while"
+                } else {
+                    "until"
+                };
+                write!(f, "{} {}; do ", keyword, cond)?;
+                write_list(f, body)?;
+                write!(f, " done")
+            }
+            CompoundNode::For {
+                var, items, body, ..
+            } => {
+                write!(f, "for {} in", var)?;
+                for item in items {
+                    write!(f, " {}", item)?;
+                }
+                write!(f, "; do ")?;
+                write_list(f, body)?;
+                write!(f, " done")
+            }
+            CompoundNode::Arithmetic(expr) => write!(f, "(({}))", expr),
+        }
+    }
+}
+
+fn write_joined(f: &mut fmt::Formatter<'_>, nodes: &[AstNode], sep: &str) -> fmt::Result {
+    for (i, node) in nodes.iter().enumerate() {
+        if i > 0 {
+            write!(f, "{}", sep)?;
+        }
+        write!(f, "{}", node)?;
+    }
+    Ok(())
+}
+
+// Each command of a compound list is terminated by `;`
+fn write_list(f: &mut fmt::Formatter<'_>, nodes: &[AstNode]) -> fmt::Result {
+    write_joined(f, nodes, "; ")?;
+    write!(f, ";")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmd(name: &str, args: &[&str]) -> AstNode {
+        AstNode::Command(CommandNode {
+            name: name.to_string(),
+            args: args.iter().map(|s| s.to_string()).collect(),
+            kind: CommandKind::Simple,
+            spans: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn test_display_pipeline_and_redirect() {
+        let node = AstNode::Background(Box::new(AstNode::Redirect {
+            node: Box::new(AstNode::Pipeline(vec![cmd("ls", &["-l"]), cmd("wc", &[])])),
+            kind: RedirectKind::Out(1),
+            file: "out.txt".to_string(),
+            span: None,
+        }));
+        assert_eq!(node.to_string(), "ls -l | wc > out.txt &");
+
+        let node = AstNode::Redirect {
+            node: Box::new(AstNode::Redirect {
+                node: Box::new(cmd("make", &[])),
+                kind: RedirectKind::DupOut(2),
+                file: "1".to_string(),
+                span: None,
+            }),
+            kind: RedirectKind::Append(1),
+            file: "log".to_string(),
+            span: None,
+        };
+        assert_eq!(node.to_string(), "make >> log 2>&1");
+    }
+
+    #[test]
+    fn test_display_compound() {
+        let node = AstNode::Compound(CompoundNode::For {
+            var: "f".to_string(),
+            items: vec!["a".to_string(), "b".to_string()],
+            spans: Vec::new(),
+            body: vec![AstNode::Or(
+                Box::new(cmd("test", &["-f", "$f"])),
+                Box::new(cmd("echo", &["$f"])),
+            )],
+        });
+        assert_eq!(
+            node.to_string(),
+            "for f in a b; do test -f $f || echo $f; done"
+        );
+    }
+}
@@ -1,11 +1,12 @@
 use std::collections::HashMap;
-use std::{ io, fmt };
-use std::io::{ BufRead, BufReader };
 use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::{fmt, io};
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub prompt: String,
+    pub continuation_prompt: String, // Shown while a command is incomplete (PS2)
     pub history_file: String,
     pub history_max: usize,
     pub executor_type: ExecutorType,
@@ -19,6 +20,7 @@ impl ConfigLoader {
     pub fn default_config() -> Config {
         Config {
             prompt: "$ ".to_string(),
+            continuation_prompt: "> ".to_string(),
             history_file: "~/.tiny_shell_history".to_string(),
             history_max: 500,
             executor_type: ExecutorType::Flatten,
@@ -40,6 +42,7 @@ impl ConfigLoader {
 
     pub fn load_from_str(src: &str) -> Result<Config, ConfigError> {
         let mut prompt = None;
+        let mut continuation_prompt = None;
         let mut history_file = None;
         let mut history_max = None;
         let mut executor_type = None;
@@ -47,12 +50,15 @@ impl ConfigLoader {
         let mut env_vars = HashMap::new();
 
         for (lineno, line) in src.lines().enumerate() {
-            let line = line;
             if line.is_empty() || line.starts_with('#') {
                 continue;
             }
             let Some((key, value)) = line.split_once('=') else {
-                return Err(ConfigError::Parse(format!("Line {}: No '=' found: {}", lineno+1, line)));
+                return Err(ConfigError::Parse(format!(
+                    "Line {}: No '=' found: {}",
+                    lineno + 1,
+                    line
+                )));
             };
             let key = key.trim();
             let value = if let Some(idx) = line.find('=') {
@@ -63,10 +69,17 @@ impl ConfigLoader {
 
             match key {
                 "prompt" => prompt = Some(value.to_string()),
+                "continuation_prompt" => continuation_prompt = Some(value.to_string()),
                 "history_file" => history_file = Some(value.to_string()),
                 "history_max" => match value.parse::<usize>() {
                     Ok(n) => history_max = Some(n),
-                    Err(_) => return Err(ConfigError::Parse(format!("Line {}: Invalid usize: {}", lineno+1, line))),
+                    Err(_) => {
+                        return Err(ConfigError::Parse(format!(
+                            "Line {}: Invalid usize: {}",
+                            lineno + 1,
+                            line
+                        )));
+                    }
                 },
                 "executor_type" => {
                     executor_type = match value {
@@ -82,13 +95,20 @@ impl ConfigLoader {
                     let var = k.trim_start_matches("env.").to_string();
                     env_vars.insert(var, value.to_string());
                 }
-                _ => return Err(ConfigError::Parse(format!("Line {}: Unknown key: {}", lineno+1, key))),
+                _ => {
+                    return Err(ConfigError::Parse(format!(
+                        "Line {}: Unknown key: {}",
+                        lineno + 1,
+                        key
+                    )));
+                }
             }
         }
 
         let default = ConfigLoader::default_config();
         Ok(Config {
             prompt: prompt.unwrap_or(default.prompt),
+            continuation_prompt: continuation_prompt.unwrap_or(default.continuation_prompt),
             history_file: history_file.unwrap_or(default.history_file),
             history_max: history_max.unwrap_or(default.history_max),
             executor_type: executor_type.unwrap_or(default.executor_type),
@@ -118,4 +138,3 @@ pub enum ExecutorType {
     Flatten,
     Recursive,
 }
-
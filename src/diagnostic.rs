@@ -0,0 +1,317 @@
+use crate::expander::ExpandError;
+use crate::lexer::LexError;
+use crate::parser::ParseError;
+use std::fmt::Write;
+
+// An error located in the source, shown with the offending line underlined:
+//
+// error[E201]: unexpected `>`
+//  --> script.sh:2:10
+//   |
+// 2 | echo a > > b
+//   |          ^
+//   = hint: did you mean `>>`?
+//
+// Codes: E1xx lexing, E2xx parsing, E3xx expansion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<(usize, usize)>, // Byte range in the source
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn from_lex(e: &LexError) -> Self {
+        let (code, message, hint) = match e {
+            LexError::UnexpectedChar(c, _) => {
+                ("E101", format!("unexpected character `{}`", c), None)
+            }
+            LexError::UnterminatedQuote(quote, _) => (
+                "E102",
+                "unterminated quote".to_string(),
+                Some(format!("add a closing `{}`", quote)),
+            ),
+            LexError::UnterminatedSubstitution(_) => (
+                "E103",
+                "unterminated substitution".to_string(),
+                Some("close it with `)`, `}` or a backquote".to_string()),
+            ),
+            LexError::UnterminatedHeredoc(delimiter, _) => (
+                "E104",
+                "here-document is not terminated".to_string(),
+                Some(format!(
+                    "end it with a line containing only `{}`",
+                    delimiter
+                )),
+            ),
+            LexError::TrailingBackslash(_) => (
+                "E105",
+                "backslash at the end of the input".to_string(),
+                Some("continue the command on the next line or remove it".to_string()),
+            ),
+            LexError::UnterminatedArithmetic(_) => (
+                "E106",
+                "unterminated arithmetic command".to_string(),
+                Some("close it with `))`".to_string()),
+            ),
+        };
+        Diagnostic {
+            code,
+            message,
+            span: Some(e.span()),
+            hint,
+        }
+    }
+
+    // The source is needed for hints that depend on the text before the error
+    pub fn from_parse(e: &ParseError, source: &str) -> Self {
+        match e {
+            ParseError::UnexpectedToken {
+                found,
+                expected,
+                span,
+            } => {
+                let message = match found.as_str() {
+                    "newline" | "end of input" => format!("unexpected {}", found),
+                    _ => format!("unexpected `{}`", found),
+                };
+                Diagnostic {
+                    code: "E201",
+                    message,
+                    span: Some(*span),
+                    hint: token_hint(found, expected, &source[..span.0.min(source.len())]),
+                }
+            }
+            ParseError::UnexpectedEof => {
+                let end = source.trim_end().len();
+                Diagnostic {
+                    code: "E202",
+                    message: "unexpected end of input".to_string(),
+                    span: Some((end, end)),
+                    hint: Some("the command is not complete".to_string()),
+                }
+            }
+            ParseError::UnmatchedParen { span } => Diagnostic {
+                code: "E203",
+                message: "unclosed `(`".to_string(),
+                span: Some(*span),
+                hint: Some("close the subshell with `)`".to_string()),
+            },
+            ParseError::UnclosedQuote { span, quote } => Diagnostic {
+                code: "E204",
+                message: "unclosed quote".to_string(),
+                span: Some(*span),
+                hint: Some(format!("add a closing `{}`", quote)),
+            },
+            ParseError::EmptyInput => Diagnostic {
+                code: "E205",
+                message: "empty input".to_string(),
+                span: None,
+                hint: None,
+            },
+        }
+    }
+
+    // The span is that of the source word the error came from, narrowed to the text the
+    // error is about if the word contains it
+    pub fn from_expand(e: &ExpandError, source: &str) -> Self {
+        let (span, e) = match e {
+            ExpandError::InWord { span, error } => (Some(*span), error.as_ref()),
+            e => (None, e),
+        };
+        let code = match e {
+            ExpandError::InvalidVariableSyntax(_) => "E301",
+            ExpandError::CommandSubstitutionFailed(_) => "E302",
+            ExpandError::GlobPatternError(_) => "E303",
+            ExpandError::GlobNoMatch(_) => "E304",
+            ExpandError::TildeExpandFailed(_) => "E305",
+            ExpandError::IoError(_) => "E306",
+            ExpandError::Unsupported(_) => "E307",
+            ExpandError::Interrupted => "E308",
+            ExpandError::DivisionByZero(_) => "E309",
+            ExpandError::ArithmeticError { .. } => "E310",
+            ExpandError::UnsetParameter { .. } => "E311",
+            ExpandError::AmbiguousRedirect(_) => "E312",
+            ExpandError::InWord { .. } => unreachable!("errors are located once"),
+        };
+        let subject = e.subject().filter(|subject| !subject.is_empty());
+        let span = span.map(|(start, end)| {
+            match subject
+                .and_then(|subject| Some((source.get(start..end)?.find(subject)?, subject.len())))
+            {
+                Some((i, len)) => (start + i, start + i + len),
+                None => (start, end),
+            }
+        });
+        let hint = match e {
+            ExpandError::GlobNoMatch(_) => {
+                Some("`failglob` is set; quote the word to use it literally".to_string())
+            }
+            _ => None,
+        };
+        Diagnostic {
+            code,
+            message: e.to_string(),
+            span,
+            hint,
+        }
+    }
+
+    // `origin` names the script file; the location is then shown as file:line:column
+    pub fn render(&self, source: &str, origin: Option<&str>) -> String {
+        let mut out = format!("error[{}]: {}\n", self.code, self.message);
+        let Some((start, end)) = self.span else {
+            if let Some(origin) = origin {
+                let _ = writeln!(out, " --> {}", origin);
+            }
+            if let Some(hint) = &self.hint {
+                let _ = writeln!(out, " = hint: {}", hint);
+            }
+            return out;
+        };
+        let start = floor_char_boundary(source, start);
+        let end = floor_char_boundary(source, end.max(start));
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line_no = source[..start].matches('\n').count() + 1;
+        let column = source[line_start..start].chars().count() + 1;
+        let gutter = " ".repeat(line_no.to_string().len());
+
+        if let Some(origin) = origin {
+            let _ = writeln!(out, "{}--> {}:{}:{}", gutter, origin, line_no, column);
+        }
+        let _ = writeln!(out, "{} |", gutter);
+        let _ = writeln!(out, "{} | {}", line_no, &source[line_start..line_end]);
+        // Tabs are kept so that the underline lines up with the text above
+        let indent: String = source[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = source[start..end.min(line_end)].chars().count().max(1);
+        let _ = writeln!(out, "{} | {}^{}", gutter, indent, "~".repeat(width - 1));
+        if let Some(hint) = &self.hint {
+            let _ = writeln!(out, "{} = hint: {}", gutter, hint);
+        }
+        out
+    }
+}
+
+// Hints for an unexpected token, from the text before it
+fn token_hint(found: &str, expected: &[String], before: &str) -> Option<String> {
+    let before = before.trim_end_matches([' ', '\t']);
+    // `> >`, `| |`, `& &` and `< <` written apart
+    if matches!(found, ">" | "|" | "&" | "<")
+        && let Some(rest) = before.strip_suffix(found)
+        && !rest.ends_with(found)
+    {
+        return Some(format!("did you mean `{}{}`?", found, found));
+    }
+    if found == ")" {
+        return Some("there is no `(` for this `)` to close".to_string());
+    }
+    if expected.iter().any(|e| e == "Word") && before.ends_with(['<', '>']) {
+        return Some("a redirection needs a file name after the operator".to_string());
+    }
+    let expected: Vec<String> = expected
+        .iter()
+        .filter_map(|e| match e.as_str() {
+            "Eof" | "Compound" | "HeredocBody" => None,
+            "Word" => Some("a word".to_string()),
+            "Name" => Some("a variable name".to_string()),
+            "Semicolon" => Some("`;`".to_string()),
+            "Newline" => Some("a newline".to_string()),
+            keyword => Some(format!("`{}`", keyword.to_lowercase())),
+        })
+        .collect();
+    (!expected.is_empty()).then(|| format!("expected {}", expected.join(" or ")))
+}
+
+fn floor_char_boundary(s: &str, mut index: usize) -> usize {
+    index = index.min(s.len());
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::{DefaultParser, Parser};
+
+    // Lex and parse `source`, rendering the error it must contain
+    fn diagnose(source: &str, origin: Option<&str>) -> String {
+        let diagnostic = match Lexer::new(source).tokenize_all() {
+            Ok(tokens) => {
+                Diagnostic::from_parse(&DefaultParser::new(&tokens).parse().unwrap_err(), source)
+            }
+            Err(e) => Diagnostic::from_lex(&e),
+        };
+        diagnostic.render(source, origin)
+    }
+
+    #[test]
+    fn test_render_with_hint() {
+        assert_eq!(
+            diagnose("echo a > > b", None),
+            "error[E201]: unexpected `>`\n  |\n1 | echo a > > b\n  |          ^\n  = hint: did you mean `>>`?\n"
+        );
+        assert!(diagnose("ls | | wc", None).contains("did you mean `||`?"));
+        assert!(diagnose("echo hi >\n", None).contains("a redirection needs a file name"));
+        assert!(diagnose("echo )", None).contains("there is no `(`"));
+    }
+
+    #[test]
+    fn test_render_script_location() {
+        let source = "echo ok\nif true; then\n  echo \"é\" 'unclosed\nfi\n";
+        assert_eq!(
+            diagnose(source, Some("script.sh")),
+            "error[E102]: unterminated quote\n --> script.sh:3:12\n  |\n3 |   echo \"é\" 'unclosed\n  |            ^\n  = hint: add a closing `'`\n"
+        );
+        let rendered = diagnose("for 1x in a; do echo; done", Some("s"));
+        assert!(rendered.contains(" --> s:1:5\n"), "{}", rendered);
+        assert!(rendered.contains("    ^~\n"), "{}", rendered);
+        assert!(
+            rendered.contains("expected a variable name"),
+            "{}",
+            rendered
+        );
+    }
+
+    #[test]
+    fn test_expand_error_span() {
+        // The word the error came from, not the first text like it
+        let source = "echo *.none; ls x *.none";
+        let error = ExpandError::GlobNoMatch("*.none".to_string()).in_word(Some((18, 24)));
+        let diagnostic = Diagnostic::from_expand(&error, source);
+        assert_eq!(diagnostic.code, "E304");
+        assert_eq!(diagnostic.span, Some((18, 24)));
+        assert!(
+            diagnostic
+                .render(source, None)
+                .contains("                  ^~~~~~\n")
+        );
+
+        // Narrowed to the text the error is about, or the whole word
+        let error = ExpandError::DivisionByZero("1 / 0".to_string()).in_word(Some((5, 17)));
+        assert_eq!(
+            Diagnostic::from_expand(&error, "echo a$((1 / 0))b").span,
+            Some((9, 14))
+        );
+        let unset = || ExpandError::UnsetParameter {
+            name: "zz".to_string(),
+            message: "msg".to_string(),
+        };
+        let diagnostic =
+            Diagnostic::from_expand(&unset().in_word(Some((5, 15))), "echo ${zz:?msg}");
+        assert_eq!((diagnostic.code, diagnostic.span), ("E311", Some((5, 15))));
+        assert_eq!(
+            Diagnostic::from_expand(&unset(), "echo ${zz:?msg}").span,
+            None
+        );
+    }
+}
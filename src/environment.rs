@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Clone, PartialEq)]
 struct Variable {
@@ -9,12 +9,36 @@ struct Variable {
 #[derive(Debug, Clone, PartialEq)]
 pub struct Environment {
     vars: HashMap<String, Variable>,
+    options: HashSet<String>,       // Enabled shell options (`set -o NAME`)
+    traps: HashMap<String, String>, // `trap` commands by condition name (`INT`, `EXIT`, `ERR`, ...)
+    script_name: String,            // `$0`
+    positional: Vec<String>,        // `$1`, `$2`, ... (script arguments, `set --`)
+    shell_pid: u32,                 // `$$`, which subshells keep
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Environment {
+    // Options accepted by `set -o NAME`
+    pub const OPTION_NAMES: &'static [&'static str] =
+        &["pipefail", "nullglob", "failglob", "noclobber"];
+    // Options with a short flag (`set -C`), shown in `$-`
+    pub const OPTION_FLAGS: &'static [(char, &'static str)] = &[('C', "noclobber")];
+
     pub fn new() -> Self {
         let mut env = Environment {
             vars: HashMap::new(),
+            options: HashSet::new(),
+            traps: HashMap::new(),
+            script_name: std::env::args()
+                .next()
+                .unwrap_or_else(|| "tiny-shell-rs".to_string()),
+            positional: Vec::new(),
+            shell_pid: std::process::id(),
         };
 
         // Import all OS environment variables when starting the process (default value)
@@ -62,6 +86,83 @@ impl Environment {
             .collect()
     }
 
+    // Exit status of the most recent command (`$?`)
+    pub fn set_last_status(&mut self, code: i32) {
+        self.set("?", &code.to_string());
+    }
+
+    pub fn last_status(&self) -> i32 {
+        self.get("?").and_then(|v| v.parse().ok()).unwrap_or(0)
+    }
+
+    pub fn script_name(&self) -> &str {
+        &self.script_name
+    }
+
+    pub fn set_script_name(&mut self, name: &str) {
+        self.script_name = name.to_string();
+    }
+
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    pub fn set_positional(&mut self, args: Vec<String>) {
+        self.positional = args;
+    }
+
+    pub fn shell_pid(&self) -> u32 {
+        self.shell_pid
+    }
+
+    // `$-`: the short flags of the enabled options
+    pub fn flags(&self) -> String {
+        Self::OPTION_FLAGS
+            .iter()
+            .filter(|(_, name)| self.option(name))
+            .map(|(flag, _)| *flag)
+            .collect()
+    }
+
+    pub fn option(&self, name: &str) -> bool {
+        self.options.contains(name)
+    }
+
+    pub fn set_option(&mut self, name: &str, enabled: bool) {
+        if enabled {
+            self.options.insert(name.to_string());
+        } else {
+            self.options.remove(name);
+        }
+    }
+
+    pub fn trap(&self, name: &str) -> Option<&str> {
+        self.traps.get(name).map(|s| s.as_str())
+    }
+
+    // `None` removes the trap; an empty command means the signal is ignored
+    pub fn set_trap(&mut self, name: &str, command: Option<&str>) {
+        match command {
+            Some(command) => {
+                self.traps.insert(name.to_string(), command.to_string());
+            }
+            None => {
+                self.traps.remove(name);
+            }
+        }
+    }
+
+    // All traps sorted by name, for `trap -p`
+    pub fn traps(&self) -> Vec<(String, String)> {
+        let mut traps: Vec<(String, String)> = self
+            .traps
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        traps.sort();
+        traps
+    }
+
     pub fn exported_vars(&self) -> Vec<(String, String)> {
         self.vars
             .iter()
@@ -106,6 +207,57 @@ mod tests {
         assert!(exported.iter().any(|(k, v)| k == "FOO" && v == "bar"));
     }
 
+    #[test]
+    fn test_last_status() {
+        let mut env = Environment::new();
+        assert_eq!(env.last_status(), 0);
+        env.set_last_status(127);
+        assert_eq!(env.get("?"), Some("127"));
+        assert_eq!(env.last_status(), 127);
+    }
+
+    #[test]
+    fn test_options() {
+        let mut env = Environment::new();
+        assert!(!env.option("pipefail"));
+        env.set_option("pipefail", true);
+        assert!(env.option("pipefail"));
+        env.set_option("pipefail", false);
+        assert!(!env.option("pipefail"));
+        assert_eq!(env.flags(), "");
+        env.set_option("noclobber", true);
+        assert_eq!(env.flags(), "C");
+    }
+
+    #[test]
+    fn test_positional_parameters() {
+        let mut env = Environment::new();
+        assert!(env.positional().is_empty());
+        env.set_positional(vec!["a b".to_string(), "c".to_string()]);
+        env.set_script_name("script.sh");
+        let copy = env.clone();
+        assert_eq!(copy.positional(), ["a b", "c"]);
+        assert_eq!(copy.script_name(), "script.sh");
+        assert_eq!(copy.shell_pid(), std::process::id());
+    }
+
+    #[test]
+    fn test_traps() {
+        let mut env = Environment::new();
+        env.set_trap("INT", Some("echo caught"));
+        env.set_trap("EXIT", Some("rm -f tmp"));
+        assert_eq!(env.trap("INT"), Some("echo caught"));
+        assert_eq!(
+            env.traps(),
+            vec![
+                ("EXIT".to_string(), "rm -f tmp".to_string()),
+                ("INT".to_string(), "echo caught".to_string()),
+            ]
+        );
+        env.set_trap("INT", None);
+        assert_eq!(env.trap("INT"), None);
+    }
+
     #[test]
     fn test_all_and_exported_vars() {
         let mut env = Environment::new();
@@ -1,8 +1,8 @@
-use std::fmt;
+use crate::config::ConfigError;
+use crate::executor::ExecError;
 use crate::lexer::LexError;
 use crate::parser::ParseError;
-use crate::executor::ExecError;
-use crate::config::ConfigError;
+use std::fmt;
 
 #[derive(Debug)]
 pub enum ShellError {
@@ -39,4 +39,3 @@ impl From<std::io::Error> for ShellError {
         ShellError::Io(e)
     }
 }
-
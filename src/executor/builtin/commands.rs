@@ -1,9 +1,9 @@
-use std::cell::RefCell;
-use std::rc::Rc;
-use crate::history::HistoryManager;
-use crate::executor::{ ExecStatus, ExecOutcome, ExecError };
 use crate::environment::Environment;
 use crate::executor::builtin::manager::BuiltinCommand;
+use crate::executor::{ExecError, ExecOutcome, ExecStatus, JobState, JobTable, SignalHandler};
+use crate::history::HistoryManager;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 pub struct HelpCommand;
 
@@ -18,6 +18,14 @@ impl BuiltinCommand for HelpCommand {
         println!("  help       : Show this help");
         println!("  export [VAR=VALUE] : Set or export environment variables");
         println!("  history    : Show command history (last N commands)");
+        println!(
+            "  set [-o|+o NAME] [-- ARG...] : Enable, disable or list shell options, set $1, $2, ..."
+        );
+        println!("  trap [-p] [COMMAND] [SIGNAL...] : Run COMMAND on a signal, EXIT or ERR");
+        println!("  jobs [-p]  : List background and stopped jobs");
+        println!("  fg [%N]    : Continue a job in the foreground");
+        println!("  bg [%N]    : Continue a stopped job in the background");
+        println!("  disown [%N|-a] : Remove jobs from the job table");
         Ok(ExecOutcome::Code(0))
     }
 }
@@ -28,10 +36,22 @@ impl BuiltinCommand for CdCommand {
     fn name(&self) -> &'static str {
         "cd"
     }
-    fn run(&self, args: &[String], _env: &mut Environment) -> ExecStatus {
-        let target = args.get(0).map(|s| s.as_str()).unwrap_or("/");
+    fn run(&self, args: &[String], env: &mut Environment) -> ExecStatus {
+        let target = args.first().map(|s| s.as_str()).unwrap_or("/");
+        let previous = std::env::current_dir();
         match std::env::set_current_dir(target) {
-            Ok(_) => Ok(ExecOutcome::Code(0)),
+            Ok(_) => {
+                // For `~-` and `~+`
+                if let Ok(previous) = previous {
+                    env.set("OLDPWD", &previous.to_string_lossy());
+                    env.export("OLDPWD");
+                }
+                if let Ok(current) = std::env::current_dir() {
+                    env.set("PWD", &current.to_string_lossy());
+                    env.export("PWD");
+                }
+                Ok(ExecOutcome::Code(0))
+            }
             Err(e) => {
                 eprintln!("cd: {}: {}", target, e);
                 Ok(ExecOutcome::Code(1))
@@ -47,7 +67,8 @@ impl BuiltinCommand for ExitCommand {
         "exit"
     }
     fn run(&self, args: &[String], _env: &mut Environment) -> ExecStatus {
-        let code = args.get(0)
+        let code = args
+            .first()
             .and_then(|s| s.parse::<i32>().ok())
             .unwrap_or(0);
         Ok(ExecOutcome::Exit(code))
@@ -80,6 +101,136 @@ impl BuiltinCommand for ExportCommand {
     }
 }
 
+pub struct SetCommand;
+
+impl BuiltinCommand for SetCommand {
+    fn name(&self) -> &'static str {
+        "set"
+    }
+    fn run(&self, args: &[String], env: &mut Environment) -> ExecStatus {
+        if args.is_empty() || args == ["-o"] {
+            for name in Environment::OPTION_NAMES {
+                let state = if env.option(name) { "on" } else { "off" };
+                println!("{:<15} {}", name, state);
+            }
+            return Ok(ExecOutcome::Code(0));
+        }
+
+        let mut idx = 0;
+        while idx < args.len() {
+            let arg = args[idx].as_str();
+            let short = arg.strip_prefix(['-', '+']).and_then(|rest| {
+                Environment::OPTION_FLAGS
+                    .iter()
+                    .find(|(flag, _)| rest.chars().eq([*flag]))
+            });
+            let enable = match arg {
+                "-o" => true,
+                "+o" => false,
+                // `set -- ARG...` (or the first word that is not an option) replaces $1, $2, ...
+                "--" => {
+                    env.set_positional(args[idx + 1..].to_vec());
+                    break;
+                }
+                _ if !arg.starts_with(['-', '+']) => {
+                    env.set_positional(args[idx..].to_vec());
+                    break;
+                }
+                // Short forms such as `-C` for noclobber
+                _ if let Some((_, name)) = short => {
+                    env.set_option(name, arg.starts_with('-'));
+                    idx += 1;
+                    continue;
+                }
+                other => {
+                    eprintln!("set: {}: invalid option", other);
+                    return Ok(ExecOutcome::Code(2));
+                }
+            };
+            let Some(name) = args.get(idx + 1) else {
+                eprintln!("set: {}: option name required", args[idx]);
+                return Ok(ExecOutcome::Code(2));
+            };
+            if !Environment::OPTION_NAMES.contains(&name.as_str()) {
+                eprintln!("set: {}: invalid option name", name);
+                return Ok(ExecOutcome::Code(2));
+            }
+            env.set_option(name, enable);
+            idx += 2;
+        }
+        Ok(ExecOutcome::Code(0))
+    }
+}
+
+pub struct TrapCommand;
+
+impl BuiltinCommand for TrapCommand {
+    fn name(&self) -> &'static str {
+        "trap"
+    }
+    fn run(&self, args: &[String], env: &mut Environment) -> ExecStatus {
+        let args = match args.first().map(|s| s.as_str()) {
+            Some("--") => &args[1..],
+            _ => args,
+        };
+
+        // `trap` / `trap -p [NAME...]`: print the traps in a reusable form
+        if args.is_empty() || args[0] == "-p" {
+            let names: Vec<Option<String>> = args.iter().skip(1).map(|n| trap_name(n)).collect();
+            for (name, command) in env.traps() {
+                if names.is_empty() || names.contains(&Some(name.clone())) {
+                    println!("trap -- '{}' {}", command.replace('\'', "'\\''"), name);
+                }
+            }
+            return Ok(ExecOutcome::Code(0));
+        }
+
+        // `trap NAME` and `trap - NAME...` reset, `trap '' NAME...` ignores
+        let (command, names) = if args.len() == 1 {
+            (None, args)
+        } else if args[0] == "-" {
+            (None, &args[1..])
+        } else {
+            (Some(args[0].as_str()), &args[1..])
+        };
+
+        let mut status = 0;
+        for spec in names {
+            let Some(name) = trap_name(spec) else {
+                eprintln!("trap: {}: invalid signal specification", spec);
+                status = 1;
+                continue;
+            };
+            let sig = SignalHandler::signal_number(&name);
+            if command.is_some() && sig.is_some_and(|sig| !SignalHandler::can_trap(sig)) {
+                eprintln!("trap: {}: cannot be trapped", spec);
+                status = 1;
+                continue;
+            }
+            env.set_trap(&name, command);
+            if let Some(sig) = sig {
+                match command {
+                    None => SignalHandler::restore(sig),
+                    Some("") => SignalHandler::ignore(sig),
+                    Some(_) => SignalHandler::catch(sig),
+                }
+            }
+        }
+        Ok(ExecOutcome::Code(status))
+    }
+}
+
+// Canonical trap name: `EXIT`, `ERR` or a signal name without the SIG prefix
+fn trap_name(spec: &str) -> Option<String> {
+    match spec.to_ascii_uppercase().as_str() {
+        "0" | "EXIT" => Some("EXIT".to_string()),
+        "ERR" => Some("ERR".to_string()),
+        _ => SignalHandler::signal_number(spec)
+            .and_then(SignalHandler::signal_name)
+            .map(|name| name.to_string()),
+    }
+}
+
 pub struct HistoryCommand {
     pub history: Rc<RefCell<HistoryManager>>,
 }
@@ -105,7 +256,10 @@ impl BuiltinCommand for HistoryCommand {
                     idx += 1;
                 }
                 _ => {
-                    return Err(ExecError::Custom(format!("history: unknown option '{}'", args[idx])));
+                    return Err(ExecError::Custom(format!(
+                        "history: unknown option '{}'",
+                        args[idx]
+                    )));
                 }
             }
         }
@@ -120,14 +274,9 @@ impl BuiltinCommand for HistoryCommand {
 
         let entries = history.list();
         let total = entries.len();
-        let start = if let Some(limit) = n {
-            if limit > total {
-                0
-            } else {
-                total - limit
-            }
-        } else {
-            0
+        let start = match n {
+            Some(limit) => total.saturating_sub(limit),
+            None => 0,
         };
 
         for (i, cmd) in entries.iter().enumerate().skip(start) {
@@ -137,3 +286,143 @@ impl BuiltinCommand for HistoryCommand {
     }
 }
 
+pub struct JobsCommand {
+    pub jobs: Rc<RefCell<JobTable>>,
+}
+
+impl BuiltinCommand for JobsCommand {
+    fn name(&self) -> &'static str {
+        "jobs"
+    }
+    fn run(&self, args: &[String], _env: &mut Environment) -> ExecStatus {
+        let pids_only = match args.first().map(|s| s.as_str()) {
+            None => false,
+            Some("-p") => true,
+            Some(other) => {
+                eprintln!("jobs: {}: invalid option", other);
+                return Ok(ExecOutcome::Code(2));
+            }
+        };
+
+        let mut table = self.jobs.borrow_mut();
+        for (job, marker) in table.reap() {
+            if !pids_only {
+                println!("{}", job.describe(marker));
+            }
+        }
+        for job in table.jobs() {
+            if pids_only {
+                println!("{}", job.pgid);
+            } else {
+                println!("{}", job.describe(table.marker(job.id)));
+            }
+        }
+        Ok(ExecOutcome::Code(0))
+    }
+}
+
+pub struct FgCommand {
+    pub jobs: Rc<RefCell<JobTable>>,
+}
+
+impl BuiltinCommand for FgCommand {
+    fn name(&self) -> &'static str {
+        "fg"
+    }
+    fn run(&self, args: &[String], _env: &mut Environment) -> ExecStatus {
+        let mut table = self.jobs.borrow_mut();
+        let Some(id) = table.resolve(args.first().map(|s| s.as_str())) else {
+            eprintln!(
+                "fg: {}: no such job",
+                args.first().map(|s| s.as_str()).unwrap_or("current")
+            );
+            return Ok(ExecOutcome::Code(1));
+        };
+        Ok(ExecOutcome::Code(table.resume_foreground(id)?))
+    }
+}
+
+pub struct BgCommand {
+    pub jobs: Rc<RefCell<JobTable>>,
+}
+
+impl BuiltinCommand for BgCommand {
+    fn name(&self) -> &'static str {
+        "bg"
+    }
+    fn run(&self, args: &[String], _env: &mut Environment) -> ExecStatus {
+        let mut table = self.jobs.borrow_mut();
+        let Some(id) = table.resolve(args.first().map(|s| s.as_str())) else {
+            eprintln!(
+                "bg: {}: no such job",
+                args.first().map(|s| s.as_str()).unwrap_or("current")
+            );
+            return Ok(ExecOutcome::Code(1));
+        };
+        if table
+            .jobs()
+            .iter()
+            .any(|job| job.id == id && job.state == JobState::Running)
+        {
+            eprintln!("bg: job {} already in background", id);
+            return Ok(ExecOutcome::Code(0));
+        }
+        table.resume_background(id)?;
+        Ok(ExecOutcome::Code(0))
+    }
+}
+
+pub struct DisownCommand {
+    pub jobs: Rc<RefCell<JobTable>>,
+}
+
+impl BuiltinCommand for DisownCommand {
+    fn name(&self) -> &'static str {
+        "disown"
+    }
+    fn run(&self, args: &[String], _env: &mut Environment) -> ExecStatus {
+        let mut table = self.jobs.borrow_mut();
+        if args.first().map(|s| s.as_str()) == Some("-a") {
+            let ids: Vec<usize> = table.jobs().iter().map(|job| job.id).collect();
+            for id in ids {
+                table.remove(id);
+            }
+            return Ok(ExecOutcome::Code(0));
+        }
+
+        let Some(id) = table.resolve(args.first().map(|s| s.as_str())) else {
+            eprintln!(
+                "disown: {}: no such job",
+                args.first().map(|s| s.as_str()).unwrap_or("current")
+            );
+            return Ok(ExecOutcome::Code(1));
+        };
+        table.remove(id);
+        Ok(ExecOutcome::Code(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_short_options_and_positional() {
+        let mut env = Environment::new();
+        let set = |args: &[&str], env: &mut Environment| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            SetCommand.run(&args, env)
+        };
+        assert!(matches!(set(&["-C"], &mut env), Ok(ExecOutcome::Code(0))));
+        assert!(env.option("noclobber"));
+        assert!(matches!(set(&["+C"], &mut env), Ok(ExecOutcome::Code(0))));
+        assert!(!env.option("noclobber"));
+        // Multibyte arguments are words or invalid options, never sliced mid-character
+        assert!(matches!(set(&["-é"], &mut env), Ok(ExecOutcome::Code(2))));
+        assert!(matches!(
+            set(&["é", "x"], &mut env),
+            Ok(ExecOutcome::Code(0))
+        ));
+        assert_eq!(env.positional(), ["é", "x"]);
+    }
+}
@@ -1,12 +1,9 @@
-use std::collections::HashMap;
-use crate::executor::{ ExecStatus, ExecError };
 use crate::environment::Environment;
 use crate::executor::builtin::commands::{
-    HelpCommand,
-    CdCommand,
-    ExitCommand,
-    ExportCommand,
+    CdCommand, ExitCommand, ExportCommand, HelpCommand, SetCommand, TrapCommand,
 };
+use crate::executor::{ExecError, ExecStatus};
+use std::collections::HashMap;
 
 pub trait BuiltinCommand {
     fn name(&self) -> &'static str;
@@ -17,6 +14,12 @@ pub struct BuiltinManager {
     commands: HashMap<String, Box<dyn BuiltinCommand>>,
 }
 
+impl Default for BuiltinManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl BuiltinManager {
     pub fn new() -> Self {
         let mut mgr = BuiltinManager {
@@ -26,6 +29,8 @@ impl BuiltinManager {
         mgr.register(Box::new(CdCommand {}));
         mgr.register(Box::new(ExitCommand {}));
         mgr.register(Box::new(ExportCommand {}));
+        mgr.register(Box::new(SetCommand {}));
+        mgr.register(Box::new(TrapCommand {}));
         mgr
     }
 
@@ -37,12 +42,7 @@ impl BuiltinManager {
         self.commands.contains_key(name)
     }
 
-    pub fn execute(
-        &self,
-        name: &str,
-        args: &[String],
-        env: &mut Environment,
-    ) -> ExecStatus {
+    pub fn execute(&self, name: &str, args: &[String], env: &mut Environment) -> ExecStatus {
         if let Some(cmd) = self.commands.get(name) {
             cmd.run(args, env)
         } else {
@@ -50,4 +50,3 @@ impl BuiltinManager {
         }
     }
 }
-
@@ -1,6 +1,5 @@
-mod manager;
 mod commands;
+mod manager;
 
-pub use manager::BuiltinManager;
-pub use commands::HistoryCommand;
-
+pub use commands::{BgCommand, DisownCommand, FgCommand, HistoryCommand, JobsCommand};
+pub use manager::{BuiltinCommand, BuiltinManager};
@@ -0,0 +1,83 @@
+use crate::ast::CommandNode;
+use crate::environment::Environment;
+use crate::executor::job::JobTable;
+use crate::executor::signal::SignalHandler;
+use crate::executor::{ExecError, ExecOutcome, ExecStatus};
+use std::cell::RefCell;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+pub struct ExternalCommand;
+
+impl ExternalCommand {
+    // Launch an external program with the exported variables and wait for it
+    // as a foreground job
+    pub fn run(
+        path: &Path,
+        cmd: &CommandNode,
+        env: &Environment,
+        jobs: &RefCell<JobTable>,
+    ) -> ExecStatus {
+        let mut command = Command::new(path);
+        command.args(&cmd.args);
+        for (k, v) in &env.exported_vars() {
+            command.env(k, v);
+        }
+        unsafe {
+            command.pre_exec(|| {
+                SignalHandler::setup_child(0, true);
+                Ok(())
+            });
+        }
+
+        let child = command.spawn().map_err(ExecError::Io)?;
+        let pid = child.id() as libc::pid_t;
+        SignalHandler::assign_group(pid, pid);
+        let statuses = jobs
+            .borrow_mut()
+            .wait_foreground(&[pid], &cmd.to_string())?;
+        Ok(ExecOutcome::Code(statuses[0]))
+    }
+}
+
+// Shell exit code of a finished process: a signal N is reported as 128+N
+pub fn exit_code(status: ExitStatus) -> i32 {
+    match status.code() {
+        Some(code) => code,
+        None => 128 + status.signal().unwrap_or(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::CommandKind;
+
+    fn sh(script: &str) -> ExecStatus {
+        let cmd = CommandNode {
+            name: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            kind: CommandKind::External,
+            spans: Vec::new(),
+        };
+        ExternalCommand::run(
+            Path::new("/bin/sh"),
+            &cmd,
+            &Environment::new(),
+            &RefCell::new(JobTable::new()),
+        )
+    }
+
+    #[test]
+    fn test_exit_code_from_status() {
+        let status = sh("exit 7");
+        assert_eq!(status.unwrap(), ExecOutcome::Code(7));
+    }
+
+    #[test]
+    fn test_exit_code_from_signal() {
+        let status = sh("kill -TERM $$");
+        assert_eq!(status.unwrap(), ExecOutcome::Code(128 + libc::SIGTERM));
+    }
+}
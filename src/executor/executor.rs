@@ -1,6 +1,7 @@
-use std::{io, fmt};
-use crate::ast::{AstNode};
+use crate::ast::AstNode;
 use crate::environment::Environment;
+use crate::expander::ExpandError;
+use std::{fmt, io};
 
 pub type ExecStatus = Result<ExecOutcome, ExecError>;
 
@@ -21,6 +22,8 @@ pub enum ExecError {
     SubshellError(String),
     NoSuchBuiltin(String),
     NotImplemented(String),
+    Expand(ExpandError),
+    Interrupted, // Ctrl+C: abandon the rest of the command line
     Custom(String),
 }
 impl fmt::Display for ExecError {
@@ -35,19 +38,39 @@ impl fmt::Display for ExecError {
             ExecError::SubshellError(msg) => write!(f, "Subshell error: {}", msg),
             ExecError::NoSuchBuiltin(name) => write!(f, "No such builtin command: {}", name),
             ExecError::NotImplemented(feature) => write!(f, "Feature not implemented: {}", feature),
+            ExecError::Expand(e) => write!(f, "Expansion error: {}", e),
+            ExecError::Interrupted => write!(f, "Interrupted"),
             ExecError::Custom(msg) => write!(f, "Execution error: {}", msg),
         }
     }
 }
 
+impl From<ExpandError> for ExecError {
+    fn from(e: ExpandError) -> Self {
+        match e {
+            ExpandError::Interrupted => ExecError::Interrupted,
+            e => ExecError::Expand(e),
+        }
+    }
+}
+
+// The working directory, which the expander needs for wildcards
+pub(super) fn current_dir() -> Result<std::path::PathBuf, ExecError> {
+    std::env::current_dir().map_err(ExecError::Io)
+}
+
 pub trait Executor {
     fn exec(&mut self, node: &AstNode, env: &mut Environment) -> ExecStatus;
+
+    // A new executor of the same kind with the same builtins and job table, for the
+    // commands of `$(...)` and `<(...)`, which run in a forked child
+    fn child(&self) -> Box<dyn Executor + '_>;
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ast::{AstNode, CommandNode, CommandKind, RedirectKind};
+    use crate::ast::{AstNode, CommandKind, CommandNode, RedirectKind};
     use crate::environment::Environment;
 
     struct TestExecutor {
@@ -59,8 +82,12 @@ mod tests {
         fn exec(&mut self, node: &AstNode, env: &mut Environment) -> ExecStatus {
             match node {
                 AstNode::Command(cmd) => {
-                    self.log.push(format!("command: {} {:?}", cmd.name, cmd.args));
-                    Ok(ExecOutcome::Code(0))
+                    self.log
+                        .push(format!("command: {} {:?}", cmd.name, cmd.args));
+                    match cmd.name.as_str() {
+                        "false" => Ok(ExecOutcome::Code(1)),
+                        _ => Ok(ExecOutcome::Code(0)),
+                    }
                 }
                 AstNode::Pipeline(nodes) => {
                     self.log.push("pipeline".to_string());
@@ -69,7 +96,9 @@ mod tests {
                     }
                     Ok(ExecOutcome::Code(0))
                 }
-                AstNode::Redirect { node, kind, file } => {
+                AstNode::Redirect {
+                    node, kind, file, ..
+                } => {
                     self.log.push(format!("redirect: {:?} {}", kind, file));
                     self.exec(node, env)
                 }
@@ -77,6 +106,11 @@ mod tests {
                     self.log.push("subshell".to_string());
                     self.exec(sub, &mut env.clone()) // サブシェルはcloneで
                 }
+                AstNode::Background(inner) => {
+                    self.log.push("background".to_string());
+                    self.exec(inner, &mut env.clone())?;
+                    Ok(ExecOutcome::Code(0))
+                }
                 AstNode::Sequence(seq) => {
                     self.log.push("sequence".to_string());
                     for node in seq {
@@ -106,6 +140,10 @@ mod tests {
                 }
             }
         }
+
+        fn child(&self) -> Box<dyn Executor + '_> {
+            Box::new(TestExecutor { log: Vec::new() })
+        }
     }
 
     impl TestExecutor {
@@ -119,6 +157,7 @@ mod tests {
             name: name.to_string(),
             args: args.iter().map(|s| s.to_string()).collect(),
             kind: CommandKind::Simple,
+            spans: Vec::new(),
             // assignments: vec![],
         })
     }
@@ -135,29 +174,30 @@ mod tests {
 
     #[test]
     fn test_pipeline() {
-        let ast = AstNode::Pipeline(vec![
-            dummy_cmd("ls", &[]),
-            dummy_cmd("wc", &[]),
-        ]);
+        let ast = AstNode::Pipeline(vec![dummy_cmd("ls", &[]), dummy_cmd("wc", &[])]);
         let mut env = Environment::new();
         let mut exec = TestExecutor::new();
         let result = exec.exec(&ast, &mut env);
         assert!(matches!(result, Ok(ExecOutcome::Code(0))));
-        assert_eq!(exec.log, vec!["pipeline", "command: ls []", "command: wc []"]);
+        assert_eq!(
+            exec.log,
+            vec!["pipeline", "command: ls []", "command: wc []"]
+        );
     }
 
     #[test]
     fn test_redirect() {
         let ast = AstNode::Redirect {
             node: Box::new(dummy_cmd("ls", &[])),
-            kind: RedirectKind::Out,
+            kind: RedirectKind::Out(1),
             file: "out.txt".to_string(),
+            span: None,
         };
         let mut env = Environment::new();
         let mut exec = TestExecutor::new();
         let result = exec.exec(&ast, &mut env);
         assert!(matches!(result, Ok(ExecOutcome::Code(0))));
-        assert_eq!(exec.log, vec!["redirect: Out out.txt", "command: ls []"]);
+        assert_eq!(exec.log, vec!["redirect: Out(1) out.txt", "command: ls []"]);
     }
 
     #[test]
@@ -178,8 +218,9 @@ mod tests {
                 dummy_cmd("ls", &[]),
                 dummy_cmd("grep", &["foo"]),
             ])))),
-            kind: RedirectKind::Out,
+            kind: RedirectKind::Out(1),
             file: "out.txt".to_string(),
+            span: None,
         };
         let mut env = Environment::new();
         let mut exec = TestExecutor::new();
@@ -188,7 +229,7 @@ mod tests {
         assert_eq!(
             exec.log,
             vec![
-                "redirect: Out out.txt",
+                "redirect: Out(1) out.txt",
                 "subshell",
                 "pipeline",
                 "command: ls []",
@@ -217,7 +258,9 @@ mod tests {
         assert_eq!(
             exec.log,
             vec![
-                "sequence", "or", "and",
+                "sequence",
+                "or",
+                "and",
                 "command: echo [\"hi\"]",
                 "command: false []",
                 "command: echo [\"fallback\"]",
@@ -226,4 +269,3 @@ mod tests {
         );
     }
 }
-
@@ -0,0 +1,328 @@
+use crate::ast::RedirectKind;
+use crate::environment::Environment;
+use crate::executor::executor::current_dir;
+use crate::executor::{ExecError, Executor, ProcessSubstitutions};
+use crate::expander::{ExpandError, Expander};
+use std::cell::{Cell, RefCell};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, Write};
+use std::os::unix::io::{AsRawFd, IntoRawFd};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Saved copies are close-on-exec and above the fds scripts usually use
+const SAVED_FD_BASE: i32 = 10;
+
+thread_local! {
+    // The saved copies of every live table, so that a redirect onto one of their numbers
+    // (e.g. `cmd 10>file`) can move the copy out of the way first
+    static SAVED_COPIES: RefCell<Vec<Rc<Cell<i32>>>> = const { RefCell::new(Vec::new()) };
+}
+
+// What the redirected fds are connected to
+enum Source {
+    File(File),
+    Fd(i32),
+    Closed,
+}
+
+// Redirections applied to the shell's own file descriptors, in order. Each replaced fd is
+// saved first and put back by `restore` (or when the table is dropped), last one first.
+// Builtins run in-process see the redirected fds directly; forked children inherit them.
+#[derive(Default)]
+pub struct FdTable {
+    saved: Vec<(i32, Option<Rc<Cell<i32>>>)>, // Replaced fd and its copy (None: it was closed)
+    processes: ProcessSubstitutions,          // Of targets such as `< <(cmd)`, reaped by `restore`
+}
+
+impl FdTable {
+    pub fn new() -> Self {
+        FdTable::default()
+    }
+
+    // Expand the redirect's word and apply it on top of the redirects already in the table.
+    // When it fails, the fds it touched are restored and the earlier redirects stay. `span`
+    // locates expansion errors in the source.
+    pub fn apply(
+        &mut self,
+        kind: &RedirectKind,
+        word: &str,
+        span: Option<(usize, usize)>,
+        env: &Environment,
+        executor: &dyn Executor,
+    ) -> Result<(), ExecError> {
+        let expander = Expander::new(env, current_dir()?, executor);
+        let target = Self::expand_with(&expander, kind, word).map_err(|e| e.in_word(span));
+        self.processes.append(&mut expander.take_processes());
+        let target = target?;
+        let _ = io::stdout().flush();
+        let mark = self.saved.len();
+        let fds = kind.fds();
+        for &fd in &fds {
+            self.save(fd);
+        }
+        let result =
+            Self::open_source(kind, &target, env).and_then(|source| Self::connect(&fds, source));
+        if result.is_err() {
+            self.restore_to(mark);
+        }
+        result
+    }
+
+    // Undo every redirect of the table
+    pub fn restore(&mut self) {
+        self.restore_to(0);
+        self.processes = ProcessSubstitutions::default();
+    }
+
+    fn restore_to(&mut self, len: usize) {
+        let _ = io::stdout().flush();
+        while self.saved.len() > len
+            && let Some((fd, copy)) = self.saved.pop()
+        {
+            match copy {
+                Some(copy) => {
+                    SAVED_COPIES
+                        .with(|copies| copies.borrow_mut().retain(|c| !Rc::ptr_eq(c, &copy)));
+                    unsafe {
+                        libc::dup2(copy.get(), fd);
+                        libc::close(copy.get());
+                    }
+                }
+                // The fd was not open before
+                None => unsafe {
+                    libc::close(fd);
+                },
+            }
+        }
+    }
+
+    fn save(&mut self, fd: i32) {
+        // To scripts, the fd of a saved copy is not open
+        if SAVED_COPIES.with(|copies| copies.borrow().iter().any(|c| c.get() == fd)) {
+            self.saved.push((fd, None));
+            return;
+        }
+        let copy = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, SAVED_FD_BASE) };
+        let copy = (copy >= 0).then(|| {
+            let copy = Rc::new(Cell::new(copy));
+            SAVED_COPIES.with(|copies| copies.borrow_mut().push(Rc::clone(&copy)));
+            copy
+        });
+        self.saved.push((fd, copy));
+    }
+
+    // If `fd` holds a saved copy, move the copy elsewhere before `fd` is replaced
+    fn release(fd: i32) -> Result<(), ExecError> {
+        SAVED_COPIES.with(|copies| {
+            for copy in copies.borrow().iter().filter(|c| c.get() == fd) {
+                let moved = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, SAVED_FD_BASE) };
+                if moved < 0 {
+                    return Err(ExecError::Io(io::Error::last_os_error()));
+                }
+                copy.set(moved);
+            }
+            Ok(())
+        })
+    }
+
+    // What a redirect's word stands for: a file name (or fd), or the content of a here-document
+    pub fn expand_target(
+        kind: &RedirectKind,
+        word: &str,
+        env: &Environment,
+        executor: &dyn Executor,
+    ) -> Result<String, ExecError> {
+        let expander = Expander::new(env, current_dir()?, executor);
+        Ok(Self::expand_with(&expander, kind, word)?)
+    }
+
+    fn expand_with(
+        expander: &Expander,
+        kind: &RedirectKind,
+        word: &str,
+    ) -> Result<String, ExpandError> {
+        Ok(match kind {
+            RedirectKind::Heredoc { .. } => {
+                expander.expand_heredoc(word, kind.is_quoted_heredoc())?
+            }
+            RedirectKind::HereString(_) => expander.expand_arg(word)?.join(" ") + "\n",
+            _ => expander.expand_single_arg(word)?,
+        })
+    }
+
+    // Here-document content is read from an unlinked temporary file, so that it does not
+    // have to fit into a pipe buffer
+    pub fn here_document(content: &str) -> io::Result<File> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "tiny-shell-heredoc-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let _ = std::fs::remove_file(&path);
+        file.write_all(content.as_bytes())?;
+        file.rewind()?;
+        Ok(file)
+    }
+
+    fn open_source(
+        kind: &RedirectKind,
+        target: &str,
+        env: &Environment,
+    ) -> Result<Source, ExecError> {
+        use RedirectKind::*;
+        let opened = match kind {
+            Heredoc { .. } | HereString(_) => {
+                return Self::here_document(target)
+                    .map(Source::File)
+                    .map_err(|e| ExecError::RedirectError(format!("here-document: {}", e)));
+            }
+            In(_) => File::open(target),
+            Out(_) | OutErr if env.option("noclobber") && Path::new(target).is_file() => {
+                return Err(ExecError::RedirectError(format!(
+                    "{}: cannot overwrite existing file",
+                    target
+                )));
+            }
+            Out(_) | Clobber(_) | OutErr => File::create(target),
+            Append(_) => OpenOptions::new().append(true).create(true).open(target),
+            ReadWrite(_) => OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(target),
+            DupIn(_) | DupOut(_) => {
+                if target == "-" {
+                    return Ok(Source::Closed);
+                }
+                return match target.parse::<i32>() {
+                    Ok(fd) if unsafe { libc::fcntl(fd, libc::F_GETFD) } >= 0 => Ok(Source::Fd(fd)),
+                    _ => Err(ExecError::RedirectError(format!(
+                        "{}: bad file descriptor",
+                        target
+                    ))),
+                };
+            }
+        };
+        opened
+            .map(Source::File)
+            .map_err(|e| ExecError::RedirectError(format!("{}: {}", target, e)))
+    }
+
+    fn connect(fds: &[i32], source: Source) -> Result<(), ExecError> {
+        let src = match &source {
+            Source::File(f) => Some(f.as_raw_fd()),
+            Source::Fd(fd) => Some(*fd),
+            Source::Closed => None,
+        };
+        // Keeps the opened file alive until its fd has been duplicated
+        let _file = match source {
+            Source::File(f) if fds.contains(&f.as_raw_fd()) => {
+                // Opened on the very fd (it was closed before): keep it open and inheritable
+                unsafe { libc::fcntl(f.as_raw_fd(), libc::F_SETFD, 0) };
+                let _ = f.into_raw_fd();
+                None
+            }
+            Source::File(f) => Some(f),
+            _ => None,
+        };
+        for &fd in fds {
+            if src == Some(fd) {
+                continue;
+            }
+            Self::release(fd)?;
+            let ret = match src {
+                Some(src) => unsafe { libc::dup2(src, fd) },
+                None => unsafe { libc::close(fd) }.max(0), // Closing an unopened fd is fine
+            };
+            if ret < 0 {
+                return Err(ExecError::Io(io::Error::last_os_error()));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for FdTable {
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::tests::MockExecutor;
+    use std::io::Read;
+
+    // Whether `fd` is open in this process
+    fn is_open(fd: i32) -> bool {
+        unsafe { libc::fcntl(fd, libc::F_GETFD) >= 0 }
+    }
+
+    #[test]
+    fn test_here_document_content() {
+        let mut env = Environment::new();
+        env.set("x", "value");
+        let executor = MockExecutor::new();
+        let heredoc = |delimiter: &str| RedirectKind::Heredoc {
+            fd: 0,
+            delimiter: delimiter.to_string(),
+            strip_tabs: false,
+        };
+        let expanded = FdTable::expand_target(&heredoc("EOF"), "a $x\n", &env, &executor).unwrap();
+        assert_eq!(expanded, "a value\n");
+        let raw = FdTable::expand_target(&heredoc("'EOF'"), "a $x\n", &env, &executor).unwrap();
+        assert_eq!(raw, "a $x\n");
+        let word =
+            FdTable::expand_target(&RedirectKind::HereString(0), "$x", &env, &executor).unwrap();
+        assert_eq!(word, "value\n");
+
+        let mut content = String::new();
+        FdTable::here_document(&expanded)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "a value\n");
+    }
+
+    #[test]
+    fn test_apply_and_restore_high_fds() {
+        let file_name =
+            std::env::temp_dir().join(format!("tiny-shell-fd-table-{}", std::process::id()));
+        let file_name = file_name.to_str().unwrap();
+        let env = Environment::new();
+        let executor = MockExecutor::new();
+        assert!(!is_open(57));
+
+        let mut table = FdTable::new();
+        table
+            .apply(&RedirectKind::Out(57), file_name, None, &env, &executor)
+            .unwrap();
+        table
+            .apply(&RedirectKind::DupOut(58), "57", None, &env, &executor)
+            .unwrap();
+        assert!(is_open(57) && is_open(58));
+        unsafe { libc::write(58, b"via 58".as_ptr().cast(), 6) };
+
+        // A failing redirect leaves the earlier ones in place
+        assert!(matches!(
+            table.apply(&RedirectKind::DupOut(59), "12345", None, &env, &executor),
+            Err(ExecError::RedirectError(_))
+        ));
+        assert!(is_open(58) && !is_open(59));
+
+        drop(table);
+        assert!(!is_open(57) && !is_open(58));
+        assert_eq!(std::fs::read_to_string(file_name).unwrap(), "via 58");
+        let _ = std::fs::remove_file(file_name);
+    }
+}
@@ -1,84 +1,278 @@
-use std::fs::File;
-use std::process::{Command};
-use std::os::unix::io::AsRawFd;
-use crate::executor::{ Executor, ExecStatus, ExecOutcome, ExecError };
+use crate::ast::{AstNode, CommandNode, CompoundNode, RedirectKind};
+use crate::environment::Environment;
 use crate::executor::builtin::BuiltinManager;
+use crate::executor::command::ExternalCommand;
+use crate::executor::executor::current_dir;
+use crate::executor::job::JobTable;
 use crate::executor::path_resolver::PathResolver;
 use crate::executor::pipeline::PipelineHandler;
-use crate::ast::{AstNode, CommandNode, RedirectKind};
-use crate::environment::Environment;
+use crate::executor::signal::SignalHandler;
+use crate::executor::subshell::SubshellHandler;
+use crate::executor::trap::TrapHandler;
+use crate::executor::{ExecError, ExecOutcome, ExecStatus, Executor, FdTable};
+use crate::expander::Expander;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 #[derive(Debug, Clone, PartialEq)]
 enum ExecStep {
     RunCommand(CommandNode),
+    RunSubshell(AstNode), // Runs in a forked child (or as a pipeline stage)
+    RunBackground(AstNode),
     BeginRedirect {
         kind: RedirectKind,
         file: String,
+        span: Option<(usize, usize)>,
     },
-    EndRedirect {
-        kind: RedirectKind,
-    },
+    EndRedirect,
     BeginPipeline,
     EndPipeline,
+    // Control flow: targets are indexes into the plan
+    Jump(usize),
+    JumpIfSuccess(usize),
+    JumpIfFailure(usize),
+    SetStatus(i32),
+    BeginLoop {
+        items: Option<Vec<String>>, // `for` words, expanded when the loop starts
+        spans: Vec<(usize, usize)>,
+    },
+    NextItem {
+        var: String,
+        end: usize,
+    },
+    ContinueLoop(usize),
+    EndLoop,
+    Arithmetic(String), // `((expr))`
+}
+
+// Runtime state of a loop being executed
+struct LoopFrame {
+    items: std::vec::IntoIter<String>,
+    status: i32, // Status of the last body iteration
 }
 
 pub struct FlattenExecutor<'a> {
     builtin_manager: &'a BuiltinManager,
-    stdin_stack: Vec<i32>,
-    stdout_stack: Vec<i32>,
-    // stderr_stack: Vec<i32>,
-    in_pipeline: bool, // Whether or not in the pipeline
+    jobs: Rc<RefCell<JobTable>>,
+    redirect_stack: Vec<FdTable>, // One table per active redirect, innermost last
+    in_pipeline: bool,            // Whether or not in the pipeline
+    loop_stack: Vec<LoopFrame>,
+    last_status: i32,
 }
 
 impl<'a> Executor for FlattenExecutor<'a> {
     fn exec(&mut self, node: &AstNode, env: &mut Environment) -> ExecStatus {
         let mut plan = Vec::new();
         self.flatten_ast(node, &mut plan);
+
+        let depth = self.redirect_stack.len();
+        let loops = self.loop_stack.len();
+        let result = self.run_plan(&plan, env);
+        if !matches!(result, Ok(ExecOutcome::Code(_))) {
+            // Leaving the plan early (an error, an interrupt or `exit`) skips its
+            // EndRedirect and EndLoop steps
+            self.unwind_redirects(depth);
+            self.loop_stack.truncate(loops);
+        }
+        result
+    }
+
+    fn child(&self) -> Box<dyn Executor + '_> {
+        Box::new(FlattenExecutor::new(
+            self.builtin_manager,
+            Rc::clone(&self.jobs),
+        ))
+    }
+}
+
+impl<'a> FlattenExecutor<'a> {
+    pub fn new(builtin_manager: &'a BuiltinManager, jobs: Rc<RefCell<JobTable>>) -> Self {
+        FlattenExecutor {
+            builtin_manager,
+            jobs,
+            redirect_stack: Vec::new(),
+            in_pipeline: false,
+            loop_stack: Vec::new(),
+            last_status: 0,
+        }
+    }
+
+    fn set_status(&mut self, code: i32, env: &mut Environment) {
+        self.last_status = code;
+        env.set_last_status(code);
+    }
+
+    // Traps run as nested plans; keep the status this plan is working with
+    fn run_traps(&mut self, failed: bool, env: &mut Environment) -> Result<Option<i32>, ExecError> {
+        let status = self.last_status;
+        let result = if failed {
+            TrapHandler::run_err(self, env)
+        } else {
+            TrapHandler::run_pending(self, env)
+        };
+        self.last_status = status;
+        result
+    }
+
+    fn run_plan(&mut self, plan: &[ExecStep], env: &mut Environment) -> ExecStatus {
         let mut pipeline_cmds = Vec::new();
+        let mut pc = 0;
+
+        while pc < plan.len() {
+            if SignalHandler::interrupted() {
+                return Err(ExecError::Interrupted);
+            }
+            if !self.in_pipeline
+                && let Some(code) = self.run_traps(false, env)?
+            {
+                return Ok(ExecOutcome::Exit(code));
+            }
+            let step = &plan[pc];
+            pc += 1;
+
+            // ERR fires for failed commands whose status is not tested by the next step
+            let tested = matches!(
+                plan.get(pc),
+                Some(ExecStep::JumpIfSuccess(_) | ExecStep::JumpIfFailure(_))
+            );
+            let runs_command = match step {
+                ExecStep::RunCommand(_) | ExecStep::RunSubshell(_) => !self.in_pipeline,
+                ExecStep::Arithmetic(_) => true,
+                ExecStep::EndPipeline => true,
+                _ => false,
+            };
 
-        for step in &plan {
             match step {
                 ExecStep::RunCommand(cmd) => {
                     if self.in_pipeline {
-                        pipeline_cmds.push(cmd.clone());
+                        pipeline_cmds.push(AstNode::Command(cmd.clone()));
+                    } else {
+                        match self.run_command(cmd, env)? {
+                            ExecOutcome::Code(code) => self.set_status(code, env),
+                            ExecOutcome::Exit(code) => return Ok(ExecOutcome::Exit(code)),
+                        }
+                    }
+                }
+                ExecStep::RunSubshell(inner) => {
+                    if self.in_pipeline {
+                        // Every pipeline stage already runs in its own process
+                        pipeline_cmds.push(inner.clone());
                     } else {
-                        let outcome = self.run_command(cmd, env)?;
-                        if let ExecOutcome::Exit(code) = outcome {
-                            return Ok(ExecOutcome::Exit(code));
+                        let jobs = Rc::clone(&self.jobs);
+                        let command = AstNode::Subshell(Box::new(inner.clone())).to_string();
+                        let outcome =
+                            SubshellHandler::exec_subshell_generic(&jobs, &command, || {
+                                self.exec(inner, env)
+                            })?;
+                        if let ExecOutcome::Code(code) = outcome {
+                            self.set_status(code, env);
                         }
                     }
                 }
-                ExecStep::BeginRedirect { kind, file } => {
-                    self.begin_redirect(kind, file)?;
+                ExecStep::RunBackground(inner) => {
+                    let jobs = Rc::clone(&self.jobs);
+                    let pid = SubshellHandler::spawn_background(&jobs, &inner.to_string(), || {
+                        self.exec(inner, env)
+                    })?;
+                    env.set("!", &pid.to_string());
+                    self.set_status(0, env);
                 }
-                ExecStep::EndRedirect { kind } => {
-                    self.end_redirect(kind)?;
+                ExecStep::BeginRedirect { kind, file, span } => {
+                    match self.begin_redirect(kind, file, *span, env) {
+                        Err(ExecError::RedirectError(msg)) => {
+                            // The redirected command does not run
+                            eprintln!("tiny-shell: {}", msg);
+                            self.set_status(1, env);
+                            pc = Self::skip_redirect(plan, pc);
+                        }
+                        result => {
+                            result?;
+                        }
+                    }
+                }
+                ExecStep::EndRedirect => {
+                    self.end_redirect();
                 }
                 ExecStep::BeginPipeline => {
                     self.begin_pipeline()?;
                 }
                 ExecStep::EndPipeline => {
-                    let outcome = self.end_pipeline(&pipeline_cmds, env)?;
-                    if let ExecOutcome::Exit(code) = outcome {
-                        return Ok(ExecOutcome::Exit(code));
+                    match self.end_pipeline(&pipeline_cmds, env)? {
+                        ExecOutcome::Code(code) => self.set_status(code, env),
+                        ExecOutcome::Exit(code) => return Ok(ExecOutcome::Exit(code)),
                     }
                     pipeline_cmds.clear();
                 }
+                ExecStep::Jump(target) => {
+                    pc = *target;
+                }
+                ExecStep::JumpIfSuccess(target) => {
+                    if self.last_status == 0 {
+                        pc = *target;
+                    }
+                }
+                ExecStep::JumpIfFailure(target) => {
+                    if self.last_status != 0 {
+                        pc = *target;
+                    }
+                }
+                ExecStep::SetStatus(code) => {
+                    self.set_status(*code, env);
+                }
+                ExecStep::BeginLoop { items, spans } => {
+                    let mut words = Vec::new();
+                    if let Some(items) = items {
+                        let expander = Expander::new(env, current_dir()?, self);
+                        words = expander.expand_items(items, spans)?;
+                        for (name, value) in expander.into_assignments() {
+                            env.set(&name, &value);
+                        }
+                    }
+                    self.loop_stack.push(LoopFrame {
+                        items: words.into_iter(),
+                        status: 0,
+                    });
+                }
+                ExecStep::NextItem { var, end } => {
+                    let frame = self
+                        .loop_stack
+                        .last_mut()
+                        .ok_or_else(|| ExecError::Custom("NextItem outside of a loop".into()))?;
+                    match frame.items.next() {
+                        Some(word) => env.set(var, &word),
+                        None => pc = *end,
+                    }
+                }
+                ExecStep::ContinueLoop(target) => {
+                    if let Some(frame) = self.loop_stack.last_mut() {
+                        frame.status = self.last_status;
+                    }
+                    pc = *target;
+                }
+                ExecStep::EndLoop => {
+                    if let Some(frame) = self.loop_stack.pop() {
+                        self.set_status(frame.status, env);
+                    }
+                }
+                ExecStep::Arithmetic(expr) => {
+                    let expander = Expander::new(env, current_dir()?, self);
+                    let value = expander.arithmetic(expr)?;
+                    for (name, value) in expander.into_assignments() {
+                        env.set(&name, &value);
+                    }
+                    self.set_status(if value != 0 { 0 } else { 1 }, env);
+                }
             }
-        }
-        Ok(ExecOutcome::Code(0))
-    }
-}
 
-impl<'a> FlattenExecutor<'a> {
-    pub fn new(builtin_manager: &'a BuiltinManager) -> Self {
-        FlattenExecutor {
-            builtin_manager,
-            stdin_stack: Vec::new(),
-            stdout_stack: Vec::new(),
-            // stderr_stack: Vec::new(),
-            in_pipeline: false,
+            if runs_command
+                && !tested
+                && self.last_status != 0
+                && let Some(code) = self.run_traps(true, env)?
+            {
+                return Ok(ExecOutcome::Exit(code));
+            }
         }
+        Ok(ExecOutcome::Code(self.last_status))
     }
 
     fn flatten_ast(&self, node: &AstNode, plan: &mut Vec<ExecStep>) {
@@ -86,15 +280,31 @@ impl<'a> FlattenExecutor<'a> {
             AstNode::Command(cmd) => {
                 plan.push(ExecStep::RunCommand(cmd.clone()));
             }
-            AstNode::Redirect { node: inner, kind, file } => {
-                plan.push(ExecStep::BeginRedirect { kind: kind.clone(), file: file.clone() });
+            AstNode::Redirect {
+                node: inner,
+                kind,
+                file,
+                span,
+            } => {
+                plan.push(ExecStep::BeginRedirect {
+                    kind: kind.clone(),
+                    file: file.clone(),
+                    span: *span,
+                });
                 self.flatten_ast(inner, plan);
-                plan.push(ExecStep::EndRedirect { kind: kind.clone() });
+                plan.push(ExecStep::EndRedirect);
             }
             AstNode::Pipeline(nodes) => {
                 plan.push(ExecStep::BeginPipeline);
                 for node in nodes {
-                    self.flatten_ast(node, plan);
+                    match node {
+                        AstNode::Command(cmd) => plan.push(ExecStep::RunCommand(cmd.clone())),
+                        AstNode::Subshell(inner) => {
+                            plan.push(ExecStep::RunSubshell((**inner).clone()))
+                        }
+                        // Compound or redirected stages are executed as a whole in the stage's process
+                        _ => plan.push(ExecStep::RunSubshell(node.clone())),
+                    }
                 }
                 plan.push(ExecStep::EndPipeline);
             }
@@ -104,100 +314,178 @@ impl<'a> FlattenExecutor<'a> {
                 }
             }
             AstNode::And(left, right) => {
+                // The right side is skipped (keeping the left status) when the left side fails
                 self.flatten_ast(left, plan);
-                // TODO: ExecStep::And
+                let jump = plan.len();
+                plan.push(ExecStep::JumpIfFailure(0));
                 self.flatten_ast(right, plan);
+                plan[jump] = ExecStep::JumpIfFailure(plan.len());
             }
             AstNode::Or(left, right) => {
                 self.flatten_ast(left, plan);
-                // TODO: ExecStep::Or
+                let jump = plan.len();
+                plan.push(ExecStep::JumpIfSuccess(0));
                 self.flatten_ast(right, plan);
+                plan[jump] = ExecStep::JumpIfSuccess(plan.len());
             }
             AstNode::Subshell(inner) => {
-                // TODO: ExecStep::BeginSubshell, ExecStep::EndSubshell
-                self.flatten_ast(inner, plan);
+                plan.push(ExecStep::RunSubshell((**inner).clone()));
+            }
+            AstNode::Background(inner) => {
+                plan.push(ExecStep::RunBackground((**inner).clone()));
             }
-            AstNode::Compound(_) => {
-                unimplemented!();
+            AstNode::Compound(compound) => {
+                self.flatten_compound(compound, plan);
             }
         }
     }
 
-    fn begin_redirect(&mut self, kind: &RedirectKind, file: &str) -> ExecStatus {
-        use RedirectKind::*;
-        match kind {
-            In => {
-                let f = File::open(file).map_err(ExecError::Io)?;
-                let new_fd = f.as_raw_fd();
-
-                // save (0: stdin)
-                let saved = unsafe { libc::dup(0) };
-                if saved < 0 {
-                    return Err(ExecError::Io(std::io::Error::last_os_error()));
-                }
-                self.stdin_stack.push(saved);
-
-                // Replacement
-                if unsafe { libc::dup2(new_fd, 0) } < 0 {
-                    return Err(ExecError::Io(std::io::Error::last_os_error()));
+    // Lower compound commands into conditional jumps.
+    // Jump targets are patched once the position of the label is known.
+    fn flatten_compound(&self, compound: &CompoundNode, plan: &mut Vec<ExecStep>) {
+        match compound {
+            CompoundNode::Group(nodes) => {
+                for node in nodes {
+                    self.flatten_ast(node, plan);
                 }
             }
-            Out => {
-                let f = File::create(file).map_err(ExecError::Io)?;
-                let new_fd = f.as_raw_fd();
-
-                let saved = unsafe { libc::dup(1) };
-                if saved < 0 {
-                    return Err(ExecError::Io(std::io::Error::last_os_error()));
+            CompoundNode::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                //     cond
+                //     JumpIfFailure(else)
+                //     then_branch
+                //     Jump(end)
+                // else:
+                //     else_branch (or SetStatus(0))
+                // end:
+                self.flatten_ast(cond, plan);
+                let jump_else = plan.len();
+                plan.push(ExecStep::JumpIfFailure(0));
+                for node in then_branch {
+                    self.flatten_ast(node, plan);
                 }
-                self.stdout_stack.push(saved);
-
-                if unsafe { libc::dup2(new_fd, 1) } < 0 {
-                    return Err(ExecError::Io(std::io::Error::last_os_error()));
+                let jump_end = plan.len();
+                plan.push(ExecStep::Jump(0));
+                plan[jump_else] = ExecStep::JumpIfFailure(plan.len());
+                match else_branch {
+                    Some(nodes) => {
+                        for node in nodes {
+                            self.flatten_ast(node, plan);
+                        }
+                    }
+                    None => plan.push(ExecStep::SetStatus(0)),
                 }
+                plan[jump_end] = ExecStep::Jump(plan.len());
             }
-            Append => {
-                let f = std::fs::OpenOptions::new()
-                    .write(true).append(true).create(true)
-                    .open(file)
-                    .map_err(ExecError::Io)?;
-                let new_fd = f.as_raw_fd();
-
-                let saved = unsafe { libc::dup(1) };
-                if saved < 0 {
-                    return Err(ExecError::Io(std::io::Error::last_os_error()));
+            CompoundNode::While { cond, body } | CompoundNode::Until { cond, body } => {
+                //     BeginLoop
+                // start:
+                //     cond
+                //     JumpIfFailure(end) (JumpIfSuccess for until)
+                //     body
+                //     ContinueLoop(start)
+                // end:
+                //     EndLoop
+                let is_while = matches!(compound, CompoundNode::While { .. });
+                plan.push(ExecStep::BeginLoop {
+                    items: None,
+                    spans: Vec::new(),
+                });
+                let start = plan.len();
+                self.flatten_ast(cond, plan);
+                let exit_jump = plan.len();
+                plan.push(ExecStep::Jump(0));
+                for node in body {
+                    self.flatten_ast(node, plan);
                 }
-                self.stdout_stack.push(saved);
-
-                if unsafe { libc::dup2(new_fd, 1) } < 0 {
-                    return Err(ExecError::Io(std::io::Error::last_os_error()));
+                plan.push(ExecStep::ContinueLoop(start));
+                let end = plan.len();
+                plan[exit_jump] = if is_while {
+                    ExecStep::JumpIfFailure(end)
+                } else {
+                    ExecStep::JumpIfSuccess(end)
+                };
+                plan.push(ExecStep::EndLoop);
+            }
+            CompoundNode::For {
+                var,
+                items,
+                spans,
+                body,
+            } => {
+                //     BeginLoop(items)
+                // start:
+                //     NextItem(var, end)
+                //     body
+                //     ContinueLoop(start)
+                // end:
+                //     EndLoop
+                plan.push(ExecStep::BeginLoop {
+                    items: Some(items.clone()),
+                    spans: spans.clone(),
+                });
+                let start = plan.len();
+                plan.push(ExecStep::NextItem {
+                    var: var.clone(),
+                    end: 0,
+                });
+                for node in body {
+                    self.flatten_ast(node, plan);
                 }
+                plan.push(ExecStep::ContinueLoop(start));
+                plan[start] = ExecStep::NextItem {
+                    var: var.clone(),
+                    end: plan.len(),
+                };
+                plan.push(ExecStep::EndLoop);
+            }
+            CompoundNode::Arithmetic(expr) => {
+                plan.push(ExecStep::Arithmetic(expr.clone()));
             }
         }
+    }
+
+    fn begin_redirect(
+        &mut self,
+        kind: &RedirectKind,
+        file: &str,
+        span: Option<(usize, usize)>,
+        env: &Environment,
+    ) -> ExecStatus {
+        let mut fds = FdTable::new();
+        fds.apply(kind, file, span, env, self)?;
+        self.redirect_stack.push(fds);
         Ok(ExecOutcome::Code(0))
     }
 
-    fn end_redirect(&mut self, kind: &RedirectKind) -> ExecStatus {
-        use RedirectKind::*;
-        match kind {
-            In => {
-                if let Some(saved) = self.stdin_stack.pop() {
-                    if unsafe { libc::dup2(saved, 0) } < 0 {
-                        return Err(ExecError::Io(std::io::Error::last_os_error()));
-                    }
-                    unsafe { libc::close(saved); }
-                }
-            }
-            Out | Append => {
-                if let Some(saved) = self.stdout_stack.pop() {
-                    if unsafe { libc::dup2(saved, 1) } < 0 {
-                        return Err(ExecError::Io(std::io::Error::last_os_error()));
-                    }
-                    unsafe { libc::close(saved); }
-                }
+    fn end_redirect(&mut self) {
+        if let Some(mut fds) = self.redirect_stack.pop() {
+            fds.restore();
+        }
+    }
+
+    // Index just past the EndRedirect matching the BeginRedirect before `pc`
+    fn skip_redirect(plan: &[ExecStep], mut pc: usize) -> usize {
+        let mut depth = 1;
+        while depth > 0 && pc < plan.len() {
+            match plan[pc] {
+                ExecStep::BeginRedirect { .. } => depth += 1,
+                ExecStep::EndRedirect => depth -= 1,
+                _ => {}
             }
+            pc += 1;
+        }
+        pc
+    }
+
+    // Restore the fds saved by redirects above the given stack depth, innermost first
+    fn unwind_redirects(&mut self, depth: usize) {
+        while self.redirect_stack.len() > depth {
+            self.end_redirect();
         }
-        Ok(ExecOutcome::Code(0))
     }
 
     fn begin_pipeline(&mut self) -> ExecStatus {
@@ -205,13 +493,27 @@ impl<'a> FlattenExecutor<'a> {
         Ok(ExecOutcome::Code(0))
     }
 
-    fn end_pipeline(&mut self, cmds: &[CommandNode], env: &mut Environment) -> ExecStatus {
-        PipelineHandler::exec_pipeline_generic(cmds, |cmd| self.run_command(cmd, env))?;
+    fn end_pipeline(&mut self, stages: &[AstNode], env: &mut Environment) -> ExecStatus {
+        // Reset before forking so that stages run their own plans normally
         self.in_pipeline = false;
-        Ok(ExecOutcome::Code(0))
+        let jobs = Rc::clone(&self.jobs);
+        let command = AstNode::Pipeline(stages.to_vec()).to_string();
+        let statuses =
+            PipelineHandler::exec_pipeline_generic(stages, &jobs, &command, |stage| match stage {
+                AstNode::Command(cmd) => self.run_command(cmd, env),
+                node => self.exec(node, env),
+            })?;
+        Ok(PipelineHandler::pipeline_status(&statuses, env))
     }
 
     fn run_command(&mut self, cmd: &CommandNode, env: &mut Environment) -> ExecStatus {
+        let expander = Expander::new(env, current_dir()?, self);
+        let cmd = &expander.expand_command(cmd.clone())?;
+        // Reaped when the command returns
+        let processes = expander.take_processes();
+        for (name, value) in expander.into_assignments() {
+            env.set(&name, &value);
+        }
         // Built-in command execution
         if self.builtin_manager.is_builtin(&cmd.name) {
             return self.builtin_manager.execute(&cmd.name, &cmd.args, env);
@@ -222,25 +524,276 @@ impl<'a> FlattenExecutor<'a> {
             Some(p) => p,
             None => {
                 eprintln!("tiny-shell: command not found or failed");
-                return Ok(ExecOutcome::Code(127)) // The shell's standard "command not found" exit code
+                return Ok(ExecOutcome::Code(127)); // The shell's standard "command not found" exit code
             }
         };
 
+        processes.inherit();
         // External command execution
-        let mut command = Command::new(path);
-        // command.args(&cmd.args);
-        for arg in &cmd.args {
-            command.arg(arg);
-        }
+        ExternalCommand::run(&path, cmd, env, &self.jobs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::CommandKind;
+    use crate::executor::builtin::BuiltinCommand;
+    use std::io::Write;
 
-        for (k, v) in &env.exported_vars() {
-            command.env(k, v);
+    fn cmd(name: &str) -> CommandNode {
+        CommandNode {
+            name: name.to_string(),
+            args: vec![],
+            kind: CommandKind::Simple,
+            spans: Vec::new(),
         }
+    }
+
+    fn plan_for(node: &AstNode) -> Vec<ExecStep> {
+        let builtin_manager = BuiltinManager::new();
+        let executor =
+            FlattenExecutor::new(&builtin_manager, Rc::new(RefCell::new(JobTable::new())));
+        let mut plan = Vec::new();
+        executor.flatten_ast(node, &mut plan);
+        plan
+    }
+
+    #[test]
+    fn test_flatten_and_or_short_circuit() {
+        // a && b || c
+        let node = AstNode::Or(
+            Box::new(AstNode::And(
+                Box::new(AstNode::Command(cmd("a"))),
+                Box::new(AstNode::Command(cmd("b"))),
+            )),
+            Box::new(AstNode::Command(cmd("c"))),
+        );
+        assert_eq!(
+            plan_for(&node),
+            vec![
+                ExecStep::RunCommand(cmd("a")),
+                ExecStep::JumpIfFailure(3),
+                ExecStep::RunCommand(cmd("b")),
+                ExecStep::JumpIfSuccess(5),
+                ExecStep::RunCommand(cmd("c")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exec_returns_last_status() {
+        let builtin_manager = BuiltinManager::new();
+        let mut env = Environment::new();
+
+        // false && true -> the right side is skipped and the status is 1
+        let node = AstNode::And(
+            Box::new(AstNode::Command(cmd("false"))),
+            Box::new(AstNode::Command(cmd("true"))),
+        );
+        let mut executor =
+            FlattenExecutor::new(&builtin_manager, Rc::new(RefCell::new(JobTable::new())));
+        assert_eq!(
+            executor.exec(&node, &mut env).unwrap(),
+            ExecOutcome::Code(1)
+        );
+
+        // false || true
+        let node = AstNode::Or(
+            Box::new(AstNode::Command(cmd("false"))),
+            Box::new(AstNode::Command(cmd("true"))),
+        );
+        let mut executor =
+            FlattenExecutor::new(&builtin_manager, Rc::new(RefCell::new(JobTable::new())));
+        assert_eq!(
+            executor.exec(&node, &mut env).unwrap(),
+            ExecOutcome::Code(0)
+        );
+    }
+
+    #[test]
+    fn test_flatten_subshell_pipeline_stage() {
+        // (a) | b
+        let node = AstNode::Pipeline(vec![
+            AstNode::Subshell(Box::new(AstNode::Command(cmd("a")))),
+            AstNode::Command(cmd("b")),
+        ]);
+        assert_eq!(
+            plan_for(&node),
+            vec![
+                ExecStep::BeginPipeline,
+                ExecStep::RunSubshell(AstNode::Command(cmd("a"))),
+                ExecStep::RunCommand(cmd("b")),
+                ExecStep::EndPipeline,
+            ]
+        );
+    }
 
-        match command.status() {
-            Ok(status) => Ok(ExecOutcome::Code(status.code().unwrap_or(1))),
-            Err(e) => Err(ExecError::Io(e)),
+    #[test]
+    fn test_exec_subshell_status() {
+        let builtin_manager = BuiltinManager::new();
+        let mut env = Environment::new();
+        let node = AstNode::Subshell(Box::new(AstNode::Command(CommandNode {
+            name: "exit".to_string(),
+            args: vec!["3".to_string()],
+            kind: CommandKind::Simple,
+            spans: Vec::new(),
+        })));
+        let mut executor =
+            FlattenExecutor::new(&builtin_manager, Rc::new(RefCell::new(JobTable::new())));
+        assert_eq!(
+            executor.exec(&node, &mut env).unwrap(),
+            ExecOutcome::Code(3)
+        );
+    }
+
+    #[test]
+    fn test_interrupt_stops_loop() {
+        let builtin_manager = BuiltinManager::new();
+        let jobs = Rc::new(RefCell::new(JobTable::new()));
+        let node = AstNode::Compound(CompoundNode::While {
+            cond: Box::new(AstNode::Command(cmd("true"))),
+            body: vec![AstNode::Command(cmd("true"))],
+        });
+        // Run in a child so that the interrupt flag does not leak into other tests
+        let status = SubshellHandler::exec_subshell_generic(&jobs, "test", || {
+            SignalHandler::interrupt();
+            let mut executor = FlattenExecutor::new(&builtin_manager, Rc::clone(&jobs));
+            executor.exec(&node, &mut Environment::new())
+        });
+        assert_eq!(status.unwrap(), ExecOutcome::Code(128 + libc::SIGINT));
+    }
+
+    #[test]
+    fn test_early_exit_unwinds_loops_and_redirects() {
+        let builtin_manager = BuiltinManager::new();
+        let mut executor =
+            FlattenExecutor::new(&builtin_manager, Rc::new(RefCell::new(JobTable::new())));
+        let file =
+            std::env::temp_dir().join(format!("tiny-shell-flatten-exit-{}", std::process::id()));
+        let exit_in_loop = |command: &str, arg: &str| AstNode::Redirect {
+            node: Box::new(AstNode::Compound(CompoundNode::For {
+                var: "x".to_string(),
+                items: vec!["1".to_string(), "2".to_string()],
+                spans: Vec::new(),
+                body: vec![AstNode::Command(CommandNode {
+                    args: vec![arg.to_string()],
+                    ..cmd(command)
+                })],
+            })),
+            kind: RedirectKind::Out(57),
+            file: file.to_str().unwrap().to_string(),
+            span: None,
+        };
+
+        let mut env = Environment::new();
+        let status = executor.exec(&exit_in_loop("exit", "3"), &mut env);
+        assert_eq!(status.unwrap(), ExecOutcome::Exit(3));
+        assert!(executor.loop_stack.is_empty() && executor.redirect_stack.is_empty());
+        assert!(
+            executor
+                .exec(&exit_in_loop("echo", "$((1 / 0))"), &mut env)
+                .is_err()
+        );
+        assert!(executor.loop_stack.is_empty() && executor.redirect_stack.is_empty());
+        assert_eq!(unsafe { libc::fcntl(57, libc::F_GETFD) }, -1);
+        let _ = std::fs::remove_file(file);
+    }
+
+    #[test]
+    fn test_substitution_uses_registered_builtins() {
+        struct Greet;
+        impl BuiltinCommand for Greet {
+            fn name(&self) -> &'static str {
+                "greet"
+            }
+            fn run(&self, _args: &[String], _env: &mut Environment) -> ExecStatus {
+                writeln!(std::io::stdout(), "hello").map_err(ExecError::Io)?;
+                Ok(ExecOutcome::Code(0))
+            }
         }
+        let mut builtin_manager = BuiltinManager::new();
+        builtin_manager.register(Box::new(Greet));
+        let mut env = Environment::new();
+        let node = AstNode::Command(CommandNode {
+            name: "export".to_string(),
+            args: vec!["X=$(greet)".to_string()],
+            kind: CommandKind::Simple,
+            spans: Vec::new(),
+        });
+        let mut executor =
+            FlattenExecutor::new(&builtin_manager, Rc::new(RefCell::new(JobTable::new())));
+        assert_eq!(
+            executor.exec(&node, &mut env).unwrap(),
+            ExecOutcome::Code(0)
+        );
+        assert_eq!(env.get("X"), Some("hello"));
+    }
+
+    #[test]
+    fn test_flatten_if_without_else() {
+        let node = AstNode::Compound(CompoundNode::If {
+            cond: Box::new(AstNode::Command(cmd("a"))),
+            then_branch: vec![AstNode::Command(cmd("b"))],
+            else_branch: None,
+        });
+        assert_eq!(
+            plan_for(&node),
+            vec![
+                ExecStep::RunCommand(cmd("a")),
+                ExecStep::JumpIfFailure(4),
+                ExecStep::RunCommand(cmd("b")),
+                ExecStep::Jump(5),
+                ExecStep::SetStatus(0),
+            ]
+        );
     }
-}
 
+    #[test]
+    fn test_flatten_until() {
+        let node = AstNode::Compound(CompoundNode::Until {
+            cond: Box::new(AstNode::Command(cmd("a"))),
+            body: vec![AstNode::Command(cmd("b"))],
+        });
+        assert_eq!(
+            plan_for(&node),
+            vec![
+                ExecStep::BeginLoop {
+                    items: None,
+                    spans: Vec::new()
+                },
+                ExecStep::RunCommand(cmd("a")),
+                ExecStep::JumpIfSuccess(5),
+                ExecStep::RunCommand(cmd("b")),
+                ExecStep::ContinueLoop(1),
+                ExecStep::EndLoop,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flatten_for() {
+        let node = AstNode::Compound(CompoundNode::For {
+            var: "x".to_string(),
+            items: vec!["1".to_string(), "2".to_string()],
+            spans: Vec::new(),
+            body: vec![AstNode::Command(cmd("b"))],
+        });
+        assert_eq!(
+            plan_for(&node),
+            vec![
+                ExecStep::BeginLoop {
+                    items: Some(vec!["1".to_string(), "2".to_string()]),
+                    spans: Vec::new()
+                },
+                ExecStep::NextItem {
+                    var: "x".to_string(),
+                    end: 4
+                },
+                ExecStep::RunCommand(cmd("b")),
+                ExecStep::ContinueLoop(1),
+                ExecStep::EndLoop,
+            ]
+        );
+    }
+}
@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 mod flatten_executor;
 
 pub use flatten_executor::FlattenExecutor;
@@ -0,0 +1,360 @@
+use crate::executor::ExecError;
+use crate::executor::command::exit_code;
+use crate::executor::signal::SignalHandler;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    Done(i32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Process {
+    pub pid: libc::pid_t,
+    pub status: Option<i32>, // None while running or stopped
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    pub id: usize,
+    pub pgid: libc::pid_t,
+    pub processes: Vec<Process>,
+    pub command: String,
+    pub state: JobState,
+}
+
+impl Job {
+    // The first process leads the job's process group
+    pub fn new(pids: &[libc::pid_t], command: &str) -> Self {
+        Job {
+            id: 0,
+            pgid: pids.first().copied().unwrap_or(0),
+            processes: pids
+                .iter()
+                .map(|&pid| Process { pid, status: None })
+                .collect(),
+            command: command.to_string(),
+            state: JobState::Running,
+        }
+    }
+
+    // e.g. "[1]+  Done                    sleep 1"
+    pub fn describe(&self, marker: char) -> String {
+        let state = match &self.state {
+            JobState::Running => "Running".to_string(),
+            JobState::Stopped => "Stopped".to_string(),
+            JobState::Done(0) => "Done".to_string(),
+            JobState::Done(code) => format!("Exit {}", code),
+        };
+        let suffix = if self.state == JobState::Running {
+            " &"
+        } else {
+            ""
+        };
+        format!(
+            "[{}]{}  {:<24}{}{}",
+            self.id, marker, state, self.command, suffix
+        )
+    }
+
+    // Exit status of every process; stopped ones report 128+SIGTSTP like a signal death
+    pub fn statuses(&self) -> Vec<i32> {
+        self.processes
+            .iter()
+            .map(|p| p.status.unwrap_or(128 + libc::SIGTSTP))
+            .collect()
+    }
+
+    // Collect status changes of the job's processes. When blocking, this returns
+    // once every process has exited or one of them has been stopped.
+    fn wait(&mut self, block: bool) -> Result<(), ExecError> {
+        let mut flags = if block { 0 } else { libc::WNOHANG };
+        if SignalHandler::job_control_enabled() {
+            flags |= libc::WUNTRACED;
+        }
+
+        for process in self.processes.iter_mut().filter(|p| p.status.is_none()) {
+            let mut status = 0;
+            let ret = loop {
+                let ret = unsafe { libc::waitpid(process.pid, &mut status, flags) };
+                if ret >= 0
+                    || std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted
+                {
+                    break ret;
+                }
+            };
+
+            if ret == 0 {
+                continue; // Still running
+            }
+            if ret < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.raw_os_error() != Some(libc::ECHILD) {
+                    return Err(ExecError::Io(err));
+                }
+                // Already reaped elsewhere; nothing more can be learned about it
+                process.status = Some(0);
+            } else if libc::WIFSTOPPED(status) {
+                self.state = JobState::Stopped;
+                if block {
+                    return Ok(());
+                }
+            } else {
+                // With job control, Ctrl+C reaches the job's process group rather than the
+                // shell; act as if the shell got it. Otherwise the shell gets it itself, and a
+                // job killed by someone else's SIGINT interrupts nothing.
+                if block
+                    && SignalHandler::job_control_enabled()
+                    && libc::WIFSIGNALED(status)
+                    && libc::WTERMSIG(status) == libc::SIGINT
+                {
+                    SignalHandler::interrupt();
+                }
+                process.status = Some(exit_code(ExitStatus::from_raw(status)));
+            }
+        }
+
+        if self.processes.iter().all(|p| p.status.is_some()) {
+            let last = self.processes.last().and_then(|p| p.status).unwrap_or(0);
+            self.state = JobState::Done(last);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct JobTable {
+    jobs: Vec<Job>,
+}
+
+impl JobTable {
+    pub fn new() -> Self {
+        Self { jobs: Vec::new() }
+    }
+
+    // Register a background process and return its job number
+    pub fn add(&mut self, pid: libc::pid_t, command: &str) -> usize {
+        self.insert(Job::new(&[pid], command))
+    }
+
+    // Jobs keep their number when they come back into the table (e.g. stopped again after `fg`)
+    fn insert(&mut self, mut job: Job) -> usize {
+        if job.id == 0 {
+            job.id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        }
+        let id = job.id;
+        let pos = self
+            .jobs
+            .iter()
+            .position(|j| j.id > id)
+            .unwrap_or(self.jobs.len());
+        self.jobs.insert(pos, job);
+        id
+    }
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let pos = self.jobs.iter().position(|job| job.id == id)?;
+        Some(self.jobs.remove(pos))
+    }
+
+    // `+` marks the current (most recent) job, `-` the previous one
+    pub fn marker(&self, id: usize) -> char {
+        let mut ids: Vec<usize> = self.jobs.iter().map(|job| job.id).collect();
+        ids.sort_unstable();
+        match ids.iter().rev().position(|&i| i == id) {
+            Some(0) => '+',
+            Some(1) => '-',
+            _ => ' ',
+        }
+    }
+
+    // Resolve a job spec (`%N`, `N`, `%+`, `%%`, `%-`, or none for the current job)
+    pub fn resolve(&self, spec: Option<&str>) -> Option<usize> {
+        let mut ids: Vec<usize> = self.jobs.iter().map(|job| job.id).collect();
+        ids.sort_unstable();
+        match spec {
+            None | Some("%") | Some("%%") | Some("%+") => ids.last().copied(),
+            Some("%-") => ids.iter().rev().nth(1).copied(),
+            Some(s) => {
+                let id = s.strip_prefix('%').unwrap_or(s).parse().ok()?;
+                ids.contains(&id).then_some(id)
+            }
+        }
+    }
+
+    // Wait for a foreground job while it owns the terminal.
+    // If it gets stopped (Ctrl+Z) it is kept in the table and reported.
+    pub fn wait_foreground(
+        &mut self,
+        pids: &[libc::pid_t],
+        command: &str,
+    ) -> Result<Vec<i32>, ExecError> {
+        self.foreground(Job::new(pids, command), false)
+    }
+
+    // `fg`: continue a job in the foreground and wait for it
+    pub fn resume_foreground(&mut self, id: usize) -> Result<i32, ExecError> {
+        let job = self
+            .remove(id)
+            .ok_or_else(|| ExecError::Custom(format!("fg: %{}: no such job", id)))?;
+        println!("{}", job.command);
+        let statuses = self.foreground(job, true)?;
+        Ok(statuses.last().copied().unwrap_or(0))
+    }
+
+    // `bg`: continue a stopped job without waiting for it
+    pub fn resume_background(&mut self, id: usize) -> Result<(), ExecError> {
+        let marker = self.marker(id);
+        let job = self
+            .jobs
+            .iter_mut()
+            .find(|job| job.id == id)
+            .ok_or_else(|| ExecError::Custom(format!("bg: %{}: no such job", id)))?;
+        if unsafe { libc::kill(-job.pgid, libc::SIGCONT) } < 0 {
+            return Err(ExecError::Io(std::io::Error::last_os_error()));
+        }
+        job.state = JobState::Running;
+        println!("[{}]{} {} &", job.id, marker, job.command);
+        Ok(())
+    }
+
+    fn foreground(&mut self, mut job: Job, resume: bool) -> Result<Vec<i32>, ExecError> {
+        SignalHandler::give_terminal(job.pgid);
+        if resume {
+            unsafe { libc::kill(-job.pgid, libc::SIGCONT) };
+            job.state = JobState::Running;
+        }
+        let result = job.wait(true);
+        SignalHandler::reclaim_terminal();
+        result?;
+
+        let statuses = job.statuses();
+        if job.state == JobState::Stopped {
+            eprintln!();
+            let id = self.insert(job);
+            if let Some(job) = self.jobs.iter().find(|job| job.id == id) {
+                eprintln!("{}", job.describe(self.marker(id)));
+            }
+        }
+        Ok(statuses)
+    }
+
+    // Collect finished jobs without blocking, removing them from the table.
+    // Each job is returned together with its marker at the time it finished.
+    pub fn reap(&mut self) -> Vec<(Job, char)> {
+        for job in self.jobs.iter_mut() {
+            if job.wait(false).is_err() {
+                job.state = JobState::Done(0);
+            }
+        }
+
+        let finished: Vec<(Job, char)> = self
+            .jobs
+            .iter()
+            .filter(|job| matches!(job.state, JobState::Done(_)))
+            .map(|job| (job.clone(), self.marker(job.id)))
+            .collect();
+        self.jobs
+            .retain(|job| !matches!(job.state, JobState::Done(_)));
+        finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn_exit(code: i32) -> libc::pid_t {
+        let pid = unsafe { libc::fork() };
+        if pid == 0 {
+            unsafe { libc::_exit(code) };
+        }
+        pid
+    }
+
+    #[test]
+    fn test_add_assigns_increasing_ids() {
+        let mut table = JobTable::new();
+        assert_eq!(table.add(100, "sleep 1"), 1);
+        assert_eq!(table.add(101, "sleep 2"), 2);
+        assert_eq!(table.marker(2), '+');
+        assert_eq!(table.marker(1), '-');
+    }
+
+    #[test]
+    fn test_reap_finished_job() {
+        let mut table = JobTable::new();
+        let pid = spawn_exit(3);
+        table.add(pid, "false");
+
+        let mut finished = Vec::new();
+        for _ in 0..100 {
+            finished = table.reap();
+            if !finished.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].0.state, JobState::Done(3));
+        assert_eq!(finished[0].1, '+');
+        assert!(table.jobs().is_empty());
+    }
+
+    #[test]
+    fn test_sigint_of_job_without_job_control() {
+        let pid = unsafe { libc::fork() };
+        if pid == 0 {
+            unsafe { libc::raise(libc::SIGINT) };
+            unsafe { libc::_exit(0) };
+        }
+        let statuses = JobTable::new()
+            .wait_foreground(&[pid], "kill -INT $$")
+            .unwrap();
+        assert_eq!(statuses, vec![128 + libc::SIGINT]);
+        // Tests run without job control, so the shell was not interrupted
+        assert!(!SignalHandler::interrupted());
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut job = Job::new(&[42], "sleep 1");
+        job.id = 1;
+        job.state = JobState::Done(0);
+        assert_eq!(job.describe('+'), "[1]+  Done                    sleep 1");
+        job.state = JobState::Stopped;
+        assert_eq!(job.describe('-'), "[1]-  Stopped                 sleep 1");
+    }
+
+    #[test]
+    fn test_resolve_job_spec() {
+        let mut table = JobTable::new();
+        table.add(100, "sleep 1");
+        table.add(101, "sleep 2");
+        assert_eq!(table.resolve(None), Some(2));
+        assert_eq!(table.resolve(Some("%-")), Some(1));
+        assert_eq!(table.resolve(Some("%1")), Some(1));
+        assert_eq!(table.resolve(Some("2")), Some(2));
+        assert_eq!(table.resolve(Some("%3")), None);
+
+        // A removed (disowned) job keeps the others' numbers
+        table.remove(1);
+        assert_eq!(table.resolve(Some("%2")), Some(2));
+        assert_eq!(table.add(102, "sleep 3"), 3);
+    }
+
+    #[test]
+    fn test_wait_foreground_collects_statuses() {
+        let mut table = JobTable::new();
+        let pids = [spawn_exit(2), spawn_exit(0)];
+        assert_eq!(table.wait_foreground(&pids, "a | b").unwrap(), vec![2, 0]);
+        assert!(table.jobs().is_empty());
+    }
+}
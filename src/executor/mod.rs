@@ -1,13 +1,25 @@
+pub mod builtin;
+mod command;
+#[allow(clippy::module_inception)]
 mod executor;
-mod recursive_executor;
+mod fd_table;
 mod flatten_executor;
+mod job;
 mod path_resolver;
 mod pipeline;
+mod recursive_executor;
+mod signal;
+mod subshell;
+#[cfg(test)]
 mod tests;
-pub mod builtin;
+mod trap;
 
-pub use executor::{Executor, ExecStatus, ExecOutcome, ExecError};
-pub use recursive_executor::RecursiveExecutor;
+pub use executor::{ExecError, ExecOutcome, ExecStatus, Executor};
+pub use fd_table::FdTable;
 pub use flatten_executor::FlattenExecutor;
+pub use job::{Job, JobState, JobTable, Process};
 pub use path_resolver::PathResolver;
-
+pub use recursive_executor::RecursiveExecutor;
+pub use signal::SignalHandler;
+pub use subshell::{ProcessSubstitutions, SubshellHandler};
+pub use trap::TrapHandler;
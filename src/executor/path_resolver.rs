@@ -18,7 +18,11 @@ impl PathResolver {
         if let Ok(paths) = env::var("PATH") {
             for dir in env::split_paths(&paths) {
                 let full_path = dir.join(command);
-                if full_path.exists() && fs::metadata(&full_path).map(|m| m.is_file()).unwrap_or(false) {
+                if full_path.exists()
+                    && fs::metadata(&full_path)
+                        .map(|m| m.is_file())
+                        .unwrap_or(false)
+                {
                     return Some(full_path);
                 }
             }
@@ -27,4 +31,3 @@ impl PathResolver {
         None
     }
 }
-
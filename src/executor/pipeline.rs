@@ -1,30 +1,40 @@
-use crate::executor::{ ExecStatus, ExecOutcome, ExecError };
+use crate::environment::Environment;
+use crate::executor::job::JobTable;
+use crate::executor::signal::SignalHandler;
+use crate::executor::subshell::exit_child;
+use crate::executor::{ExecError, ExecOutcome};
+use std::cell::RefCell;
 
 pub struct PipelineHandler;
 
 impl PipelineHandler {
+    // Run each node in its own process connected by pipes, all in one process group,
+    // returning the exit status of every stage in order.
     pub fn exec_pipeline_generic<T, F>(
         nodes: &[T],
+        jobs: &RefCell<JobTable>,
+        command: &str,
         mut exec_fn: F,
-    ) -> ExecStatus
+    ) -> Result<Vec<i32>, ExecError>
     where
         F: FnMut(&T) -> Result<ExecOutcome, ExecError>,
     {
         if nodes.len() < 2 {
-            return Err(ExecError::Custom("Pipeline must have at least two commands".into()));
+            return Err(ExecError::Custom(
+                "Pipeline must have at least two commands".into(),
+            ));
         }
 
         let mut prev_read_fd: Option<i32> = None;
         let mut child_pids = Vec::new();
+        let mut pgid = 0; // The first stage leads the group
 
         for (i, node) in nodes.iter().enumerate() {
             let is_last = i == nodes.len() - 1;
             let mut pipefds = [0; 2];
 
-            if !is_last {
-                if unsafe { libc::pipe(pipefds.as_mut_ptr()) } == -1 {
-                    return Err(ExecError::Io(std::io::Error::last_os_error()));
-                }
+            if !is_last && unsafe { libc::pipe(pipefds.as_mut_ptr()) } == -1 {
+                return Err(ExecError::Io(std::io::Error::last_os_error()));
             }
 
             let pid = unsafe { libc::fork() };
@@ -34,6 +44,7 @@ impl PipelineHandler {
 
             if pid == 0 {
                 // Child process
+                SignalHandler::setup_child(pgid, true);
                 if let Some(read_fd) = prev_read_fd {
                     unsafe {
                         libc::dup2(read_fd, 0);
@@ -47,43 +58,79 @@ impl PipelineHandler {
                         libc::close(pipefds[1]);
                     }
                 }
-                std::process::exit(match exec_fn(node) {
-                    Ok(ExecOutcome::Code(code)) | Ok(ExecOutcome::Exit(code)) => code,
-                    Err(_) => 1,
-                });
+                exit_child(exec_fn(node));
             } else {
                 // Parent process
                 if let Some(read_fd) = prev_read_fd {
-                    unsafe { libc::close(read_fd); }
+                    unsafe {
+                        libc::close(read_fd);
+                    }
                 }
                 if !is_last {
-                    unsafe { libc::close(pipefds[1]); }
+                    unsafe {
+                        libc::close(pipefds[1]);
+                    }
                     prev_read_fd = Some(pipefds[0]);
                 } else {
                     prev_read_fd = None;
                 }
+                if pgid == 0 {
+                    pgid = pid;
+                }
+                SignalHandler::assign_group(pid, pgid);
                 child_pids.push(pid);
             }
         }
 
-        for pid in child_pids {
-            let mut status_code = 0;
-            unsafe { libc::waitpid(pid, &mut status_code, 0); }
-        }
-        Ok(ExecOutcome::Code(0))
+        jobs.borrow_mut().wait_foreground(&child_pids, command)
+    }
+
+    // Record the stage statuses in `PIPESTATUS` and compute the pipeline's status:
+    // the last stage, or with `pipefail` the rightmost stage that failed.
+    pub fn pipeline_status(statuses: &[i32], env: &mut Environment) -> ExecOutcome {
+        let joined = statuses
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        env.set("PIPESTATUS", &joined);
+
+        let code = if env.option("pipefail") {
+            statuses
+                .iter()
+                .rev()
+                .find(|&&s| s != 0)
+                .copied()
+                .unwrap_or(0)
+        } else {
+            statuses.last().copied().unwrap_or(0)
+        };
+        ExecOutcome::Code(code)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::executor::{ExecError, ExecStatus};
+    use crate::executor::ExecError;
+
+    fn run<F>(nodes: &[i32], exec_fn: F) -> Result<Vec<i32>, ExecError>
+    where
+        F: FnMut(&i32) -> Result<ExecOutcome, ExecError>,
+    {
+        PipelineHandler::exec_pipeline_generic(
+            nodes,
+            &RefCell::new(JobTable::new()),
+            "test",
+            exec_fn,
+        )
+    }
 
     #[test]
     fn test_pipeline_with_two_nodes_success() {
         let nodes = vec![1, 2];
         let exec_fn = |_n: &i32| Ok(ExecOutcome::Code(0));
-        let result = PipelineHandler::exec_pipeline_generic(&nodes, exec_fn);
+        let result = run(&nodes, exec_fn);
         assert!(result.is_ok());
     }
 
@@ -91,7 +138,7 @@ mod tests {
     fn test_pipeline_with_one_node_should_fail() {
         let nodes = vec![1];
         let exec_fn = |_n: &i32| Ok(ExecOutcome::Code(0));
-        let result = PipelineHandler::exec_pipeline_generic(&nodes, exec_fn);
+        let result = run(&nodes, exec_fn);
         assert!(matches!(result, Err(ExecError::Custom(_))));
     }
 
@@ -99,9 +146,46 @@ mod tests {
     fn test_pipeline_exec_fn_error_propagation() {
         let nodes = vec![1, 2];
         let exec_fn = |_n: &i32| Err(ExecError::Custom("fail".into()));
-        let result = PipelineHandler::exec_pipeline_generic(&nodes, exec_fn);
-        // The error is only visible in the child, parent always returns Ok(0)
-        assert!(result.is_ok());
+        let result = run(&nodes, exec_fn);
+        // The error is only visible in the child, which reports it and exits with status 1
+        assert_eq!(result.unwrap(), vec![1, 1]);
+
+        // An interrupted stage exits as if killed by SIGINT
+        let exec_fn = |n: &i32| {
+            if *n == 1 {
+                Err(ExecError::Interrupted)
+            } else {
+                Ok(ExecOutcome::Code(0))
+            }
+        };
+        assert_eq!(run(&nodes, exec_fn).unwrap(), vec![130, 0]);
     }
-}
 
+    #[test]
+    fn test_pipeline_collects_stage_statuses() {
+        let nodes = vec![3, 0, 5];
+        let exec_fn = |n: &i32| Ok(ExecOutcome::Code(*n));
+        let result = run(&nodes, exec_fn);
+        assert_eq!(result.unwrap(), vec![3, 0, 5]);
+    }
+
+    #[test]
+    fn test_pipeline_status_and_pipefail() {
+        let mut env = Environment::new();
+        assert_eq!(
+            PipelineHandler::pipeline_status(&[1, 0], &mut env),
+            ExecOutcome::Code(0)
+        );
+        assert_eq!(env.get("PIPESTATUS"), Some("1 0"));
+
+        env.set_option("pipefail", true);
+        assert_eq!(
+            PipelineHandler::pipeline_status(&[1, 2, 0], &mut env),
+            ExecOutcome::Code(2)
+        );
+        assert_eq!(
+            PipelineHandler::pipeline_status(&[0, 0], &mut env),
+            ExecOutcome::Code(0)
+        );
+    }
+}
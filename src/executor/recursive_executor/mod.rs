@@ -1,5 +1,5 @@
+#[allow(clippy::module_inception)]
 mod recursive_executor;
 mod redirect;
 
 pub use recursive_executor::RecursiveExecutor;
-
@@ -1,74 +1,235 @@
-use std::process::Command;
 use super::redirect::RedirectHandler;
-use crate::executor::{ Executor, ExecStatus, ExecOutcome, ExecError };
+use crate::ast::{AstNode, CommandKind, CommandNode, CompoundNode};
+use crate::environment::Environment;
 use crate::executor::builtin::BuiltinManager;
+use crate::executor::command::ExternalCommand;
+use crate::executor::executor::current_dir;
+use crate::executor::job::JobTable;
 use crate::executor::path_resolver::PathResolver;
 use crate::executor::pipeline::PipelineHandler;
-use crate::ast::{AstNode, CommandNode, CommandKind};
-use crate::environment::Environment;
+use crate::executor::signal::SignalHandler;
+use crate::executor::subshell::SubshellHandler;
+use crate::executor::trap::TrapHandler;
+use crate::executor::{ExecError, ExecOutcome, ExecStatus, Executor};
+use crate::expander::Expander;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 pub struct RecursiveExecutor<'a> {
     builtin_manager: &'a BuiltinManager,
-    // pub path_resolver: PathResolver,
-    // pub redirect_handler: RedirectHandler,
-    // pub signal_handler: SignalHandler,
+    jobs: Rc<RefCell<JobTable>>,
+    condition_depth: usize, // > 0 while running an if/while/until condition or the left of && / ||
+                            // pub path_resolver: PathResolver,
+                            // pub redirect_handler: RedirectHandler,
+                            // pub signal_handler: SignalHandler,
 }
 
 impl<'a> Executor for RecursiveExecutor<'a> {
     fn exec(&mut self, node: &AstNode, env: &mut Environment) -> ExecStatus {
-        match node {
-            AstNode::Command(cmd) => {
-                self.exec_command(cmd, env)
-            }
-            AstNode::Redirect { node: inner, kind, file } => {
-                RedirectHandler::handle_redirect(inner, kind, file, self, env)
-            }
-            AstNode::Pipeline(nodes) => {
-                PipelineHandler::exec_pipeline_generic(nodes, |node| self.exec(node, env))
+        if SignalHandler::interrupted() {
+            return Err(ExecError::Interrupted);
+        }
+        if let Some(code) = TrapHandler::run_pending(self, env)? {
+            return Ok(ExecOutcome::Exit(code));
+        }
+        let outcome = self.exec_node(node, env)?;
+        if let ExecOutcome::Code(code) = outcome {
+            env.set_last_status(code);
+            let is_command = matches!(
+                node,
+                AstNode::Command(_)
+                    | AstNode::Pipeline(_)
+                    | AstNode::Subshell(_)
+                    | AstNode::Compound(CompoundNode::Arithmetic(_))
+            );
+            if code != 0
+                && is_command
+                && self.condition_depth == 0
+                && let Some(code) = TrapHandler::run_err(self, env)?
+            {
+                return Ok(ExecOutcome::Exit(code));
             }
-            AstNode::Sequence(seq) => {
-                for node in seq {
-                    self.exec(node, env)?;
+        }
+        Ok(outcome)
+    }
+
+    fn child(&self) -> Box<dyn Executor + '_> {
+        Box::new(RecursiveExecutor::new(
+            self.builtin_manager,
+            Rc::clone(&self.jobs),
+        ))
+    }
+}
+
+impl<'a> RecursiveExecutor<'a> {
+    fn exec_node(&mut self, node: &AstNode, env: &mut Environment) -> ExecStatus {
+        match node {
+            AstNode::Command(cmd) => self.exec_command(cmd, env),
+            AstNode::Redirect {
+                node: inner,
+                kind,
+                file,
+                span,
+            } => {
+                match RedirectHandler::handle_redirect(inner, kind, file, *span, self, env) {
+                    Err(ExecError::RedirectError(msg)) => {
+                        // The redirected command does not run
+                        eprintln!("tiny-shell: {}", msg);
+                        Ok(ExecOutcome::Code(1))
+                    }
+                    result => result,
                 }
-                Ok(ExecOutcome::Code(0))
             }
-            AstNode::And(left, right) => {
-                if self.exec(left, env)? == ExecOutcome::Code(0) {
-                    self.exec(right, env)
-                } else {
-                    Ok(ExecOutcome::Code(1))
-                }
+            AstNode::Pipeline(nodes) => {
+                let jobs = Rc::clone(&self.jobs);
+                let statuses = PipelineHandler::exec_pipeline_generic(
+                    nodes,
+                    &jobs,
+                    &node.to_string(),
+                    |node| self.exec(node, env),
+                )?;
+                Ok(PipelineHandler::pipeline_status(&statuses, env))
             }
-            AstNode::Or(left, right) => {
-                if self.exec(left, env)? != ExecOutcome::Code(0) {
-                    self.exec(right, env)
-                } else {
-                    Ok(ExecOutcome::Code(0))
-                }
+            AstNode::Sequence(seq) => self.exec_list(seq, env),
+            AstNode::And(left, right) => match self.exec_condition(left, env)? {
+                ExecOutcome::Code(0) => self.exec(right, env),
+                outcome => Ok(outcome),
+            },
+            AstNode::Or(left, right) => match self.exec_condition(left, env)? {
+                ExecOutcome::Code(code) if code != 0 => self.exec(right, env),
+                outcome => Ok(outcome),
+            },
+            AstNode::Subshell(inner) => {
+                let jobs = Rc::clone(&self.jobs);
+                SubshellHandler::exec_subshell_generic(&jobs, &node.to_string(), || {
+                    self.exec(inner, env)
+                })
             }
-            AstNode::Subshell(_inner) => {
-                Err(ExecError::NotImplemented("Not implemented".to_string()))
+            AstNode::Background(inner) => {
+                let jobs = Rc::clone(&self.jobs);
+                let pid = SubshellHandler::spawn_background(&jobs, &inner.to_string(), || {
+                    self.exec(inner, env)
+                })?;
+                env.set("!", &pid.to_string());
+                Ok(ExecOutcome::Code(0))
             }
-            _ => Err(ExecError::NotImplemented("Not implemented".to_string())),
+            AstNode::Compound(compound) => self.exec_compound(compound, env),
         }
     }
 }
 
 impl<'a> RecursiveExecutor<'a> {
-    pub fn new(builtin_manager: &'a BuiltinManager) -> Self {
+    pub fn new(builtin_manager: &'a BuiltinManager, jobs: Rc<RefCell<JobTable>>) -> Self {
         RecursiveExecutor {
             builtin_manager,
+            jobs,
+            condition_depth: 0,
             // path_resolver: PathResolver,
             // redirect_handler: RedirectHandler::new(),
             // signal_handler: SignalHandler::new(),
         }
     }
 
-    fn exec_command(
+    fn exec_compound(&mut self, compound: &CompoundNode, env: &mut Environment) -> ExecStatus {
+        match compound {
+            CompoundNode::Group(nodes) => self.exec_list(nodes, env),
+            CompoundNode::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => match self.exec_condition(cond, env)? {
+                ExecOutcome::Code(0) => self.exec_list(then_branch, env),
+                ExecOutcome::Code(_) => match else_branch {
+                    Some(nodes) => self.exec_list(nodes, env),
+                    None => Ok(ExecOutcome::Code(0)),
+                },
+                exit => Ok(exit),
+            },
+            CompoundNode::While { cond, body } => self.exec_loop(cond, body, true, env),
+            CompoundNode::Until { cond, body } => self.exec_loop(cond, body, false, env),
+            CompoundNode::For {
+                var,
+                items,
+                spans,
+                body,
+            } => {
+                let expander = Expander::new(env, current_dir()?, self);
+                let words = expander.expand_items(items, spans)?;
+                for (name, value) in expander.into_assignments() {
+                    env.set(&name, &value);
+                }
+                let mut status = ExecOutcome::Code(0);
+                for word in words {
+                    env.set(var, &word);
+                    status = self.exec_list(body, env)?;
+                    if let ExecOutcome::Exit(_) = status {
+                        break;
+                    }
+                }
+                Ok(status)
+            }
+            CompoundNode::Arithmetic(expr) => {
+                let expander = Expander::new(env, current_dir()?, self);
+                let value = expander.arithmetic(expr)?;
+                for (name, value) in expander.into_assignments() {
+                    env.set(&name, &value);
+                }
+                Ok(ExecOutcome::Code(if value != 0 { 0 } else { 1 }))
+            }
+        }
+    }
+
+    // Run the body while the condition's success matches `expected`
+    fn exec_loop(
         &mut self,
-        cmd: &CommandNode,
+        cond: &AstNode,
+        body: &[AstNode],
+        expected: bool,
         env: &mut Environment,
     ) -> ExecStatus {
+        let mut status = ExecOutcome::Code(0);
+        loop {
+            match self.exec_condition(cond, env)? {
+                ExecOutcome::Code(code) if (code == 0) == expected => {}
+                ExecOutcome::Code(_) => break,
+                exit => return Ok(exit),
+            }
+            status = self.exec_list(body, env)?;
+            if let ExecOutcome::Exit(_) = status {
+                break;
+            }
+        }
+        Ok(status)
+    }
+
+    // A failing condition is a normal outcome, so it does not trigger the ERR trap
+    fn exec_condition(&mut self, node: &AstNode, env: &mut Environment) -> ExecStatus {
+        self.condition_depth += 1;
+        let result = self.exec(node, env);
+        self.condition_depth -= 1;
+        result
+    }
+
+    // Execute a compound list, returning the status of the last command
+    fn exec_list(&mut self, nodes: &[AstNode], env: &mut Environment) -> ExecStatus {
+        let mut status = ExecOutcome::Code(0);
+        for node in nodes {
+            status = self.exec(node, env)?;
+            if let ExecOutcome::Exit(_) = status {
+                break;
+            }
+        }
+        Ok(status)
+    }
+
+    fn exec_command(&mut self, cmd: &CommandNode, env: &mut Environment) -> ExecStatus {
+        let expander = Expander::new(env, current_dir()?, self);
+        let cmd = expander.expand_command(cmd.clone())?;
+        // Reaped when the command returns
+        let processes = expander.take_processes();
+        for (name, value) in expander.into_assignments() {
+            env.set(&name, &value);
+        }
         match cmd.kind {
             CommandKind::Builtin => {
                 // if let Some(builtin) = self.builtin_manager.find(&cmd.name) {
@@ -91,28 +252,15 @@ impl<'a> RecursiveExecutor<'a> {
                     Some(p) => p,
                     None => {
                         eprintln!("tiny-shell: command not found or failed");
-                        return Ok(ExecOutcome::Code(127)) // The shell's standard "command not found" exit code
+                        return Ok(ExecOutcome::Code(127)); // The shell's standard "command not found" exit code
                         // return Err(ExecError::CommandNotFound(cmd.name.clone()));
                     }
                 };
 
+                processes.inherit();
                 // External command execution
-                let mut command = Command::new(path);
-
-                // command.args(&cmd.args);
-                for arg in &cmd.args {
-                    command.arg(arg);
-                }
-                for (k, v) in &env.exported_vars() {
-                    command.env(k, v);
-                }
-
-                match command.status() {
-                    Ok(status) => Ok(ExecOutcome::Code(status.code().unwrap_or(1))),
-                    Err(e) => Err(ExecError::Io(e)),
-                }
+                ExternalCommand::run(&path, &cmd, env, &self.jobs)
             }
         }
     }
 }
-
@@ -1,95 +1,35 @@
-use std::fs::{File, OpenOptions};
-use std::os::unix::io::{AsRawFd};
-use std::io;
 use crate::ast::{AstNode, RedirectKind};
-use crate::executor::{ExecStatus, ExecError, Executor};
+use crate::environment::Environment;
+use crate::executor::{ExecStatus, Executor, FdTable};
 
 pub struct RedirectHandler;
 
 impl RedirectHandler {
-    // pub fn new() -> Self {
-    //     RedirectHandler
-    // }
-    //
     pub fn handle_redirect(
-        // &self,
         node: &AstNode,
         kind: &RedirectKind,
         file: &str,
+        span: Option<(usize, usize)>,
         executor: &mut dyn Executor,
-        env: &mut crate::environment::Environment,
+        env: &mut Environment,
     ) -> ExecStatus {
-        // 1. Save the file descriptor (so it can be restored later)
-        // 2. Open the file and replace the appropriate FD with dup2
-        // 3. Execute the node (recursively call executor.exec)
-        // 4. Restore the FD
-
-        use RedirectKind::*;
-        let result = match kind {
-            In => {
-                let f = File::open(file);
-                match f {
-                    Ok(f) => {
-                        let fd = f.as_raw_fd();
-                        let saved = unsafe { libc::dup(0) };
-                        if unsafe { libc::dup2(fd, 0) } == -1 {
-                            return Err(ExecError::Io(io::Error::last_os_error()));
-                        }
-                        // Explicitly forget the File so the fd is not closed
-                        std::mem::forget(f);
-                        let res = executor.exec(node, env);
-                        unsafe { libc::dup2(saved, 0); libc::close(saved); }
-                        res
-                    }
-                    Err(e) => Err(ExecError::Io(e)),
-                }
-            }
-            Out => {
-                let f = File::create(file);
-                match f {
-                    Ok(f) => {
-                        let fd = f.as_raw_fd();
-                        let saved = unsafe { libc::dup(1) };
-                        if unsafe { libc::dup2(fd, 1) } == -1 {
-                            return Err(ExecError::Io(io::Error::last_os_error()));
-                        }
-                        std::mem::forget(f);
-                        let res = executor.exec(node, env);
-                        unsafe { libc::dup2(saved, 1); libc::close(saved); }
-                        res
-                    }
-                    Err(e) => Err(ExecError::Io(e)),
-                }
-            }
-            Append => {
-                let f = OpenOptions::new().write(true).append(true).create(true).open(file);
-                match f {
-                    Ok(f) => {
-                        let fd = f.as_raw_fd();
-                        let saved = unsafe { libc::dup(1) };
-                        if unsafe { libc::dup2(fd, 1) } == -1 {
-                            return Err(ExecError::Io(io::Error::last_os_error()));
-                        }
-                        std::mem::forget(f);
-                        let res = executor.exec(node, env);
-                        unsafe { libc::dup2(saved, 1); libc::close(saved); }
-                        res
-                    }
-                    Err(e) => Err(ExecError::Io(e)),
-                }
-            }
-        };
-        result
+        // 1. Apply the redirect (the table saves the replaced FDs)
+        // 2. Execute the node (recursively call executor.exec)
+        // 3. Restore the FDs when the table is dropped, also when something failed
+        let mut fds = FdTable::new();
+        fds.apply(kind, file, span, env, executor)?;
+        executor.exec(node, env)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::fs::{File, read_to_string, remove_file};
-    use std::io::Write;
     use super::*;
-    use crate::ast::{AstNode, CommandNode, CommandKind, RedirectKind};
+    use crate::ast::{AstNode, CommandKind, CommandNode, RedirectKind};
     use crate::environment::Environment;
+    use crate::executor::ExecError;
+    use std::fs::{File, remove_file};
+    use std::io::Write;
 
     #[test]
     fn test_redirect_out_creates_file() {
@@ -102,14 +42,16 @@ mod tests {
             name: "echo".to_string(),
             args: vec!["hello".to_string()],
             kind: CommandKind::Simple,
+            spans: Vec::new(),
         };
         let cmd_node = AstNode::Command(cmd.clone());
 
         // 3. Prepare the redirect node
         let _redirect_node = AstNode::Redirect {
             node: Box::new(cmd_node.clone()),
-            kind: RedirectKind::Out,
+            kind: RedirectKind::Out(1),
             file: file_name.to_string(),
+            span: None,
         };
 
         // 4. Prepare mock Executor, Environment, and RedirectHandler
@@ -117,19 +59,18 @@ mod tests {
         let mut env = Environment::new();
         // let handler = RedirectHandler::new();
 
-
         // 5. execute by handle_redirect
         let res = RedirectHandler::handle_redirect(
             &AstNode::Command(cmd.clone()),
-            &RedirectKind::Out,
+            &RedirectKind::Out(1),
             file_name,
+            None,
             &mut mock_executor,
             &mut env,
         );
         assert!(res.is_ok());
 
         // 6. Confirm that the file has been created
-        let content = read_to_string(file_name).unwrap_or_default();
         // * Since this is a mock Executor, there is no real output, but the file itself should be generated
         assert!(std::path::Path::new(file_name).exists());
 
@@ -147,13 +88,15 @@ mod tests {
             name: "cat".to_string(),
             args: vec![],
             kind: CommandKind::Simple,
+            spans: Vec::new(),
         };
         let cmd_node = AstNode::Command(cmd.clone());
 
         let _redirect_node = AstNode::Redirect {
             node: Box::new(cmd_node.clone()),
-            kind: RedirectKind::In,
+            kind: RedirectKind::In(0),
             file: file_name.to_string(),
+            span: None,
         };
 
         let mut mock_executor = crate::executor::tests::MockExecutor::new();
@@ -162,8 +105,9 @@ mod tests {
 
         let res = RedirectHandler::handle_redirect(
             &AstNode::Command(cmd.clone()),
-            &RedirectKind::In,
+            &RedirectKind::In(0),
             file_name,
+            None,
             &mut mock_executor,
             &mut env,
         );
@@ -171,5 +115,40 @@ mod tests {
 
         let _ = remove_file(file_name);
     }
-}
 
+    #[test]
+    fn test_noclobber_refuses_existing_file() {
+        let file_name = "test_redirect_noclobber.txt";
+        File::create(file_name).unwrap();
+        let cmd = AstNode::Command(CommandNode {
+            name: "echo".to_string(),
+            args: vec![],
+            kind: CommandKind::Simple,
+            spans: Vec::new(),
+        });
+        let mut mock_executor = crate::executor::tests::MockExecutor::new();
+        let mut env = Environment::new();
+        env.set_option("noclobber", true);
+
+        let res = RedirectHandler::handle_redirect(
+            &cmd,
+            &RedirectKind::Out(1),
+            file_name,
+            None,
+            &mut mock_executor,
+            &mut env,
+        );
+        assert!(matches!(res, Err(ExecError::RedirectError(_))));
+        let res = RedirectHandler::handle_redirect(
+            &cmd,
+            &RedirectKind::Clobber(1),
+            file_name,
+            None,
+            &mut mock_executor,
+            &mut env,
+        );
+        assert!(res.is_ok());
+
+        let _ = remove_file(file_name);
+    }
+}
@@ -0,0 +1,228 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+// Whether this process manages process groups and the terminal.
+// Only the interactive shell itself does; forked children turn it off.
+static JOB_CONTROL: AtomicBool = AtomicBool::new(false);
+
+// Set by Ctrl+C (or a foreground child killed by it) until the next command line
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Signals the interactive shell ignores; children get the default disposition back
+const IGNORED_SIGNALS: [libc::c_int; 4] =
+    [libc::SIGQUIT, libc::SIGTSTP, libc::SIGTTIN, libc::SIGTTOU];
+
+// Signals with a `trap` command (bit N = signal N), and those caught but not yet handled
+static CAUGHT: AtomicU64 = AtomicU64::new(0);
+static PENDING: AtomicU64 = AtomicU64::new(0);
+
+// Set in forked children, which do not inherit the shell's traps
+static SUBSHELL: AtomicBool = AtomicBool::new(false);
+
+// Signals that `trap` accepts, by name without the SIG prefix
+const SIGNAL_NAMES: [(&str, libc::c_int); 18] = [
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ABRT", libc::SIGABRT),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("WINCH", libc::SIGWINCH),
+    ("SYS", libc::SIGSYS),
+];
+
+extern "C" fn on_sigint(_sig: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn on_trapped(sig: libc::c_int) {
+    PENDING.fetch_or(1 << sig, Ordering::SeqCst);
+}
+
+// Install `handler` without SA_RESTART, so that blocking reads return early
+fn install(sig: libc::c_int, handler: extern "C" fn(libc::c_int)) {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as *const () as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(sig, &action, std::ptr::null_mut());
+    }
+}
+
+pub struct SignalHandler;
+
+impl SignalHandler {
+    // Enable job control when running on a terminal: the shell moves into its own
+    // process group, takes the terminal and ignores Ctrl+Z and Ctrl+\ itself.
+    // Ctrl+C only sets the interrupt flag; since SA_RESTART is not used it also
+    // breaks a blocking read of the prompt line.
+    pub fn handle_signals() {
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+            return;
+        }
+        unsafe {
+            // Started in the background (e.g. `tiny-shell &`): wait until we are in the foreground
+            loop {
+                let pgrp = libc::getpgrp();
+                if libc::tcgetpgrp(libc::STDIN_FILENO) == pgrp {
+                    break;
+                }
+                libc::kill(-pgrp, libc::SIGTTIN);
+            }
+            for sig in IGNORED_SIGNALS {
+                libc::signal(sig, libc::SIG_IGN);
+            }
+            install(libc::SIGINT, on_sigint);
+            // Fails harmlessly when the shell already leads its session
+            libc::setpgid(0, 0);
+            libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+        }
+        JOB_CONTROL.store(true, Ordering::SeqCst);
+    }
+
+    pub fn job_control_enabled() -> bool {
+        JOB_CONTROL.load(Ordering::SeqCst)
+    }
+
+    // Run in a freshly forked child (or right before exec). The child joins `pgid`
+    // (0 starts a new group led by itself) and takes the terminal when in the foreground.
+    // Trapped signals get their default disposition back.
+    // Only async-signal-safe calls are allowed here.
+    pub fn setup_child(pgid: libc::pid_t, foreground: bool) {
+        SUBSHELL.store(true, Ordering::SeqCst);
+        PENDING.store(0, Ordering::SeqCst);
+        let caught = CAUGHT.swap(0, Ordering::SeqCst);
+        for sig in 1..64 {
+            if caught & (1 << sig) != 0 {
+                unsafe { libc::signal(sig, libc::SIG_DFL) };
+            }
+        }
+
+        if !JOB_CONTROL.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        unsafe {
+            libc::setpgid(0, pgid);
+            if foreground {
+                libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+            }
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            for sig in IGNORED_SIGNALS {
+                libc::signal(sig, libc::SIG_DFL);
+            }
+        }
+    }
+
+    // For a command substitution child, which stays in the shell's process group
+    // (the one owning the terminal). Ctrl+Z stays ignored there: the shell is blocked
+    // reading the child's output and could never resume it.
+    pub fn setup_substitution_child() {
+        let job_control = Self::job_control_enabled();
+        Self::setup_child(unsafe { libc::getpgrp() }, false);
+        if job_control {
+            unsafe { libc::signal(libc::SIGTSTP, libc::SIG_IGN) };
+        }
+    }
+
+    pub fn interrupted() -> bool {
+        INTERRUPTED.load(Ordering::SeqCst)
+    }
+
+    pub fn interrupt() {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+
+    // Returns whether an interrupt was pending
+    pub fn clear_interrupt() -> bool {
+        INTERRUPTED.swap(false, Ordering::SeqCst)
+    }
+
+    pub fn in_subshell() -> bool {
+        SUBSHELL.load(Ordering::SeqCst)
+    }
+
+    // Accepts `INT`, `SIGINT` or `2`
+    pub fn signal_number(name: &str) -> Option<libc::c_int> {
+        if let Ok(num) = name.parse::<libc::c_int>() {
+            return SIGNAL_NAMES
+                .iter()
+                .find(|(_, n)| *n == num)
+                .map(|(_, n)| *n);
+        }
+        let name = name.to_ascii_uppercase();
+        let name = name.strip_prefix("SIG").unwrap_or(&name);
+        SIGNAL_NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, n)| *n)
+    }
+
+    pub fn signal_name(sig: libc::c_int) -> Option<&'static str> {
+        SIGNAL_NAMES
+            .iter()
+            .find(|(_, n)| *n == sig)
+            .map(|(n, _)| *n)
+    }
+
+    // SIGKILL and SIGSTOP can be neither caught nor ignored
+    pub fn can_trap(sig: libc::c_int) -> bool {
+        !matches!(sig, libc::SIGKILL | libc::SIGSTOP)
+    }
+
+    // Record `sig` for `take_pending` instead of its usual effect
+    pub fn catch(sig: libc::c_int) {
+        CAUGHT.fetch_or(1 << sig, Ordering::SeqCst);
+        install(sig, on_trapped);
+    }
+
+    pub fn ignore(sig: libc::c_int) {
+        CAUGHT.fetch_and(!(1 << sig), Ordering::SeqCst);
+        unsafe { libc::signal(sig, libc::SIG_IGN) };
+    }
+
+    // Back to what the shell does without a trap
+    pub fn restore(sig: libc::c_int) {
+        CAUGHT.fetch_and(!(1 << sig), Ordering::SeqCst);
+        if Self::job_control_enabled() && sig == libc::SIGINT {
+            install(sig, on_sigint);
+        } else if Self::job_control_enabled() && IGNORED_SIGNALS.contains(&sig) {
+            unsafe { libc::signal(sig, libc::SIG_IGN) };
+        } else {
+            unsafe { libc::signal(sig, libc::SIG_DFL) };
+        }
+    }
+
+    // Trapped signals received since the last call, in signal number order
+    pub fn take_pending() -> Vec<libc::c_int> {
+        let pending = PENDING.swap(0, Ordering::SeqCst);
+        (1..64).filter(|sig| pending & (1 << sig) != 0).collect()
+    }
+
+    // Parent side of `setup_child`, so that the group exists whichever process runs first
+    pub fn assign_group(pid: libc::pid_t, pgid: libc::pid_t) {
+        if Self::job_control_enabled() {
+            unsafe { libc::setpgid(pid, pgid) };
+        }
+    }
+
+    pub fn give_terminal(pgid: libc::pid_t) {
+        if Self::job_control_enabled() {
+            unsafe { libc::tcsetpgrp(libc::STDIN_FILENO, pgid) };
+        }
+    }
+
+    pub fn reclaim_terminal() {
+        if Self::job_control_enabled() {
+            unsafe { libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp()) };
+        }
+    }
+}
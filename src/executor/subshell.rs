@@ -0,0 +1,318 @@
+use crate::executor::command::exit_code;
+use crate::executor::job::JobTable;
+use crate::executor::signal::SignalHandler;
+use crate::executor::{ExecError, ExecOutcome, ExecStatus};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::FromRawFd;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+pub struct SubshellHandler;
+
+impl SubshellHandler {
+    // Run `exec_fn` in a forked child and return its exit status.
+    // The child works on its own copy of the environment, cwd and file descriptors,
+    // so nothing it changes is visible to the shell.
+    pub fn exec_subshell_generic<F>(
+        jobs: &RefCell<JobTable>,
+        command: &str,
+        exec_fn: F,
+    ) -> ExecStatus
+    where
+        F: FnOnce() -> ExecStatus,
+    {
+        let pid = Self::spawn_subshell_generic(true, exec_fn)?;
+        let statuses = jobs.borrow_mut().wait_foreground(&[pid], command)?;
+        Ok(ExecOutcome::Code(statuses[0]))
+    }
+
+    // Fork a child running `exec_fn` without waiting for it; the caller reaps it. Under job
+    // control the child leads its own process group, and `foreground` decides whether that
+    // group takes the terminal (`exec_subshell_generic`) or leaves it to the shell (`cmd &`).
+    pub fn spawn_subshell_generic<F>(foreground: bool, exec_fn: F) -> Result<libc::pid_t, ExecError>
+    where
+        F: FnOnce() -> ExecStatus,
+    {
+        // Avoid flushing the same buffered output from both processes
+        let _ = std::io::stdout().flush();
+
+        let pid = unsafe { libc::fork() };
+        if pid < 0 {
+            return Err(ExecError::Io(std::io::Error::last_os_error()));
+        }
+
+        if pid == 0 {
+            // Child process: never return into the caller's control flow
+            SignalHandler::setup_child(0, foreground);
+            exit_child(exec_fn());
+        }
+
+        SignalHandler::assign_group(pid, pid);
+        Ok(pid)
+    }
+
+    // Start `exec_fn` as the background job `command` (for `cmd &`): add it to the job
+    // table and print `[n] pid` when interactive. Returns the pid for `$!`.
+    pub fn spawn_background<F>(
+        jobs: &RefCell<JobTable>,
+        command: &str,
+        exec_fn: F,
+    ) -> Result<libc::pid_t, ExecError>
+    where
+        F: FnOnce() -> ExecStatus,
+    {
+        let pid = Self::spawn_subshell_generic(false, exec_fn)?;
+        let id = jobs.borrow_mut().add(pid, command);
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } == 1 {
+            eprintln!("[{}] {}", id, pid);
+        }
+        Ok(pid)
+    }
+
+    // Run `exec_fn` in a forked child with stdout connected to a pipe (for `$(...)`)
+    // and return everything it wrote together with its exit status.
+    pub fn capture_output_generic<F>(exec_fn: F) -> Result<(String, i32), ExecError>
+    where
+        F: FnOnce() -> ExecStatus,
+    {
+        let _ = std::io::stdout().flush();
+
+        let mut pipefds = [0; 2];
+        if unsafe { libc::pipe(pipefds.as_mut_ptr()) } == -1 {
+            return Err(ExecError::Io(std::io::Error::last_os_error()));
+        }
+        let pid = unsafe { libc::fork() };
+        if pid < 0 {
+            let err = std::io::Error::last_os_error();
+            unsafe {
+                libc::close(pipefds[0]);
+                libc::close(pipefds[1]);
+            }
+            return Err(ExecError::Io(err));
+        }
+
+        if pid == 0 {
+            SignalHandler::setup_substitution_child();
+            unsafe {
+                libc::close(pipefds[0]);
+                libc::dup2(pipefds[1], 1);
+                libc::close(pipefds[1]);
+            }
+            exit_child(exec_fn());
+        }
+
+        unsafe { libc::close(pipefds[1]) };
+        let mut output = Vec::new();
+        let read_result = unsafe { File::from_raw_fd(pipefds[0]) }.read_to_end(&mut output);
+
+        let mut status = 0;
+        while unsafe { libc::waitpid(pid, &mut status, 0) } < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                return Err(ExecError::Io(err));
+            }
+        }
+        read_result.map_err(ExecError::Io)?;
+
+        if libc::WIFSIGNALED(status) && libc::WTERMSIG(status) == libc::SIGINT {
+            SignalHandler::interrupt();
+        }
+        if SignalHandler::interrupted() {
+            return Err(ExecError::Interrupted);
+        }
+        let code = exit_code(ExitStatus::from_raw(status));
+        Ok((String::from_utf8_lossy(&output).into_owned(), code))
+    }
+
+    // Run `exec_fn` in a forked child whose stdout (stdin with `child_reads`) is connected to
+    // a pipe, for `<(...)` and `>(...)`. Returns the child's pid and the shell's end of the
+    // pipe, which is close-on-exec (see `ProcessSubstitutions::inherit`).
+    pub fn spawn_process_substitution<F>(
+        child_reads: bool,
+        exec_fn: F,
+    ) -> Result<(libc::pid_t, i32), ExecError>
+    where
+        F: FnOnce() -> ExecStatus,
+    {
+        let _ = std::io::stdout().flush();
+
+        let mut pipefds = [0; 2];
+        if unsafe { libc::pipe(pipefds.as_mut_ptr()) } == -1 {
+            return Err(ExecError::Io(std::io::Error::last_os_error()));
+        }
+        let (child_end, shell_end) = if child_reads {
+            (pipefds[0], pipefds[1])
+        } else {
+            (pipefds[1], pipefds[0])
+        };
+        unsafe { libc::fcntl(shell_end, libc::F_SETFD, libc::FD_CLOEXEC) };
+        let pid = unsafe { libc::fork() };
+        if pid < 0 {
+            let err = std::io::Error::last_os_error();
+            unsafe {
+                libc::close(pipefds[0]);
+                libc::close(pipefds[1]);
+            }
+            return Err(ExecError::Io(err));
+        }
+
+        if pid == 0 {
+            SignalHandler::setup_substitution_child();
+            unsafe {
+                libc::close(shell_end);
+                libc::dup2(child_end, if child_reads { 0 } else { 1 });
+                libc::close(child_end);
+            }
+            exit_child(exec_fn());
+        }
+
+        unsafe { libc::close(child_end) };
+        Ok((pid, shell_end))
+    }
+}
+
+// End a forked child with the status of what it ran
+pub(crate) fn exit_child(status: ExecStatus) -> ! {
+    let code = match status {
+        Ok(ExecOutcome::Code(code)) | Ok(ExecOutcome::Exit(code)) => code,
+        Err(ExecError::Interrupted) => 128 + libc::SIGINT,
+        Err(e) => {
+            eprintln!("tiny-shell: {}", e);
+            1
+        }
+    };
+    let _ = std::io::stdout().flush();
+    std::process::exit(code);
+}
+
+// The children of a command's process substitutions and the shell's ends of their pipes.
+// Dropping it (once the command is done) closes every end first, so that each `>(...)`
+// sees the end of its input, and then reaps the children.
+#[derive(Default)]
+pub struct ProcessSubstitutions(Vec<(libc::pid_t, i32)>);
+
+impl ProcessSubstitutions {
+    // Start `exec_fn` as in `SubshellHandler::spawn_process_substitution` and return the fd
+    pub fn spawn<F>(&mut self, child_reads: bool, exec_fn: F) -> Result<i32, ExecError>
+    where
+        F: FnOnce() -> ExecStatus,
+    {
+        let (pid, fd) = SubshellHandler::spawn_process_substitution(child_reads, exec_fn)?;
+        self.0.push((pid, fd));
+        Ok(fd)
+    }
+
+    // Let the next program executed open the `/dev/fd/N` paths. Called right before the
+    // command that got them starts; the fds are closed once it is done.
+    pub fn inherit(&self) {
+        for &(_, fd) in &self.0 {
+            unsafe { libc::fcntl(fd, libc::F_SETFD, 0) };
+        }
+    }
+
+    pub fn append(&mut self, other: &mut ProcessSubstitutions) {
+        self.0.append(&mut other.0);
+    }
+}
+
+impl Drop for ProcessSubstitutions {
+    fn drop(&mut self) {
+        for &(_, fd) in &self.0 {
+            unsafe { libc::close(fd) };
+        }
+        for &(pid, _) in &self.0 {
+            let mut status = 0;
+            while unsafe { libc::waitpid(pid, &mut status, 0) } < 0
+                && std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted
+            {
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run<F: FnOnce() -> ExecStatus>(exec_fn: F) -> ExecStatus {
+        SubshellHandler::exec_subshell_generic(&RefCell::new(JobTable::new()), "test", exec_fn)
+    }
+
+    #[test]
+    fn test_subshell_returns_child_status() {
+        let result = run(|| Ok(ExecOutcome::Code(3)));
+        assert_eq!(result.unwrap(), ExecOutcome::Code(3));
+    }
+
+    #[test]
+    fn test_subshell_exit_does_not_leave_shell() {
+        // `(exit 4)` only terminates the subshell
+        let result = run(|| Ok(ExecOutcome::Exit(4)));
+        assert_eq!(result.unwrap(), ExecOutcome::Code(4));
+    }
+
+    #[test]
+    fn test_subshell_error_becomes_status() {
+        let result = run(|| Err(ExecError::Custom("fail".into())));
+        assert_eq!(result.unwrap(), ExecOutcome::Code(1));
+    }
+
+    #[test]
+    fn test_subshell_cwd_is_isolated() {
+        let before = std::env::current_dir().unwrap();
+        let result = run(|| {
+            std::env::set_current_dir("/").map_err(ExecError::Io)?;
+            Ok(ExecOutcome::Code(0))
+        });
+        assert_eq!(result.unwrap(), ExecOutcome::Code(0));
+        assert_eq!(std::env::current_dir().unwrap(), before);
+    }
+
+    #[test]
+    fn test_capture_output() {
+        let (output, code) = SubshellHandler::capture_output_generic(|| {
+            // Not `println!`, which the test harness captures in memory
+            writeln!(std::io::stdout(), "captured").map_err(ExecError::Io)?;
+            Ok(ExecOutcome::Code(2))
+        })
+        .unwrap();
+        assert_eq!(output, "captured\n");
+        assert_eq!(code, 2);
+    }
+
+    #[test]
+    fn test_process_substitution_pipes() {
+        let mut processes = ProcessSubstitutions::default();
+        let fd = processes
+            .spawn(false, || {
+                writeln!(std::io::stdout(), "substituted").map_err(ExecError::Io)?;
+                Ok(ExecOutcome::Code(0))
+            })
+            .unwrap();
+        // Inheritable only once the command using it is about to start
+        assert_eq!(unsafe { libc::fcntl(fd, libc::F_GETFD) }, libc::FD_CLOEXEC);
+        processes.inherit();
+        assert_eq!(unsafe { libc::fcntl(fd, libc::F_GETFD) }, 0);
+        let mut output = String::new();
+        File::open(format!("/dev/fd/{}", fd))
+            .unwrap()
+            .read_to_string(&mut output)
+            .unwrap();
+        assert_eq!(output, "substituted\n");
+
+        // The child reads until the shell's end is closed, when the table is dropped
+        let fd = processes
+            .spawn(true, || {
+                let mut input = String::new();
+                std::io::stdin()
+                    .read_to_string(&mut input)
+                    .map_err(ExecError::Io)?;
+                Ok(ExecOutcome::Code(0))
+            })
+            .unwrap();
+        writeln!(File::create(format!("/dev/fd/{}", fd)).unwrap(), "input").unwrap();
+        drop(processes);
+    }
+}
@@ -1,6 +1,6 @@
-use crate::ast::{AstNode};
-use crate::executor::{Executor, ExecStatus, ExecOutcome, ExecError};
+use crate::ast::AstNode;
 use crate::environment::Environment;
+use crate::executor::{ExecError, ExecOutcome, ExecStatus, Executor};
 
 pub struct MockExecutor {
     pub last_cmd: Option<String>,
@@ -9,7 +9,10 @@ pub struct MockExecutor {
 
 impl MockExecutor {
     pub fn new() -> Self {
-        Self { last_cmd: None, last_args: Vec::new() }
+        Self {
+            last_cmd: None,
+            last_args: Vec::new(),
+        }
     }
 }
 
@@ -23,5 +26,8 @@ impl Executor for MockExecutor {
             Err(ExecError::Custom("Mock: Not CommandNode".into()))
         }
     }
-}
 
+    fn child(&self) -> Box<dyn Executor + '_> {
+        Box::new(MockExecutor::new())
+    }
+}
@@ -0,0 +1,144 @@
+use crate::environment::Environment;
+use crate::executor::signal::SignalHandler;
+use crate::executor::{ExecError, ExecOutcome, Executor};
+use crate::lexer::Lexer;
+use crate::parser::{DefaultParser, Parser};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set while a trap command runs, so that it does not trigger traps itself
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+pub struct TrapHandler;
+
+impl TrapHandler {
+    // Run the traps of signals received since the last safe point.
+    // Returns the exit code if a trap command ran `exit`.
+    pub fn run_pending(
+        executor: &mut dyn Executor,
+        env: &mut Environment,
+    ) -> Result<Option<i32>, ExecError> {
+        if RUNNING.load(Ordering::SeqCst) {
+            return Ok(None);
+        }
+        for sig in SignalHandler::take_pending() {
+            if let Some(name) = SignalHandler::signal_name(sig)
+                && let Some(code) = Self::run(executor, name, env)?
+            {
+                return Ok(Some(code));
+            }
+        }
+        Ok(None)
+    }
+
+    // Called after a command failed outside of a condition
+    pub fn run_err(
+        executor: &mut dyn Executor,
+        env: &mut Environment,
+    ) -> Result<Option<i32>, ExecError> {
+        Self::run(executor, "ERR", env)
+    }
+
+    // Called once when the shell exits
+    pub fn run_exit(
+        executor: &mut dyn Executor,
+        env: &mut Environment,
+    ) -> Result<Option<i32>, ExecError> {
+        let result = Self::run(executor, "EXIT", env);
+        env.set_trap("EXIT", None);
+        result
+    }
+
+    // Parse and execute the command registered for `name`, keeping `$?` intact
+    fn run(
+        executor: &mut dyn Executor,
+        name: &str,
+        env: &mut Environment,
+    ) -> Result<Option<i32>, ExecError> {
+        if SignalHandler::in_subshell() || RUNNING.load(Ordering::SeqCst) {
+            return Ok(None);
+        }
+        let command = match env.trap(name) {
+            Some(command) if !command.is_empty() => command.to_string(),
+            _ => return Ok(None),
+        };
+
+        let tokens = Lexer::new(&command)
+            .tokenize_all()
+            .map_err(|e| ExecError::Custom(format!("trap: {}", e)))?;
+        let ast = DefaultParser::new(&tokens)
+            .parse()
+            .map_err(|e| ExecError::Custom(format!("trap: {}", e)))?;
+
+        let status = env.last_status();
+        RUNNING.store(true, Ordering::SeqCst);
+        let result = executor.exec(&ast, env);
+        RUNNING.store(false, Ordering::SeqCst);
+        env.set_last_status(status);
+
+        match result? {
+            ExecOutcome::Exit(code) => Ok(Some(code)),
+            ExecOutcome::Code(_) => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::builtin::BuiltinManager;
+    use crate::executor::tests::MockExecutor;
+
+    #[test]
+    fn test_err_trap_runs_command_and_keeps_status() {
+        let mut executor = MockExecutor::new();
+        let mut env = Environment::new();
+        env.set_trap("ERR", Some("echo failed"));
+        env.set_last_status(3);
+
+        assert_eq!(TrapHandler::run_err(&mut executor, &mut env).unwrap(), None);
+        assert_eq!(executor.last_cmd.as_deref(), Some("echo"));
+        assert_eq!(executor.last_args, vec!["failed".to_string()]);
+        assert_eq!(env.last_status(), 3);
+    }
+
+    #[test]
+    fn test_exit_trap_runs_once() {
+        let mut executor = MockExecutor::new();
+        let mut env = Environment::new();
+        env.set_trap("EXIT", Some("cleanup"));
+
+        TrapHandler::run_exit(&mut executor, &mut env).unwrap();
+        assert_eq!(executor.last_cmd.as_deref(), Some("cleanup"));
+        assert_eq!(env.trap("EXIT"), None);
+    }
+
+    #[test]
+    fn test_ignored_trap_runs_nothing() {
+        let mut executor = MockExecutor::new();
+        let mut env = Environment::new();
+        env.set_trap("ERR", Some(""));
+
+        assert_eq!(TrapHandler::run_err(&mut executor, &mut env).unwrap(), None);
+        assert_eq!(executor.last_cmd, None);
+    }
+
+    #[test]
+    fn test_kill_and_stop_cannot_be_trapped() {
+        let builtins = BuiltinManager::new();
+        let mut env = Environment::new();
+        for spec in ["KILL", "SIGSTOP", "9"] {
+            let args = vec!["echo caught".to_string(), spec.to_string()];
+            assert!(matches!(
+                builtins.execute("trap", &args, &mut env),
+                Ok(ExecOutcome::Code(1))
+            ));
+            let args = vec![String::new(), spec.to_string()];
+            assert!(matches!(
+                builtins.execute("trap", &args, &mut env),
+                Ok(ExecOutcome::Code(1))
+            ));
+        }
+        assert_eq!(env.trap("KILL"), None);
+        assert_eq!(env.trap("STOP"), None);
+    }
+}
@@ -0,0 +1,543 @@
+use super::ExpandError;
+
+// Variables seen by an arithmetic expression. Unset and empty variables are 0; other
+// values are evaluated as expressions themselves.
+pub trait ArithVars {
+    fn get(&self, name: &str) -> Option<String>;
+    fn set(&mut self, name: &str, value: i64);
+}
+
+// How deeply variable values may refer to further expressions (`a=b`, `b=a`)
+const MAX_DEPTH: usize = 64;
+
+// Evaluate `expr` with 64-bit wrapping integers, like `$((...))` in bash:
+// - `+ - * / % **`, `<< >> & | ^ ~`, comparisons, `! && ||`, `?:` and `,`
+// - assignments (`=`, `+=`, `<<=`, ...) and `++`/`--`, before or after a name
+// - literals in decimal, hex (`0x1f`), octal (`017`) and `BASE#DIGITS` (`2#101`)
+pub fn evaluate(expr: &str, vars: &mut dyn ArithVars) -> Result<i64, ExpandError> {
+    Evaluator { vars, depth: 0 }.evaluate(expr)
+}
+
+#[derive(Debug)]
+enum Expr {
+    Num(i64),
+    Var(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+    // `op` is the binary operator of a compound assignment (`+` for `+=`)
+    Assign {
+        name: String,
+        op: Option<&'static str>,
+        value: Box<Expr>,
+    },
+    IncDec {
+        name: String,
+        delta: i64,
+        prefix: bool,
+    },
+}
+
+// Longest first, so that e.g. `<<=` is not read as `<<` and `=`
+const OPERATORS: [&str; 39] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "=", "!", "~", "&", "^",
+    "|", "?", ":", ",", "(", ")",
+];
+
+fn binary_precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | ">" | "<=" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        "**" => 11,
+        _ => return None,
+    })
+}
+
+struct ExprParser<'s> {
+    chars: Vec<char>,
+    pos: usize,
+    source: &'s str,
+}
+
+impl<'s> ExprParser<'s> {
+    fn error(&self, message: &str) -> ExpandError {
+        let rest: String = self.chars[self.pos.min(self.chars.len())..]
+            .iter()
+            .collect();
+        let message = if rest.trim().is_empty() {
+            message.to_string()
+        } else {
+            format!("{} (error token is \"{}\")", message, rest.trim())
+        };
+        ExpandError::ArithmeticError {
+            expr: self.source.trim().to_string(),
+            message,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    // The operator at the current position, without consuming it
+    fn peek_operator(&mut self) -> Option<&'static str> {
+        self.skip_whitespace();
+        OPERATORS.into_iter().find(|op| {
+            op.chars()
+                .enumerate()
+                .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+        })
+    }
+
+    fn consume(&mut self, op: &str) -> bool {
+        if self.peek_operator() == Some(op) {
+            self.pos += op.len();
+            return true;
+        }
+        false
+    }
+
+    fn name(&mut self) -> Option<String> {
+        self.skip_whitespace();
+        let start = self.pos;
+        if !self
+            .chars
+            .get(start)
+            .is_some_and(|c| c.is_ascii_alphabetic() || *c == '_')
+        {
+            return None;
+        }
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_')
+        {
+            self.pos += 1;
+        }
+        Some(self.chars[start..self.pos].iter().collect())
+    }
+
+    // expr , expr
+    fn parse_comma(&mut self) -> Result<Expr, ExpandError> {
+        let mut left = self.parse_assign()?;
+        while self.consume(",") {
+            left = Expr::Binary(",", Box::new(left), Box::new(self.parse_assign()?));
+        }
+        Ok(left)
+    }
+
+    // name = expr, name += expr, ... (right associative)
+    fn parse_assign(&mut self) -> Result<Expr, ExpandError> {
+        let start = self.pos;
+        if let Some(name) = self.name() {
+            let op = match self.peek_operator() {
+                Some("=") => Some(None),
+                Some(op)
+                    if op.len() >= 2
+                        && op.ends_with('=')
+                        && !matches!(op, "==" | "!=" | "<=" | ">=") =>
+                {
+                    Some(Some(&op[..op.len() - 1]))
+                }
+                _ => None,
+            };
+            if let Some(op) = op {
+                self.pos += op.map_or(1, |op| op.len() + 1);
+                let value = Box::new(self.parse_assign()?);
+                return Ok(Expr::Assign { name, op, value });
+            }
+        }
+        self.pos = start;
+        self.parse_conditional()
+    }
+
+    // cond ? expr : expr
+    fn parse_conditional(&mut self) -> Result<Expr, ExpandError> {
+        let cond = self.parse_binary(1)?;
+        if !self.consume("?") {
+            return Ok(cond);
+        }
+        let then = self.parse_assign()?;
+        if !self.consume(":") {
+            return Err(self.error("`:' expected for conditional expression"));
+        }
+        let otherwise = self.parse_conditional()?;
+        Ok(Expr::Cond(
+            Box::new(cond),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    // Binary operators by precedence climbing; only `**` is right associative
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, ExpandError> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let op = match self.peek_operator() {
+                // `1--1` is `1 - -1`
+                Some("++") => "+",
+                Some("--") => "-",
+                Some(op) => op,
+                None => break,
+            };
+            let Some(precedence) = binary_precedence(op).filter(|p| *p >= min_precedence) else {
+                break;
+            };
+            self.pos += op.len();
+            let next = if op == "**" {
+                precedence
+            } else {
+                precedence + 1
+            };
+            let right = self.parse_binary(next)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ExpandError> {
+        match self.peek_operator() {
+            Some(op @ ("++" | "--")) => {
+                let start = self.pos;
+                self.pos += 2;
+                if let Some(name) = self.name() {
+                    let delta = if op == "++" { 1 } else { -1 };
+                    return Ok(Expr::IncDec {
+                        name,
+                        delta,
+                        prefix: true,
+                    });
+                }
+                // `--1` is `-(-1)`
+                self.pos = start + 1;
+                Ok(Expr::Unary(&op[..1], Box::new(self.parse_unary()?)))
+            }
+            Some(op @ ("-" | "+" | "!" | "~")) => {
+                self.pos += 1;
+                Ok(Expr::Unary(op, Box::new(self.parse_unary()?)))
+            }
+            _ => self.parse_postfix(),
+        }
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, ExpandError> {
+        let primary = self.parse_primary()?;
+        if let Expr::Var(name) = &primary {
+            let op = self.peek_operator();
+            if let Some(op @ ("++" | "--")) = op {
+                self.pos += 2;
+                let delta = if op == "++" { 1 } else { -1 };
+                return Ok(Expr::IncDec {
+                    name: name.clone(),
+                    delta,
+                    prefix: false,
+                });
+            }
+        }
+        Ok(primary)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ExpandError> {
+        self.skip_whitespace();
+        if self.consume("(") {
+            let inner = self.parse_comma()?;
+            if !self.consume(")") {
+                return Err(self.error("missing `)'"));
+            }
+            return Ok(inner);
+        }
+        if let Some(name) = self.name() {
+            return Ok(Expr::Var(name));
+        }
+        let start = self.pos;
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '#' | '@' | '_'))
+        {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(self.error("syntax error: operand expected"));
+        }
+        let literal: String = self.chars[start..self.pos].iter().collect();
+        match parse_number(&literal) {
+            Some(n) => Ok(Expr::Num(n)),
+            None => {
+                self.pos = start;
+                Err(self.error("value too great for base"))
+            }
+        }
+    }
+}
+
+// Decimal, 0x hex, 0 octal or BASE#DIGITS (bases 2 to 64: 0-9, a-z, A-Z, @, _)
+fn parse_number(literal: &str) -> Option<i64> {
+    let (base, digits) = if let Some((base, digits)) = literal.split_once('#') {
+        (
+            base.parse::<u32>().ok().filter(|b| (2..=64).contains(b))?,
+            digits,
+        )
+    } else if let Some(hex) = literal
+        .strip_prefix("0x")
+        .or_else(|| literal.strip_prefix("0X"))
+    {
+        (16, hex)
+    } else if literal.len() > 1 && literal.starts_with('0') {
+        (8, &literal[1..])
+    } else {
+        (10, literal)
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    digits.chars().try_fold(0i64, |acc, c| {
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return None,
+        };
+        (digit < base).then(|| acc.wrapping_mul(base as i64).wrapping_add(digit as i64))
+    })
+}
+
+struct Evaluator<'v> {
+    vars: &'v mut dyn ArithVars,
+    depth: usize,
+}
+
+impl<'v> Evaluator<'v> {
+    fn evaluate(&mut self, expr: &str) -> Result<i64, ExpandError> {
+        if expr.trim().is_empty() {
+            return Ok(0);
+        }
+        let mut parser = ExprParser {
+            chars: expr.chars().collect(),
+            pos: 0,
+            source: expr,
+        };
+        let tree = parser.parse_comma()?;
+        parser.skip_whitespace();
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("syntax error in expression"));
+        }
+        self.eval(&tree, expr)
+    }
+
+    fn variable(&mut self, name: &str, expr: &str) -> Result<i64, ExpandError> {
+        let value = self.vars.get(name).unwrap_or_default();
+        if let Some(n) = parse_number(value.trim()) {
+            return Ok(n);
+        }
+        if self.depth >= MAX_DEPTH {
+            return Err(ExpandError::ArithmeticError {
+                expr: expr.trim().to_string(),
+                message: "expression recursion level exceeded".to_string(),
+            });
+        }
+        self.depth += 1;
+        let result = self.evaluate(&value);
+        self.depth -= 1;
+        result
+    }
+
+    fn eval(&mut self, tree: &Expr, expr: &str) -> Result<i64, ExpandError> {
+        Ok(match tree {
+            Expr::Num(n) => *n,
+            Expr::Var(name) => self.variable(name, expr)?,
+            Expr::Unary(op, operand) => {
+                let value = self.eval(operand, expr)?;
+                match *op {
+                    "-" => value.wrapping_neg(),
+                    "!" => (value == 0) as i64,
+                    "~" => !value,
+                    _ => value,
+                }
+            }
+            // Only the operands that are needed are evaluated (and assign)
+            Expr::Binary("&&", left, right) => {
+                (self.eval(left, expr)? != 0 && self.eval(right, expr)? != 0) as i64
+            }
+            Expr::Binary("||", left, right) => {
+                (self.eval(left, expr)? != 0 || self.eval(right, expr)? != 0) as i64
+            }
+            Expr::Binary(",", left, right) => {
+                self.eval(left, expr)?;
+                self.eval(right, expr)?
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left, expr)?;
+                let right = self.eval(right, expr)?;
+                apply(op, left, right, expr)?
+            }
+            Expr::Cond(cond, then, otherwise) => {
+                if self.eval(cond, expr)? != 0 {
+                    self.eval(then, expr)?
+                } else {
+                    self.eval(otherwise, expr)?
+                }
+            }
+            Expr::Assign { name, op, value } => {
+                let value = match op {
+                    Some(op) => {
+                        let current = self.variable(name, expr)?;
+                        let operand = self.eval(value, expr)?;
+                        apply(op, current, operand, expr)?
+                    }
+                    None => self.eval(value, expr)?,
+                };
+                self.vars.set(name, value);
+                value
+            }
+            Expr::IncDec {
+                name,
+                delta,
+                prefix,
+            } => {
+                let current = self.variable(name, expr)?;
+                let updated = current.wrapping_add(*delta);
+                self.vars.set(name, updated);
+                if *prefix { updated } else { current }
+            }
+        })
+    }
+}
+
+fn apply(op: &str, left: i64, right: i64, expr: &str) -> Result<i64, ExpandError> {
+    Ok(match op {
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" | "%" if right == 0 => {
+            return Err(ExpandError::DivisionByZero(expr.trim().to_string()));
+        }
+        "/" => left.wrapping_div(right),
+        "%" => left.wrapping_rem(right),
+        "**" if right < 0 => {
+            return Err(ExpandError::ArithmeticError {
+                expr: expr.trim().to_string(),
+                message: "exponent less than 0".to_string(),
+            });
+        }
+        "**" => left.wrapping_pow(right.min(u32::MAX as i64) as u32),
+        "<<" => left.wrapping_shl(right as u32),
+        ">>" => left.wrapping_shr(right as u32),
+        "&" => left & right,
+        "|" => left | right,
+        "^" => left ^ right,
+        "==" => (left == right) as i64,
+        "!=" => (left != right) as i64,
+        "<" => (left < right) as i64,
+        ">" => (left > right) as i64,
+        "<=" => (left <= right) as i64,
+        ">=" => (left >= right) as i64,
+        _ => unreachable!("not a binary operator: {}", op),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    impl ArithVars for HashMap<String, String> {
+        fn get(&self, name: &str) -> Option<String> {
+            HashMap::get(self, name).cloned()
+        }
+        fn set(&mut self, name: &str, value: i64) {
+            self.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    fn eval(expr: &str) -> i64 {
+        evaluate(expr, &mut HashMap::new()).unwrap()
+    }
+
+    #[test]
+    fn test_operators_and_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7);
+        assert_eq!(eval("(1 + 2) * 3"), 9);
+        assert_eq!(eval("7 / 2 + 7 % 2"), 4);
+        assert_eq!(eval("2 ** 3 ** 2"), 512);
+        assert_eq!(eval("-2 ** 2"), 4);
+        assert_eq!(eval("1 << 4 | 3 & 1 ^ 2"), 19);
+        assert_eq!(eval("~0 + !5 + !0"), 0);
+        assert_eq!(eval("3 > 2 && 2 >= 2 || 0"), 1);
+        assert_eq!(eval("1 == 2 ? 10 : 1 != 2 ? 20 : 30"), 20);
+        assert_eq!(eval("1--1"), 2);
+        assert_eq!(eval("1, 2, 3"), 3);
+        assert_eq!(eval("  "), 0);
+        assert_eq!(
+            eval("0x1f + 010 + 2#101 + 36#z + 64#_"),
+            31 + 8 + 5 + 35 + 63
+        );
+        assert_eq!(eval("9223372036854775807 + 1"), i64::MIN);
+    }
+
+    #[test]
+    fn test_variables_and_assignment() {
+        let mut vars = HashMap::new();
+        vars.insert("x".to_string(), "5".to_string());
+        vars.insert("ref".to_string(), "x * 2".to_string());
+        assert_eq!(evaluate("x + unset + ref", &mut vars).unwrap(), 15);
+        assert_eq!(evaluate("y = x += 3", &mut vars).unwrap(), 8);
+        assert_eq!((vars["x"].as_str(), vars["y"].as_str()), ("8", "8"));
+        assert_eq!(evaluate("x++ + ++x", &mut vars).unwrap(), 18);
+        assert_eq!(evaluate("x <<= 1, x--", &mut vars).unwrap(), 20);
+        assert_eq!(vars["x"], "19");
+        // Short-circuited operands do not assign
+        assert_eq!(
+            evaluate("0 && (z = 1), 1 || (z = 2), 1 ? 3 : (z = 3)", &mut vars).unwrap(),
+            3
+        );
+        assert!(!vars.contains_key("z"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut vars = HashMap::new();
+        assert!(
+            matches!(evaluate(" 1 / (2 - 2)", &mut vars), Err(ExpandError::DivisionByZero(e)) if e == "1 / (2 - 2)")
+        );
+        assert!(matches!(
+            evaluate("5 % 0", &mut vars),
+            Err(ExpandError::DivisionByZero(_))
+        ));
+        assert!(matches!(
+            evaluate("1 +", &mut vars),
+            Err(ExpandError::ArithmeticError { .. })
+        ));
+        assert!(matches!(
+            evaluate("(1", &mut vars),
+            Err(ExpandError::ArithmeticError { .. })
+        ));
+        assert!(matches!(
+            evaluate("2 ** -1", &mut vars),
+            Err(ExpandError::ArithmeticError { .. })
+        ));
+        assert!(matches!(
+            evaluate("08", &mut vars),
+            Err(ExpandError::ArithmeticError { .. })
+        ));
+        vars.insert("a".to_string(), "a + 1".to_string());
+        assert!(matches!(
+            evaluate("a", &mut vars),
+            Err(ExpandError::ArithmeticError { .. })
+        ));
+    }
+}
@@ -0,0 +1,183 @@
+use crate::lexer::Word;
+
+// Brace expansion of a raw word: `file{1,2}.txt`, nested `a{b,c{d,e}}` and sequences such as
+// `{1..10..2}`, `{01..10}` and `{a..e}`. It comes before every other expansion. Quoted or
+// escaped braces, those of `${...}` and braces without a comma or sequence inside are
+// left as they are. Empty words an expansion yields (`{,x}`) are dropped.
+pub fn expand(word: &str) -> Vec<String> {
+    let words = expand_word(word);
+    if words == [word] {
+        return words;
+    }
+    words.into_iter().filter(|w| !w.is_empty()).collect()
+}
+
+fn expand_word(word: &str) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let active = Word::active_chars(word);
+    for open in (0..chars.len()).filter(|&i| active[i] && chars[i] == '{') {
+        if let Some((close, items)) = braces(&chars, &active, open) {
+            let preamble: String = chars[..open].iter().collect();
+            let postscript: String = chars[close + 1..].iter().collect();
+            // Later braces (in the item or after it) expand for each item in turn
+            return items
+                .iter()
+                .flat_map(|item| expand_word(&format!("{}{}", item, postscript)))
+                .map(|rest| format!("{}{}", preamble, rest))
+                .collect();
+        }
+    }
+    vec![word.to_string()]
+}
+
+// The `}` closing the `{` at `open` and the words the braces stand for, if they form
+// an expansion
+fn braces(chars: &[char], active: &[bool], open: usize) -> Option<(usize, Vec<String>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new(); // Those of this level, not of nested braces
+    for close in open + 1..chars.len() {
+        if !active[close] {
+            continue;
+        }
+        match chars[close] {
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            '}' if commas.is_empty() => {
+                let body: String = chars[open + 1..close].iter().collect();
+                return sequence(&body).map(|items| (close, items));
+            }
+            '}' => {
+                let mut start = open + 1;
+                let mut items = Vec::new();
+                for &end in commas.iter().chain([&close]) {
+                    items.push(chars[start..end].iter().collect());
+                    start = end + 1;
+                }
+                return Some((close, items));
+            }
+            ',' if depth == 0 => commas.push(close),
+            _ => {}
+        }
+    }
+    None
+}
+
+// `start..end[..step]` of integers or of single letters. A leading zero on either
+// number pads all of them to the same width.
+fn sequence(body: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = body.split("..").collect();
+    let (start, end, step) = match parts[..] {
+        [start, end] => (start, end, 1),
+        // The direction comes from the ends, not from the sign of the step
+        [start, end, step] => (start, end, step.parse::<i64>().ok()?.unsigned_abs().max(1)),
+        _ => return None,
+    };
+
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let padded = [start, end].iter().any(|n| {
+            let digits = n.trim_start_matches('-');
+            digits.len() > 1 && digits.starts_with('0')
+        });
+        let width = if padded {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+        return Some(
+            range(first, last, step)?
+                .map(|n| format!("{:0width$}", n, width = width))
+                .collect(),
+        );
+    }
+
+    let letter = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_alphabetic() => Some(c as i64),
+            _ => None,
+        }
+    };
+    let items = range(letter(start)?, letter(end)?, step)?.map(|code| {
+        let c = char::from(code as u8);
+        // Between `Z` and `a` are characters such as `[` and `\` that must stay literal
+        if c.is_ascii_alphabetic() {
+            c.to_string()
+        } else {
+            format!("\\{}", c)
+        }
+    });
+    Some(items.collect())
+}
+
+// A sequence may give at most this many words; a longer one is left as it is
+const MAX_SEQUENCE_WORDS: u64 = 1_000_000;
+
+// None if the sequence is too long. Each offset `k * step` is at most the distance from
+// `first` to `last`, so the values stay between the two.
+fn range(first: i64, last: i64, step: u64) -> Option<impl Iterator<Item = i64>> {
+    let count = (first.abs_diff(last) / step)
+        .checked_add(1)
+        .filter(|&count| count <= MAX_SEQUENCE_WORDS)?;
+    Some((0..count).map(move |k| {
+        if first <= last {
+            first.wrapping_add_unsigned(k * step)
+        } else {
+            first.wrapping_sub_unsigned(k * step)
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lists() {
+        assert_eq!(expand("file{1,2}.txt"), vec!["file1.txt", "file2.txt"]);
+        assert_eq!(expand("a{b,c{d,e},}f"), vec!["abf", "acdf", "acef", "af"]);
+        assert_eq!(expand("{a,b}{1,2}"), vec!["a1", "a2", "b1", "b2"]);
+        assert_eq!(expand("{\"x,y\",'}',z}"), vec!["\"x,y\"", "'}'", "z"]);
+        assert_eq!(expand("{a,{b,c}"), vec!["{a,b", "{a,c"]);
+        // Unquoted empty words are not arguments
+        assert_eq!(expand("{,x}"), vec!["x"]);
+        assert_eq!(expand("{x,}"), vec!["x"]);
+        assert!(expand("{,}").is_empty());
+        assert_eq!(expand("{'',x}"), vec!["''", "x"]);
+        // Nothing to expand
+        for word in ["{}", "{a}", "{a,b", "\\{a,b}", "'{a,b}'", "${a,b}", "a}b{"] {
+            assert_eq!(expand(word), vec![word], "{}", word);
+        }
+    }
+
+    #[test]
+    fn test_sequences() {
+        assert_eq!(expand("{1..5}"), vec!["1", "2", "3", "4", "5"]);
+        assert_eq!(expand("{10..1..-3}"), vec!["10", "7", "4", "1"]);
+        assert_eq!(expand("x{-1..1}"), vec!["x-1", "x0", "x1"]);
+        assert_eq!(expand("{08..11}"), vec!["08", "09", "10", "11"]);
+        assert_eq!(expand("{1..100..50}"), vec!["1", "51"]);
+        assert_eq!(expand("{-05..5..5}"), vec!["-05", "000", "005"]);
+        assert_eq!(expand("{a..e..2}"), vec!["a", "c", "e"]);
+        assert_eq!(expand("{C..A}"), vec!["C", "B", "A"]);
+        assert_eq!(expand("{Z..a}")[1], "\\[");
+        // Ends and steps at the limits of the integers
+        assert_eq!(
+            expand("{9223372036854775806..9223372036854775807}"),
+            vec!["9223372036854775806", "9223372036854775807"]
+        );
+        assert_eq!(
+            expand("{-9223372036854775808..9223372036854775807..-9223372036854775808}"),
+            vec!["-9223372036854775808", "0"]
+        );
+        // Too many words to generate
+        for word in [
+            "{-9223372036854775808..9223372036854775807}",
+            "{1..1000001}",
+        ] {
+            assert_eq!(expand(word), vec![word], "{}", word);
+        }
+        for word in ["{1..}", "{a..1}", "{aa..b}", "{1..2..x}", "{1...2}"] {
+            assert_eq!(expand(word), vec![word], "{}", word);
+        }
+    }
+}
@@ -0,0 +1,1461 @@
+use super::arith::{self, ArithVars};
+use super::param::{self, ParamOp};
+use super::{brace, glob, tilde};
+use crate::ast::{AstNode, CommandNode, CompoundNode};
+use crate::environment::Environment;
+use crate::executor::{
+    ExecError, ExecOutcome, ExecStatus, Executor, ProcessSubstitutions, SubshellHandler,
+};
+use crate::lexer::{Lexer, Word, WordSegment, starts_process_substitution, substitution_end};
+use crate::parser::{DefaultParser, ParseError, Parser};
+use std::cell::RefCell;
+use std::fmt;
+
+pub struct Expander<'a> {
+    env: &'a Environment,
+    executor: &'a dyn Executor, // The caller's, whose child() runs `$(...)` and `<(...)`
+    cwd: std::path::PathBuf,    // Required for wildcard expansion
+    assigned: RefCell<Vec<(String, String)>>, // By `$((i++))` and `${x:=word}`, see into_assignments
+    processes: RefCell<ProcessSubstitutions>, // Of `<(...)` and `>(...)`, see take_processes
+}
+
+impl<'a> Expander<'a> {
+    pub fn new(
+        env: &'a Environment,
+        cwd: impl Into<std::path::PathBuf>,
+        executor: &'a dyn Executor,
+    ) -> Self {
+        Self {
+            env,
+            executor,
+            cwd: cwd.into(),
+            assigned: RefCell::new(Vec::new()),
+            processes: RefCell::default(),
+        }
+    }
+
+    // Variables assigned while expanding. The expander only reads the environment, so
+    // the caller stores them once it is done.
+    pub fn into_assignments(self) -> Vec<(String, String)> {
+        self.assigned.into_inner()
+    }
+
+    // The children started by process substitutions. The caller keeps them until the
+    // command that uses their `/dev/fd/N` paths is done; dropping them reaps the children.
+    pub fn take_processes(&self) -> ProcessSubstitutions {
+        self.processes.take()
+    }
+
+    // A variable's value, including assignments made during this expansion, or a special
+    // parameter (`$#`, `$1`, `$$`, ...)
+    fn var(&self, name: &str) -> Option<String> {
+        match name {
+            "#" => return Some(self.env.positional().len().to_string()),
+            "@" | "*" => return Some(self.env.positional().join(&self.ifs_separator())),
+            "$" => return Some(self.env.shell_pid().to_string()),
+            "-" => return Some(self.env.flags()),
+            _ if name.starts_with(|c: char| c.is_ascii_digit()) => {
+                return match name.parse::<usize>().ok()? {
+                    0 => Some(self.env.script_name().to_string()),
+                    n => self.env.positional().get(n - 1).cloned(),
+                };
+            }
+            _ => {}
+        }
+        let assigned = self.assigned.borrow();
+        match assigned.iter().find(|(assigned, _)| assigned == name) {
+            Some((_, value)) => Some(value.clone()),
+            None => self.env.get(name).map(str::to_string),
+        }
+    }
+
+    // The characters that separate fields; unset means space, tab and newline
+    fn ifs(&self) -> String {
+        self.var("IFS").unwrap_or_else(|| " \t\n".to_string())
+    }
+
+    // What `"$*"` puts between the positional parameters: the first character of IFS
+    fn ifs_separator(&self) -> String {
+        self.ifs().chars().take(1).collect()
+    }
+
+    // `"$@"` (and unquoted, field-split `$@` and `$*`) expand to one word per positional
+    // parameter; the first and last join the text around them. Returns false for other
+    // parameters. `ifs` is set when unquoted results are split.
+    fn push_positional(
+        &self,
+        fields: &mut Fields,
+        name: &str,
+        quoting: Quoting,
+        ifs: Option<&str>,
+    ) -> bool {
+        self.push_parameters(fields, name, self.env.positional(), quoting, ifs)
+    }
+
+    // Like push_positional, for the parameters after a `${@...}` operator. Where they do
+    // not stay separate words, they are joined as in `"$*"`.
+    fn push_parameters(
+        &self,
+        fields: &mut Fields,
+        name: &str,
+        words: &[String],
+        quoting: Quoting,
+        ifs: Option<&str>,
+    ) -> bool {
+        match (name, quoting, ifs) {
+            ("@", Quoting::Double, _) => fields.push_words(words, None),
+            ("@" | "*", Quoting::Unquoted, Some(ifs)) => fields.push_words(words, Some(ifs)),
+            _ => return false,
+        }
+        true
+    }
+
+    // Assign a variable for the rest of the expansion (and afterwards, see into_assignments)
+    fn assign(&self, name: &str, value: &str) {
+        let mut assigned = self.assigned.borrow_mut();
+        assigned.retain(|(assigned, _)| assigned != name);
+        assigned.push((name.to_string(), value.to_string()));
+    }
+
+    // Evaluate an arithmetic expression (`$((expr))`, `((expr))`) after expanding the
+    // parameters and command substitutions in it
+    pub fn arithmetic(&self, expr: &str) -> Result<i64, ExpandError> {
+        let mut fields = Fields::default();
+        self.substitute(&mut fields, expr, Quoting::Heredoc, Split::Never)?;
+        arith::evaluate(&fields.finish().concat(), &mut ExpanderVars(self))
+    }
+
+    // Recursively expands the AST (such as command substitution, variable expansion, wildcard expansion, etc.)
+    pub fn expand(&self, node: AstNode) -> Result<AstNode, ExpandError> {
+        match node {
+            AstNode::Command(cmd) => {
+                let expanded = self.expand_command(cmd)?;
+                Ok(AstNode::Command(expanded))
+            }
+            AstNode::Subshell(inner) => {
+                let expanded = self.expand(*inner)?;
+                Ok(AstNode::Subshell(Box::new(expanded)))
+            }
+            AstNode::Background(inner) => {
+                let expanded = self.expand(*inner)?;
+                Ok(AstNode::Background(Box::new(expanded)))
+            }
+            AstNode::Redirect {
+                node,
+                kind,
+                file,
+                span,
+            } => {
+                let expanded_node = self.expand(*node)?;
+                let file = self.expand_single_arg(&file).map_err(|e| e.in_word(span))?;
+                Ok(AstNode::Redirect {
+                    node: Box::new(expanded_node),
+                    kind,
+                    file,
+                    span: None,
+                })
+            }
+            AstNode::Pipeline(nodes) => {
+                let expanded_nodes = nodes
+                    .into_iter()
+                    .map(|node| self.expand(node))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(AstNode::Pipeline(expanded_nodes))
+            }
+            AstNode::And(left, right) => Ok(AstNode::And(
+                Box::new(self.expand(*left)?),
+                Box::new(self.expand(*right)?),
+            )),
+            AstNode::Or(left, right) => Ok(AstNode::Or(
+                Box::new(self.expand(*left)?),
+                Box::new(self.expand(*right)?),
+            )),
+            AstNode::Sequence(nodes) => {
+                let expanded_nodes = nodes
+                    .into_iter()
+                    .map(|node| self.expand(node))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(AstNode::Sequence(expanded_nodes))
+            }
+            AstNode::Compound(compound) => Ok(AstNode::Compound(self.expand_compound(compound)?)),
+        }
+    }
+
+    fn expand_compound(&self, compound: CompoundNode) -> Result<CompoundNode, ExpandError> {
+        let expand_list = |nodes: Vec<AstNode>| {
+            nodes
+                .into_iter()
+                .map(|node| self.expand(node))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        match compound {
+            CompoundNode::Group(nodes) => Ok(CompoundNode::Group(expand_list(nodes)?)),
+            CompoundNode::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => Ok(CompoundNode::If {
+                cond: Box::new(self.expand(*cond)?),
+                then_branch: expand_list(then_branch)?,
+                else_branch: else_branch.map(expand_list).transpose()?,
+            }),
+            CompoundNode::While { cond, body } => Ok(CompoundNode::While {
+                cond: Box::new(self.expand(*cond)?),
+                body: expand_list(body)?,
+            }),
+            CompoundNode::Until { cond, body } => Ok(CompoundNode::Until {
+                cond: Box::new(self.expand(*cond)?),
+                body: expand_list(body)?,
+            }),
+            CompoundNode::For {
+                var,
+                items,
+                spans,
+                body,
+            } => Ok(CompoundNode::For {
+                var,
+                items: self.expand_items(&items, &spans)?,
+                spans: Vec::new(),
+                body: expand_list(body)?,
+            }),
+            // Evaluated when it runs
+            CompoundNode::Arithmetic(expr) => Ok(CompoundNode::Arithmetic(expr)),
+        }
+    }
+
+    pub fn expand_command(&self, cmd: CommandNode) -> Result<CommandNode, ExpandError> {
+        // The name may expand to several words (e.g. a glob); the extra ones become arguments
+        let span = |i: usize| cmd.spans.get(i).copied();
+        let mut words = self.expand_arg(&cmd.name).map_err(|e| e.in_word(span(0)))?;
+        let declaration = words.first().is_some_and(|name| name == "export");
+        for (i, arg) in cmd.args.iter().enumerate() {
+            if declaration && is_assignment(arg) {
+                words.push(
+                    self.expand_assignment(arg)
+                        .map_err(|e| e.in_word(span(i + 1)))?,
+                );
+            } else {
+                words.extend(self.expand_arg(arg).map_err(|e| e.in_word(span(i + 1)))?);
+            }
+        }
+
+        // The expanded words no longer match those of the source
+        let mut words = words.into_iter();
+        Ok(CommandNode {
+            name: words.next().unwrap_or_default(),
+            args: words.collect(),
+            kind: cmd.kind,
+            spans: Vec::new(),
+        })
+    }
+
+    // Argument expansion (brace, variable, command, wildcard, quote processing).
+    // Only the results of unquoted expansions are split at IFS; only unquoted parts are globbed.
+    pub fn expand_arg(&self, arg: &str) -> Result<Vec<String>, ExpandError> {
+        let mut parts = Vec::new();
+        for word in brace::expand(arg) {
+            let mut fields = Fields::default();
+            self.substitute_word(&mut fields, &word, Quoting::Unquoted, Split::Expansions)?;
+            for field in fields.finish() {
+                parts.extend(self.glob_expand(&field)?);
+            }
+        }
+        Ok(parts)
+    }
+
+    // The words of a `for` loop's list, given their spans in the source
+    pub fn expand_items(
+        &self,
+        items: &[String],
+        spans: &[(usize, usize)],
+    ) -> Result<Vec<String>, ExpandError> {
+        let mut words = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            words.extend(
+                self.expand_arg(item)
+                    .map_err(|e| e.in_word(spans.get(i).copied()))?,
+            );
+        }
+        Ok(words)
+    }
+
+    // A `NAME=value` argument of `export`, expanded like an assignment: the value is
+    // neither split nor globbed, and a tilde expands at its start and after each unquoted
+    // `:` (`PATH=~/bin:~/.local/bin`)
+    fn expand_assignment(&self, arg: &str) -> Result<String, ExpandError> {
+        let (name, value) = arg.split_once('=').unwrap_or((arg, ""));
+        let mut fields = Fields::default();
+        fields.push_str(name);
+        fields.push('=');
+        let chars: Vec<char> = value.chars().collect();
+        let active = Word::active_chars(value);
+        let mut start = 0;
+        for end in (0..chars.len())
+            .filter(|&i| active[i] && chars[i] == ':')
+            .chain([chars.len()])
+        {
+            if start > 0 {
+                fields.push(':');
+            }
+            let part: String = chars[start..end].iter().collect();
+            self.substitute_word(&mut fields, &part, Quoting::Unquoted, Split::Never)?;
+            start = end + 1;
+        }
+        Ok(glob::unescape(&fields.finish().join(" ")))
+    }
+
+    // Expansion of quoted heredoc
+    pub fn expand_heredoc(&self, content: &str, quoted: bool) -> Result<String, ExpandError> {
+        if quoted {
+            Ok(content.to_string()) // No expansion
+        } else {
+            let mut fields = Fields::default();
+            self.substitute(&mut fields, content, Quoting::Heredoc, Split::Never)?;
+            Ok(fields.finish().concat())
+        }
+    }
+
+    // Replace $VAR, ${VAR}, $(...), $((...)) and `...` in one segment of a word. Unquoted,
+    // the results are split into fields as `split` says; inside double quotes everything
+    // is literal for globbing.
+    fn substitute(
+        &self,
+        fields: &mut Fields,
+        input: &str,
+        quoting: Quoting,
+        split: Split,
+    ) -> Result<(), ExpandError> {
+        let chars: Vec<char> = input.chars().collect();
+        let push_text = |fields: &mut Fields, text: &str| {
+            if quoting == Quoting::Double {
+                fields.push_literal(text);
+            } else {
+                fields.push_str(text);
+            }
+        };
+        let ifs = (quoting == Quoting::Unquoted && split != Split::Never).then(|| self.ifs());
+        let process_at =
+            |i: usize| quoting == Quoting::Unquoted && starts_process_substitution(&chars, i);
+        let push_expansion = |fields: &mut Fields, text: &str| match &ifs {
+            Some(ifs) => fields.push_split(text, ifs),
+            None => push_text(fields, text),
+        };
+        let mut i = 0;
+
+        while i < chars.len() {
+            let ch = chars[i];
+            if ch == '\\' {
+                match (quoting, chars.get(i + 1)) {
+                    (_, Some('\n')) => {} // Line continuation
+                    // Escaped character → Add as is (glob metacharacters stay escaped for glob_expand)
+                    (Quoting::Unquoted, Some(&next)) => fields.push_literal(&next.to_string()),
+                    // In double quotes (and heredocs) only a few characters can be escaped
+                    (Quoting::Double, Some(&next)) if matches!(next, '$' | '`' | '"' | '\\') => {
+                        fields.push_literal(&next.to_string())
+                    }
+                    (Quoting::Heredoc, Some(&next)) if matches!(next, '$' | '`' | '\\') => {
+                        fields.push(next)
+                    }
+                    _ => {
+                        push_text(fields, "\\");
+                        i += 1;
+                        continue;
+                    }
+                }
+                i += 2;
+            } else if ch == '`' || (ch == '$' && chars.get(i + 1) == Some(&'(')) {
+                let end = substitution_end(&chars, i)
+                    .map_err(|e| ExpandError::CommandSubstitutionFailed(e.to_string()))?;
+                // `$((expr))`, unless the inner parentheses close early (`$((cmd) | cmd)`)
+                if ch == '$'
+                    && chars.get(i + 2) == Some(&'(')
+                    && substitution_end(&chars, i + 1).is_ok_and(|inner| inner == end - 1)
+                {
+                    let expr: String = chars[i + 3..end - 2].iter().collect();
+                    push_expansion(fields, &self.arithmetic(&expr)?.to_string());
+                    i = end;
+                    continue;
+                }
+                let command = if ch == '`' {
+                    unescape_backticks(&chars[i + 1..end - 1])
+                } else {
+                    chars[i + 2..end - 1].iter().collect()
+                };
+                push_expansion(fields, &self.command_substitute(&command)?);
+                i = end;
+            } else if process_at(i) {
+                let end = substitution_end(&chars, i)
+                    .map_err(|e| ExpandError::CommandSubstitutionFailed(e.to_string()))?;
+                let command: String = chars[i + 2..end - 1].iter().collect();
+                fields.push_literal(&self.process_substitute(&command, ch == '>')?);
+                i = end;
+            } else if ch == '$' {
+                i += 1;
+                match chars.get(i) {
+                    Some('{') => {
+                        let end = substitution_end(&chars, i - 1).map_err(|_| {
+                            ExpandError::InvalidVariableSyntax(chars[i - 1..].iter().collect())
+                        })?;
+                        let inner: String = chars[i + 1..end - 1].iter().collect();
+                        i = end;
+                        if self.push_positional(fields, &inner, quoting, ifs.as_deref()) {
+                            continue;
+                        }
+                        match self.parameter(&inner, quoting)? {
+                            Parameter::Value(value) => push_expansion(fields, &value),
+                            Parameter::Positional(name, words) => {
+                                if !self.push_parameters(
+                                    fields,
+                                    name,
+                                    &words,
+                                    quoting,
+                                    ifs.as_deref(),
+                                ) {
+                                    push_expansion(fields, &words.join(&self.ifs_separator()));
+                                }
+                            }
+                            // The word's own text is split too (`${x:-a b}` gives two fields)
+                            Parameter::Word(word) => {
+                                let split = if split == Split::Never {
+                                    split
+                                } else {
+                                    Split::All
+                                };
+                                self.substitute_word(fields, word, quoting, split)?
+                            }
+                        }
+                    }
+                    // Special parameters, and `$0`-`$9` (`$10` is `${1}0`)
+                    Some(&c)
+                        if matches!(c, '?' | '!' | '#' | '$' | '-' | '@' | '*')
+                            || c.is_ascii_digit() =>
+                    {
+                        i += 1;
+                        if !self.push_positional(fields, &c.to_string(), quoting, ifs.as_deref()) {
+                            push_expansion(fields, &self.var(&c.to_string()).unwrap_or_default());
+                        }
+                    }
+                    Some(&c) if is_var_start_char(c) => {
+                        let mut var_name = String::new();
+                        while i < chars.len() && is_var_char(chars[i]) {
+                            var_name.push(chars[i]);
+                            i += 1;
+                        }
+                        push_expansion(fields, &self.var(&var_name).unwrap_or_default());
+                    }
+                    _ => {
+                        // No variable name follows $ → Add $ as is
+                        push_text(fields, "$");
+                    }
+                }
+            } else {
+                // Plain text up to the next escape or expansion
+                let start = i;
+                while i < chars.len() && !matches!(chars[i], '\\' | '`' | '$') && !process_at(i) {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                match &ifs {
+                    Some(ifs) if split == Split::All => fields.push_split(&text, ifs),
+                    _ => push_text(fields, &text),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // `${...}`, given the text between the braces: the parameter's value after applying
+    // the operator, or the word of `${x:-word}` / `${x:+word}` to expand in its place
+    fn parameter<'w>(
+        &self,
+        inner: &'w str,
+        quoting: Quoting,
+    ) -> Result<Parameter<'w>, ExpandError> {
+        let bad = || ExpandError::InvalidVariableSyntax(format!("${{{}}}", inner));
+        let (name, operation) = param::parse(inner).ok_or_else(bad)?;
+        let value = self.var(name);
+        let text = value.clone().unwrap_or_default();
+        // `${@...}` and `${*...}` apply the operator to each positional parameter, which
+        // stay separate words
+        let positional = matches!(name, "@" | "*");
+        let each = |apply: &dyn Fn(&str) -> String| match positional {
+            true => Parameter::Positional(
+                name,
+                self.env.positional().iter().map(|p| apply(p)).collect(),
+            ),
+            false => Parameter::Value(apply(&text)),
+        };
+        Ok(match operation {
+            ParamOp::Value => Parameter::Value(text),
+            ParamOp::Length if positional => {
+                Parameter::Value(self.env.positional().len().to_string())
+            }
+            ParamOp::Length => Parameter::Value(text.chars().count().to_string()),
+            ParamOp::Test { op, colon, word } => {
+                // With a colon an empty value counts as unset
+                let set = value.is_some_and(|value| !colon || !value.is_empty());
+                match (op, set) {
+                    ('-', false) | ('+', true) => Parameter::Word(word),
+                    ('+', false) => Parameter::Value(String::new()),
+                    ('=', false) => {
+                        // Only variables can be assigned, not `$1` or `$?`
+                        if !name.starts_with(is_var_start_char) {
+                            return Err(bad());
+                        }
+                        let text = self.expand_operand(word, quoting)?;
+                        self.assign(name, &text);
+                        Parameter::Value(text)
+                    }
+                    ('?', false) => {
+                        let message = self.expand_operand(word, quoting)?;
+                        return Err(ExpandError::UnsetParameter {
+                            name: name.to_string(),
+                            message,
+                        });
+                    }
+                    _ => each(&str::to_string),
+                }
+            }
+            ParamOp::RemovePrefix { pattern, longest } => {
+                let pattern = self.expand_pattern(pattern)?;
+                each(&|value| param::remove_prefix(value, &pattern, longest))
+            }
+            ParamOp::RemoveSuffix { pattern, longest } => {
+                let pattern = self.expand_pattern(pattern)?;
+                each(&|value| param::remove_suffix(value, &pattern, longest))
+            }
+            ParamOp::Replace {
+                pattern,
+                replacement,
+                mode,
+            } => {
+                let pattern = self.expand_pattern(pattern)?;
+                let replacement = self.expand_operand(replacement, quoting)?;
+                each(&|value| param::replace(value, &pattern, &replacement, mode))
+            }
+            ParamOp::Substring { offset, length } => {
+                if offset.trim().is_empty() {
+                    return Err(bad());
+                }
+                let length = match length {
+                    Some(length) if length.trim().is_empty() => Some(0),
+                    Some(length) => Some(self.arithmetic(length)?),
+                    None => None,
+                };
+                let offset = self.arithmetic(offset)?;
+                if positional {
+                    // Offset 0 is `$0`, as in `${@:0}`
+                    let mut items = vec![self.env.script_name().to_string()];
+                    items.extend_from_slice(self.env.positional());
+                    Parameter::Positional(name, param::sublist(&items, offset, length))
+                } else {
+                    Parameter::Value(param::substring(&text, offset, length))
+                }
+            }
+            ParamOp::Case { upper, all } => each(&|value| param::change_case(value, upper, all)),
+        })
+    }
+
+    // A word with quotes of its own: a command argument or the word of a `${...}` operator.
+    // Inside double quotes, single quotes in an operator's word are ordinary characters.
+    fn substitute_word(
+        &self,
+        fields: &mut Fields,
+        word: &str,
+        quoting: Quoting,
+        split: Split,
+    ) -> Result<(), ExpandError> {
+        let unquoted = quoting == Quoting::Unquoted;
+        let segments = Word::parse(word).segments;
+        for (i, segment) in segments.iter().enumerate() {
+            match segment {
+                WordSegment::Unquoted(text) if i == 0 && unquoted => {
+                    // The tilde prefix must be unquoted up to its `/`: `~"/x"` stays as it is
+                    let tilde = if text.contains('/') || segments.len() == 1 {
+                        self.tilde_prefix(text)?
+                    } else {
+                        None
+                    };
+                    match tilde {
+                        // The directory is neither split nor globbed
+                        Some((dir, rest)) => {
+                            fields.push_literal(&dir);
+                            self.substitute(fields, rest, quoting, split)?;
+                        }
+                        None => self.substitute(fields, text, quoting, split)?,
+                    }
+                }
+                WordSegment::Unquoted(text) => self.substitute(fields, text, quoting, split)?,
+                WordSegment::SingleQuoted(text) if unquoted => fields.push_literal(text),
+                WordSegment::SingleQuoted(text) => {
+                    self.substitute(fields, &format!("'{}'", text), quoting, Split::Never)?
+                }
+                WordSegment::DoubleQuoted(text) if unquoted => {
+                    // `""` is a word even when empty, but `"$@"` is nothing without parameters
+                    if !only_positional(text) {
+                        fields.push_str("");
+                    }
+                    self.substitute(fields, text, Quoting::Double, Split::Never)?
+                }
+                WordSegment::DoubleQuoted(text) => {
+                    self.substitute(fields, text, quoting, Split::Never)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // An operator's word as plain text (`${x:=word}`, `${x:?word}`, replacements)
+    fn expand_operand(&self, word: &str, quoting: Quoting) -> Result<String, ExpandError> {
+        let mut fields = Fields::default();
+        let quoting = if quoting == Quoting::Unquoted {
+            quoting
+        } else {
+            Quoting::Double
+        };
+        self.substitute_word(&mut fields, word, quoting, Split::Never)?;
+        Ok(glob::unescape(&fields.finish().join(" ")))
+    }
+
+    // A pattern of `${x#pattern}` and the like. It is active even inside double quotes;
+    // its quoted parts are escaped.
+    fn expand_pattern(&self, pattern: &str) -> Result<String, ExpandError> {
+        let mut fields = Fields::default();
+        self.substitute_word(&mut fields, pattern, Quoting::Unquoted, Split::Never)?;
+        Ok(fields.finish().join(" "))
+    }
+
+    // The AST of a substituted command; None if there is no command
+    fn parse_substitution(command: &str) -> Result<Option<AstNode>, ExpandError> {
+        let tokens = Lexer::new(command)
+            .tokenize_all()
+            .map_err(|e| ExpandError::CommandSubstitutionFailed(e.to_string()))?;
+        match DefaultParser::new(&tokens).parse() {
+            Ok(ast) => Ok(Some(ast)),
+            Err(ParseError::EmptyInput) => Ok(None),
+            Err(e) => Err(ExpandError::CommandSubstitutionFailed(e.to_string())),
+        }
+    }
+
+    // Run a substituted command (in the forked child) with an executor like the caller's
+    fn run_substitution(&self, ast: &AstNode) -> ExecStatus {
+        let mut env = self.env.clone();
+        self.executor.child().exec(ast, &mut env)
+    }
+
+    // Run `command` in a child shell and return its output without trailing newlines
+    fn command_substitute(&self, command: &str) -> Result<String, ExpandError> {
+        let Some(ast) = Self::parse_substitution(command)? else {
+            return Ok(String::new());
+        };
+
+        let result = SubshellHandler::capture_output_generic(|| self.run_substitution(&ast));
+        match result {
+            Ok((output, _)) => Ok(output.trim_end_matches('\n').to_string()),
+            Err(ExecError::Interrupted) => Err(ExpandError::Interrupted),
+            Err(e) => Err(ExpandError::CommandSubstitutionFailed(e.to_string())),
+        }
+    }
+
+    // `<(command)` (or `>(command)` with `child_reads`) runs the command in the background
+    // with its output (input) on a pipe and stands for the other end, e.g. /dev/fd/63
+    fn process_substitute(&self, command: &str, child_reads: bool) -> Result<String, ExpandError> {
+        let ast = Self::parse_substitution(command)?;
+        let fd = self
+            .processes
+            .borrow_mut()
+            .spawn(child_reads, || match &ast {
+                Some(ast) => self.run_substitution(ast),
+                None => Ok(ExecOutcome::Code(0)),
+            })
+            .map_err(|e| match e {
+                ExecError::Io(e) => ExpandError::IoError(e),
+                e => ExpandError::CommandSubstitutionFailed(e.to_string()),
+            })?;
+        Ok(format!("/dev/fd/{}", fd))
+    }
+
+    // Example: *.rs → ["lib.rs", "main.rs"]. Without a match the pattern is kept as is,
+    // unless `nullglob` (drop it) or `failglob` (error) is set.
+    fn glob_expand(&self, pattern: &str) -> Result<Vec<String>, ExpandError> {
+        if !glob::has_glob_chars(pattern) {
+            return Ok(vec![glob::unescape(pattern)]);
+        }
+
+        let matches = glob::glob(pattern, &self.cwd);
+        if !matches.is_empty() {
+            Ok(matches)
+        } else if self.env.option("failglob") {
+            Err(ExpandError::GlobNoMatch(glob::unescape(pattern)))
+        } else if self.env.option("nullglob") {
+            Ok(Vec::new())
+        } else {
+            Ok(vec![glob::unescape(pattern)])
+        }
+    }
+
+    // A redirect's target, which must expand to exactly one word
+    pub fn expand_single_arg(&self, s: &str) -> Result<String, ExpandError> {
+        let mut words = self.expand_arg(s)?;
+        match words.len() {
+            1 => Ok(words.remove(0)),
+            _ => Err(ExpandError::AmbiguousRedirect(s.to_string())),
+        }
+    }
+
+    // The directory that a leading `~` (HOME), `~name` (that user's home), `~+` (PWD) or
+    // `~-` (OLDPWD) stands for, with the text after the prefix, which ends at the first `/`.
+    // None if there is no prefix or it does not resolve.
+    fn tilde_prefix<'t>(&self, text: &'t str) -> Result<Option<(String, &'t str)>, ExpandError> {
+        let Some(rest) = text.strip_prefix('~') else {
+            return Ok(None);
+        };
+        let (prefix, rest) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let dir = match prefix {
+            "" => match self.var("HOME") {
+                Some(home) => Some(home),
+                None => tilde::home_dir(None)?,
+            },
+            "+" => self.var("PWD"),
+            "-" => self.var("OLDPWD"),
+            name => tilde::home_dir(Some(name))?,
+        };
+        Ok(dir.map(|dir| (dir, rest)))
+    }
+}
+
+// Inside backticks a backslash only escapes `$`, `` ` `` and itself
+fn unescape_backticks(chars: &[char]) -> String {
+    let mut result = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '\\' && matches!(chars.get(i + 1), Some('$' | '`' | '\\')) {
+            i += 1;
+        }
+        result.push(chars[i]);
+        i += 1;
+    }
+    result
+}
+
+// Whether quoted text is just `$@` or `${@...}`, which give no word without parameters
+fn only_positional(text: &str) -> bool {
+    let chars: Vec<char> = text.chars().collect();
+    text == "$@"
+        || (text.starts_with("${@")
+            && substitution_end(&chars, 0).is_ok_and(|end| end == chars.len()))
+}
+
+// What a `${...}` expands to
+enum Parameter<'w> {
+    Value(String),
+    Positional(&'w str, Vec<String>), // `${@...}` or `${*...}`: the name and each parameter's value
+    Word(&'w str),                    // An operator's word, expanded like the text around it
+}
+
+// How the text passed to `substitute` was quoted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quoting {
+    Unquoted,
+    Double,
+    Heredoc,
+}
+
+// Which results of unquoted expansions are split into fields at IFS characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Split {
+    Never,      // Patterns, assignments and other single strings
+    Expansions, // Results of $x, ${x}, $(cmd) and $((expr)) in command arguments
+    All,        // Also the text of an operator's word (`${x:-a b}` gives two fields)
+}
+
+// The words produced by `substitute`. Unquoted command substitution output and the
+// positional parameters of `$@` may start new words; everything else extends the current one.
+#[derive(Default)]
+struct Fields {
+    words: Vec<String>,
+    current: String,
+    started: bool, // Whether `current` holds a word (possibly empty)
+    split: bool,   // Whether any output was split (or was separate words, as from "$@")
+}
+
+impl Fields {
+    fn push(&mut self, c: char) {
+        self.current.push(c);
+        self.started = true;
+    }
+
+    fn push_str(&mut self, s: &str) {
+        self.current.push_str(s);
+        self.started = true;
+    }
+
+    // Quoted text: escape glob metacharacters so that glob_expand keeps them literally
+    fn push_literal(&mut self, s: &str) {
+        for c in s.chars() {
+            if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+                self.current.push('\\');
+            }
+            self.current.push(c);
+        }
+        self.started = true;
+    }
+
+    // Separate words such as the positional parameters of `"$@"`, each split at `ifs` if
+    // given. Without any, a word that consists of nothing else disappears.
+    fn push_words(&mut self, words: &[String], ifs: Option<&str>) {
+        self.split = true;
+        for (i, word) in words.iter().enumerate() {
+            if i > 0 {
+                self.end_word();
+            }
+            match ifs {
+                Some(ifs) => self.push_split(word, ifs),
+                None => self.push_literal(word),
+            }
+        }
+    }
+
+    // Split unquoted expansion output into fields. IFS whitespace (space, tab, newline)
+    // separates fields and is dropped at the ends; any other IFS character, together with
+    // the whitespace around it, ends a field even when that leaves it empty.
+    fn push_split(&mut self, output: &str, ifs: &str) {
+        self.split = true;
+        let mut after_space = false; // A field just ended at IFS whitespace
+        for c in output.chars() {
+            if !ifs.contains(c) {
+                after_space = false;
+                // A backslash in the output is an ordinary character, not a glob escape
+                if c == '\\' {
+                    self.current.push('\\');
+                }
+                self.push(c);
+            } else if matches!(c, ' ' | '\t' | '\n') {
+                after_space |= self.started;
+                self.end_word();
+            } else if after_space {
+                after_space = false;
+            } else {
+                self.words.push(std::mem::take(&mut self.current));
+                self.started = false;
+            }
+        }
+    }
+
+    fn end_word(&mut self) {
+        if self.started {
+            self.words.push(std::mem::take(&mut self.current));
+            self.started = false;
+        }
+    }
+
+    // A word that expanded to nothing but whitespace-only output disappears
+    fn finish(mut self) -> Vec<String> {
+        if self.started || !self.split {
+            self.words.push(self.current);
+        }
+        self.words
+    }
+}
+
+// `NAME=value`
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        name.starts_with(is_var_start_char) && name.chars().all(is_var_char)
+    })
+}
+
+fn is_var_start_char(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_var_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+#[derive(Debug)]
+pub enum ExpandError {
+    InvalidVariableSyntax(String), // The whole `${...}`
+    CommandSubstitutionFailed(String),
+    GlobPatternError(String),
+    GlobNoMatch(String),
+    TildeExpandFailed(String),
+    IoError(std::io::Error),
+    Unsupported(String),
+    Interrupted,
+    DivisionByZero(String), // The expression
+    ArithmeticError {
+        expr: String,
+        message: String,
+    },
+    UnsetParameter {
+        name: String,
+        message: String,
+    }, // `${name:?message}`
+    AmbiguousRedirect(String), // A redirect's word that gives no or several words
+    // An error in a word of the source, with the word's byte range
+    InWord {
+        span: (usize, usize),
+        error: Box<ExpandError>,
+    },
+}
+// Arithmetic variables: read through the expander, assignments kept in it
+struct ExpanderVars<'x, 'a>(&'x Expander<'a>);
+
+impl ArithVars for ExpanderVars<'_, '_> {
+    fn get(&self, name: &str) -> Option<String> {
+        self.0.var(name)
+    }
+
+    fn set(&mut self, name: &str, value: i64) {
+        self.0.assign(name, &value.to_string());
+    }
+}
+
+impl ExpandError {
+    // Locate the error at the source word it came from, unless it already is
+    pub fn in_word(self, span: Option<(usize, usize)>) -> Self {
+        match (self, span) {
+            (ExpandError::Interrupted, _) => ExpandError::Interrupted,
+            (error @ ExpandError::InWord { .. }, _) | (error, None) => error,
+            (error, Some(span)) => ExpandError::InWord {
+                span,
+                error: Box::new(error),
+            },
+        }
+    }
+
+    // The text within the word that the error is about
+    pub fn subject(&self) -> Option<&str> {
+        match self {
+            ExpandError::InWord { error, .. } => error.subject(),
+            ExpandError::GlobPatternError(pattern) | ExpandError::GlobNoMatch(pattern) => {
+                Some(pattern)
+            }
+            ExpandError::DivisionByZero(expr) | ExpandError::ArithmeticError { expr, .. } => {
+                Some(expr)
+            }
+            ExpandError::InvalidVariableSyntax(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpandError::InvalidVariableSyntax(text) => write!(f, "{}: bad substitution", text),
+            ExpandError::CommandSubstitutionFailed(cmd) => {
+                write!(f, "Command substitution failed: {}", cmd)
+            }
+            ExpandError::GlobPatternError(pattern) => write!(f, "Glob pattern error: {}", pattern),
+            ExpandError::GlobNoMatch(pattern) => write!(f, "no match: {}", pattern),
+            ExpandError::TildeExpandFailed(user) => {
+                write!(f, "Tilde expansion failed for user: {}", user)
+            }
+            ExpandError::IoError(e) => write!(f, "IO error: {}", e),
+            ExpandError::Unsupported(msg) => write!(f, "Unsupported operation: {}", msg),
+            ExpandError::Interrupted => write!(f, "Interrupted"),
+            ExpandError::DivisionByZero(expr) => write!(f, "{}: division by 0", expr),
+            ExpandError::ArithmeticError { expr, message } => write!(f, "{}: {}", expr, message),
+            ExpandError::UnsetParameter { name, message } if message.is_empty() => {
+                write!(f, "{}: parameter null or not set", name)
+            }
+            ExpandError::UnsetParameter { name, message } => write!(f, "{}: {}", name, message),
+            ExpandError::AmbiguousRedirect(word) => write!(f, "{}: ambiguous redirect", word),
+            ExpandError::InWord { error, .. } => write!(f, "{}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{CommandKind, CommandNode};
+    use crate::environment::Environment;
+    use crate::executor::builtin::BuiltinManager;
+    use crate::executor::{Executor, JobTable, RecursiveExecutor};
+    use crate::expander::{ExpandError, Expander};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn setup_env() -> Environment {
+        let mut env = Environment::new();
+        env.set("USER", "user");
+        env.set("EMPTY", "");
+        env
+    }
+
+    // Substitutions run with a RecursiveExecutor, kept for the rest of the test run
+    fn executor() -> &'static dyn Executor {
+        let builtins = Box::leak(Box::new(BuiltinManager::new()));
+        Box::leak(Box::new(RecursiveExecutor::new(
+            builtins,
+            Rc::new(RefCell::new(JobTable::new())),
+        )))
+    }
+
+    fn with_expander<F: FnOnce(&Expander)>(test: F) {
+        let env = setup_env();
+        let expander = Expander::new(&env, ".", executor());
+        test(&expander);
+    }
+
+    #[test]
+    fn test_variable_substitution_simple() {
+        with_expander(|expander| {
+            let result = expander.expand_arg("Hello $USER").unwrap();
+            assert_eq!(result, vec!["Hello user"]);
+        });
+    }
+
+    #[test]
+    fn test_variable_substitution_braced() {
+        with_expander(|expander| {
+            let result = expander.expand_arg("Home: ${USER}land").unwrap();
+            assert_eq!(result, vec!["Home: userland"]);
+        });
+    }
+
+    #[test]
+    fn test_variable_substitution_missing() {
+        with_expander(|expander| {
+            let result = expander.expand_arg("Unset: $NOTFOUND").unwrap();
+            assert_eq!(result, vec!["Unset: "]);
+        });
+    }
+
+    #[test]
+    fn test_command_substitution_basic() {
+        with_expander(|expander| {
+            let result = expander.expand_arg("Today is $(echo Friday)").unwrap();
+            assert_eq!(result, vec!["Today is Friday"]);
+        });
+    }
+
+    #[test]
+    fn test_command_substitution_backtick() {
+        with_expander(|expander| {
+            let result = expander.expand_arg("Now: `echo 42`").unwrap();
+            assert_eq!(result, vec!["Now: 42"]);
+        });
+    }
+
+    #[test]
+    fn test_command_substitution_nested_and_split() {
+        with_expander(|expander| {
+            let result = expander.expand_arg("<$(echo $(echo a) \"b  c\")>").unwrap();
+            assert_eq!(result, vec!["<a", "b", "c>"]);
+            assert_eq!(expander.expand_arg("$(echo x; echo)").unwrap(), vec!["x"]);
+            assert!(expander.expand_arg("$(true)").unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_glob_expansion() {
+        with_expander(|expander| {
+            let result = expander.expand_arg("src/*.rs").unwrap();
+            assert!(result.iter().any(|s| s.ends_with(".rs")));
+        });
+    }
+
+    #[test]
+    fn test_quoted_string_expansion() {
+        with_expander(|expander| {
+            let result = expander.expand_arg("\"Hello $USER\"").unwrap();
+            assert_eq!(result, vec!["Hello user"]);
+        });
+    }
+
+    #[test]
+    fn test_single_quotes_suppress_expansion() {
+        with_expander(|expander| {
+            let result = expander.expand_arg("'$USER $(echo x) *'").unwrap();
+            assert_eq!(result, vec!["$USER $(echo x) *"]);
+        });
+    }
+
+    #[test]
+    fn test_double_quotes_suppress_glob_and_split() {
+        with_expander(|expander| {
+            assert_eq!(
+                expander.expand_arg("\"src/*.rs\"").unwrap(),
+                vec!["src/*.rs"]
+            );
+            assert_eq!(
+                expander.expand_arg("\"$(echo 'a  b')\"").unwrap(),
+                vec!["a  b"]
+            );
+            assert_eq!(expander.expand_arg("\"$(true)\"").unwrap(), vec![""]);
+            assert_eq!(
+                expander.expand_arg("\"\\$USER \\x\"").unwrap(),
+                vec!["$USER \\x"]
+            );
+        });
+    }
+
+    #[test]
+    fn test_adjacent_segments_join() {
+        with_expander(|expander| {
+            let result = expander.expand_arg("foo\"$USER\"'$USER'bar").unwrap();
+            assert_eq!(result, vec!["foouser$USERbar"]);
+        });
+    }
+
+    #[test]
+    fn test_escaped_characters() {
+        with_expander(|expander| {
+            // Unquoted, a backslash quotes any character; in double quotes only `$ ` " \`
+            let result = expander.expand_arg("Line\\nBreak\\$USER").unwrap();
+            assert_eq!(result, vec!["LinenBreak$USER"]);
+            let result = expander.expand_arg("\"Line\\nBreak\\$USER\"").unwrap();
+            assert_eq!(result, vec!["Line\\nBreak$USER"]);
+            let result = expander.expand_arg("a\\ b\\'").unwrap();
+            assert_eq!(result, vec!["a b'"]);
+        });
+    }
+
+    #[test]
+    fn test_heredoc_unquoted() {
+        with_expander(|expander| {
+            let input = "Path: $USER\nToday: $(echo Sun)";
+            let output = expander.expand_heredoc(input, false).unwrap();
+            assert_eq!(output.trim(), "Path: user\nToday: Sun");
+        });
+    }
+
+    #[test]
+    fn test_heredoc_quoted_no_expansion() {
+        with_expander(|expander| {
+            let input = "Path: $USER\nToday: $(echo Sun)";
+            let output = expander.expand_heredoc(input, true).unwrap();
+            assert_eq!(output.trim(), input);
+        });
+    }
+
+    #[test]
+    fn test_glob_no_match_returns_literal() {
+        with_expander(|expander| {
+            let result = expander.expand_arg("no_such_file_*.xyz").unwrap();
+            assert_eq!(result, vec!["no_such_file_*.xyz"]);
+        });
+    }
+
+    #[test]
+    fn test_glob_no_match_modes() {
+        let mut env = setup_env();
+        env.set_option("nullglob", true);
+        let expander = Expander::new(&env, ".", executor());
+        assert!(
+            expander
+                .expand_arg("no_such_file_*.xyz")
+                .unwrap()
+                .is_empty()
+        );
+
+        env.set_option("nullglob", false);
+        env.set_option("failglob", true);
+        let expander = Expander::new(&env, ".", executor());
+        assert!(expander.expand_arg("no_such_file_*.xyz").is_err());
+    }
+
+    #[test]
+    fn test_escaped_glob_is_literal() {
+        with_expander(|expander| {
+            let result = expander.expand_arg("src/\\*.rs").unwrap();
+            assert_eq!(result, vec!["src/*.rs"]);
+        });
+    }
+
+    #[test]
+    fn test_last_status_variable() {
+        let mut env = setup_env();
+        env.set_last_status(2);
+        let expander = Expander::new(&env, ".", executor());
+        assert_eq!(expander.expand_arg("status=$?").unwrap(), vec!["status=2"]);
+    }
+
+    #[test]
+    fn test_empty_variable() {
+        with_expander(|expander| {
+            let result = expander.expand_arg("[$EMPTY]").unwrap();
+            assert_eq!(result, vec!["[]"]);
+        });
+    }
+
+    #[test]
+    fn test_arithmetic_expansion() {
+        let mut env = setup_env();
+        env.set("n", "4");
+        let expander = Expander::new(&env, ".", executor());
+        assert_eq!(expander.expand_arg("$((n * 2 + 1))").unwrap(), vec!["9"]);
+        assert_eq!(
+            expander.expand_arg("\"$(( n > 3 ? n : 0 ))\"x").unwrap(),
+            vec!["4x"]
+        );
+        // Assignments are visible later in the same expansion and handed to the caller
+        assert_eq!(
+            expander.expand_arg("$((n++)),$n,$((n += 10))").unwrap(),
+            vec!["4,5,15"]
+        );
+        assert_eq!(
+            expander.into_assignments(),
+            vec![("n".to_string(), "15".to_string())]
+        );
+
+        let expander = Expander::new(&env, ".", executor());
+        assert!(matches!(
+            expander.expand_arg("$((n / 0))"),
+            Err(ExpandError::DivisionByZero(_))
+        ));
+    }
+
+    #[test]
+    fn test_parameter_operators() {
+        let mut env = setup_env();
+        env.set("path", "/usr/lib/file.tar.gz");
+        let expander = Expander::new(&env, ".", executor());
+        let expand = |arg: &str| expander.expand_arg(arg).unwrap();
+        assert_eq!(expand("${UNSET:-a b}"), vec!["a", "b"]);
+        assert_eq!(expand("\"${UNSET:-a b}\""), vec!["a b"]);
+        assert_eq!(
+            expand("${EMPTY-set}|${EMPTY:-empty}|${USER:+alt}|${UNSET+alt}"),
+            vec!["|empty|alt|"]
+        );
+        assert_eq!(expand("\"${UNSET:-'$USER' *}\""), vec!["'user' *"]);
+        // Patterns stay active inside double quotes
+        assert_eq!(
+            expand("\"${#path} ${path##*/} ${path%.*} ${path%%.*}\""),
+            vec!["20 file.tar.gz /usr/lib/file.tar /usr/lib/file"]
+        );
+        assert_eq!(
+            expand("${path#\"/usr\"},${path/\\//:},${path//l/L},${path/#\\/usr/.}"),
+            vec!["/lib/file.tar.gz,:usr/lib/file.tar.gz,/usr/Lib/fiLe.tar.gz,./lib/file.tar.gz"]
+        );
+        assert_eq!(
+            expand("\"${USER:1} ${USER: -2} ${USER:0:$((1 + 1))} ${USER^} ${USER^^}\""),
+            vec!["ser er us User USER"]
+        );
+
+        // `=` assigns for the rest of the expansion and for the caller
+        assert_eq!(expand("${EMPTY:=x y}/$EMPTY"), vec!["x", "y/x", "y"]);
+        assert_eq!(
+            expander.into_assignments(),
+            vec![("EMPTY".to_string(), "x y".to_string())]
+        );
+
+        let expander = Expander::new(&env, ".", executor());
+        assert!(matches!(
+            expander.expand_arg("${UNSET:?not here}"),
+            Err(ExpandError::UnsetParameter { message, .. }) if message == "not here"
+        ));
+        // Errors in a command's words carry the word's span for the diagnostic
+        let cmd = CommandNode {
+            name: "echo".to_string(),
+            args: vec!["ok".to_string(), "${UNSET:?x}".to_string()],
+            kind: CommandKind::Simple,
+            spans: vec![(0, 4), (5, 7), (8, 19)],
+        };
+        assert!(matches!(
+            expander.expand_command(cmd),
+            Err(ExpandError::InWord { span: (8, 19), .. })
+        ));
+        assert!(
+            matches!(expander.expand_arg("${USER!}"), Err(ExpandError::InvalidVariableSyntax(s)) if s == "${USER!}")
+        );
+        assert!(matches!(
+            expander.expand_arg("${1:=x}"),
+            Err(ExpandError::InvalidVariableSyntax(_))
+        ));
+    }
+
+    #[test]
+    fn test_field_splitting() {
+        let mut env = setup_env();
+        env.set("FILES", "  a b\tc\n");
+        env.set("CSV", "1,,2 , 3,");
+        env.set("GLOB", "*.none x\\y");
+        let expander = Expander::new(&env, ".", executor());
+        let expand = |arg: &str| expander.expand_arg(arg).unwrap();
+        assert_eq!(expand("$FILES"), vec!["a", "b", "c"]);
+        assert_eq!(expand("<$FILES>"), vec!["<", "a", "b", "c", ">"]);
+        assert_eq!(expand("\"$FILES\""), vec!["  a b\tc\n"]);
+        assert_eq!(expand("$(echo 1 2)$((3 * 4))"), vec!["1", "212"]);
+        assert_eq!(expand("$EMPTY"), Vec::<String>::new());
+        assert_eq!(expand("$EMPTY\"\""), vec![""]);
+        // Split before globbing; the backslash from the value is kept
+        assert_eq!(expand("$GLOB"), vec!["*.none", "x\\y"]);
+        assert_eq!(expand("${CSV}"), vec!["1,,2", ",", "3,"]);
+
+        env.set("IFS", ", ");
+        let expander = Expander::new(&env, ".", executor());
+        assert_eq!(
+            expander.expand_arg("$CSV").unwrap(),
+            vec!["1", "", "2", "3"]
+        );
+        assert_eq!(
+            expander.expand_arg("x${UNSET:-a,b}").unwrap(),
+            vec!["xa", "b"]
+        );
+        // Literal text is never split, nor are the values of `export` assignments
+        assert_eq!(expander.expand_arg("a,b").unwrap(), vec!["a,b"]);
+        let cmd = CommandNode {
+            name: "export".to_string(),
+            args: vec!["L=$CSV".to_string(), "$CSV".to_string()],
+            kind: CommandKind::Simple,
+            spans: Vec::new(),
+        };
+        assert_eq!(
+            expander.expand_command(cmd).unwrap().args,
+            vec!["L=1,,2 , 3,", "1", "", "2", "3"]
+        );
+
+        env.set("IFS", "");
+        let expander = Expander::new(&env, ".", executor());
+        assert_eq!(expander.expand_arg("$FILES").unwrap(), vec!["  a b\tc\n"]);
+    }
+
+    #[test]
+    fn test_redirect_target_is_one_word() {
+        let mut env = setup_env();
+        env.set("V", "a b");
+        let expander = Expander::new(&env, ".", executor());
+        assert_eq!(expander.expand_single_arg("\"$V\"").unwrap(), "a b");
+        assert_eq!(expander.expand_single_arg("*.none").unwrap(), "*.none");
+        for word in ["$V", "$EMPTY", "src/*.rs"] {
+            assert!(
+                matches!(
+                    expander.expand_single_arg(word),
+                    Err(ExpandError::AmbiguousRedirect(w)) if w == word
+                ),
+                "{}",
+                word
+            );
+        }
+    }
+
+    #[test]
+    fn test_brace_expansion() {
+        with_expander(|expander| {
+            // Before variable expansion and splitting; quotes keep their meaning in each word
+            assert_eq!(
+                expander
+                    .expand_arg("{$USER,'a b',\"$USER\"}-{1..2}")
+                    .unwrap(),
+                vec!["user-1", "user-2", "a b-1", "a b-2", "user-1", "user-2"]
+            );
+            // Nor inside quotes or `${...}`
+            assert_eq!(
+                expander.expand_arg("\"{a,b}\"${EMPTY:-{c,d}}").unwrap(),
+                vec!["{a,b}{c,d}"]
+            );
+        });
+    }
+
+    #[test]
+    fn test_special_parameters() {
+        let mut env = setup_env();
+        env.set_script_name("script.sh");
+        env.set_positional(vec!["a b".to_string(), "*".to_string()]);
+        let expander = Expander::new(&env, ".", executor());
+        let expand = |arg: &str| expander.expand_arg(arg).unwrap();
+        assert_eq!(
+            expand("\"$0:$#:$1:${2}:$3:$10\""),
+            vec!["script.sh:2:a b:*::a b0"]
+        );
+        assert_eq!(expand("$$"), vec![std::process::id().to_string()]);
+        // "$@" is one word per parameter, joined with the text around it at the ends
+        assert_eq!(expand("\"$@\""), vec!["a b", "*"]);
+        assert_eq!(expand("<\"$@\">"), vec!["<a b", "*>"]);
+        assert_eq!(expand("\"$*\""), vec!["a b *"]);
+        assert_eq!(expand("\"${#@}\""), vec!["2"]);
+        // So are the parameters after an operator
+        assert_eq!(expand("\"${@:-none}\""), vec!["a b", "*"]);
+        assert_eq!(expand("\"${*:-none}\""), vec!["a b *"]);
+        assert_eq!(expand("<\"${@^}\">"), vec!["<A b", "*>"]);
+        assert_eq!(expand("\"${@/ /_}\""), vec!["a_b", "*"]);
+        assert_eq!(expand("\"${@:2}\""), vec!["*"]);
+        assert_eq!(expand("${@:0:2}"), vec!["script.sh", "a", "b"]);
+
+        let mut env = setup_env();
+        env.set("IFS", ":");
+        let expander = Expander::new(&env, ".", executor());
+        assert_eq!(expander.expand_arg("\"$@\"").unwrap(), Vec::<String>::new());
+        assert_eq!(expander.expand_arg("x\"$@\"").unwrap(), vec!["x"]);
+        assert_eq!(
+            expander.expand_arg("\"${@^}\"").unwrap(),
+            Vec::<String>::new()
+        );
+        assert_eq!(expander.expand_arg("\"${@:-none}\"").unwrap(), vec!["none"]);
+        env.set_positional(vec!["1".to_string(), "2".to_string()]);
+        let expander = Expander::new(&env, ".", executor());
+        assert_eq!(expander.expand_arg("\"$*\"").unwrap(), vec!["1:2"]);
+    }
+
+    #[test]
+    fn test_tilde_expand_home() {
+        // HOME comes from the shell's environment, not the process's
+        let mut env = setup_env();
+        env.set("HOME", "/home/some user");
+        let expander = Expander::new(&env, ".", executor());
+        let result = expander.expand_arg("~").unwrap();
+        assert_eq!(result, vec!["/home/some user"]);
+
+        let result = expander.expand_arg("~/foo/bar").unwrap();
+        assert_eq!(result, vec!["/home/some user/foo/bar"]);
+    }
+
+    #[test]
+    fn test_tilde_prefixes() {
+        let mut env = setup_env();
+        env.set("HOME", "/h*");
+        env.set("PWD", "/now");
+        env.set("OLDPWD", "/before");
+        let expander = Expander::new(&env, ".", executor());
+        let expand = |arg: &str| expander.expand_arg(arg).unwrap();
+        assert_eq!(expand("~+/x"), vec!["/now/x"]);
+        assert_eq!(expand("~-"), vec!["/before"]);
+        // Quoted or not at the start: left alone
+        for word in [
+            "\"~\"",
+            "'~'/x",
+            "~\"/x\"",
+            "\\~",
+            "a~",
+            "~no-such-user-here/x",
+        ] {
+            assert_eq!(expand(word).len(), 1, "{}", word);
+            assert!(expand(word)[0].contains('~'), "{}", word);
+        }
+        let root = expand("~root/x").remove(0);
+        assert!(root.starts_with('/') && root.ends_with("/x"), "{}", root);
+
+        // In `export` assignments also after `:`
+        let cmd = CommandNode {
+            name: "export".to_string(),
+            args: vec!["P=~/bin:~-:'~'/y:a~".to_string(), "x=~".to_string()],
+            kind: CommandKind::Simple,
+            spans: Vec::new(),
+        };
+        assert_eq!(
+            expander.expand_command(cmd).unwrap().args,
+            vec!["P=/h*/bin:/before:~/y:a~", "x=/h*"]
+        );
+    }
+
+    #[test]
+    fn test_process_substitution() {
+        with_expander(|expander| {
+            let path = expander.expand_arg("<(echo $USER; echo ')')").unwrap();
+            assert_eq!(path.len(), 1);
+            assert!(path[0].starts_with("/dev/fd/"), "{}", path[0]);
+            assert_eq!(std::fs::read_to_string(&path[0]).unwrap(), "user\n)\n");
+            drop(expander.take_processes());
+            // Quoted, it is plain text
+            assert_eq!(
+                expander.expand_arg("\"<(x)\"'>(y)'").unwrap(),
+                vec!["<(x)>(y)"]
+            );
+        });
+    }
+}
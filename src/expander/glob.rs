@@ -0,0 +1,302 @@
+use std::fs;
+use std::path::Path;
+
+// Filename generation for `*`, `?`, `[...]` and `**`.
+// A backslash makes the next character literal; the expander uses this for quoted text.
+
+// Whether the pattern contains an unescaped metacharacter
+pub fn has_glob_chars(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+// Remove the escaping backslashes of a pattern that is used literally
+pub fn unescape(pattern: &str) -> String {
+    let mut result = String::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some('*' | '?' | '[' | ']' | '\\')) => result.push(chars.next().unwrap()),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+// Match a single name against a pattern (no `/` handling)
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    match_from(&pattern, &name)
+}
+
+fn match_from(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => {
+            // Try every possible length for the star, shortest first
+            (0..=name.len()).any(|skip| match_from(&pattern[1..], &name[skip..]))
+        }
+        Some('?') => !name.is_empty() && match_from(&pattern[1..], &name[1..]),
+        Some('[') => match (name.first(), match_class(&pattern[1..])) {
+            (Some(&c), Some((set, rest))) => set.contains(c) && match_from(rest, &name[1..]),
+            // No closing `]`: the bracket is an ordinary character
+            (Some('['), None) => match_from(&pattern[1..], &name[1..]),
+            _ => false,
+        },
+        Some('\\') if pattern.len() > 1 => {
+            name.first() == Some(&pattern[1]) && match_from(&pattern[2..], &name[1..])
+        }
+        Some(&c) => name.first() == Some(&c) && match_from(&pattern[1..], &name[1..]),
+    }
+}
+
+// A bracket expression such as `[abc]`, `[a-z]` or `[!0-9]`
+struct CharClass {
+    negated: bool,
+    items: Vec<(char, char)>, // Inclusive ranges; a single char is (c, c)
+}
+
+impl CharClass {
+    fn contains(&self, c: char) -> bool {
+        let found = self.items.iter().any(|&(lo, hi)| lo <= c && c <= hi);
+        found != self.negated
+    }
+}
+
+// Parse the class after `[`, returning it with the rest of the pattern after `]`
+fn match_class(pattern: &[char]) -> Option<(CharClass, &[char])> {
+    let mut i = 0;
+    let negated = matches!(pattern.first(), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut items = Vec::new();
+    let start = i;
+    while i < pattern.len() {
+        let mut c = pattern[i];
+        // `]` right after the opening bracket is a member, not the end
+        if c == ']' && i > start {
+            return Some((CharClass { negated, items }, &pattern[i + 1..]));
+        }
+        if c == '\\' && i + 1 < pattern.len() {
+            i += 1;
+            c = pattern[i];
+        }
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            items.push((c, pattern[i + 2]));
+            i += 3;
+        } else {
+            items.push((c, c));
+            i += 1;
+        }
+    }
+    None
+}
+
+// Expand a pattern against the filesystem relative to `cwd`.
+// Returns the sorted matches, or nothing if no file matches.
+pub fn glob(pattern: &str, cwd: &Path) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    let components: Vec<&str> = rest.split('/').collect();
+    for (i, component) in components.iter().enumerate() {
+        let is_last = i == components.len() - 1;
+        let mut next = Vec::new();
+        for path in &paths {
+            if component.is_empty() {
+                // `a//b` or a trailing `/`: only directories continue
+                if is_dir(cwd, path) {
+                    next.push(path.clone());
+                }
+            } else if *component == "**" {
+                next.push(path.clone());
+                walk(cwd, path, &mut next);
+            } else if has_glob_chars(component) {
+                for name in read_dir(cwd, path) {
+                    if name.starts_with('.') && !component.starts_with('.') {
+                        continue; // Hidden files need an explicit leading dot
+                    }
+                    if matches(component, &name) {
+                        next.push(join(path, &name));
+                    }
+                }
+            } else {
+                next.push(join(path, &unescape(component)));
+            }
+        }
+        // Intermediate components must name directories
+        if !is_last {
+            next.retain(|p| p.is_empty() || is_dir(cwd, p));
+        }
+        paths = next;
+    }
+
+    let mut results: Vec<String> = paths
+        .into_iter()
+        .filter(|p| !p.is_empty() && fs::symlink_metadata(cwd.join(p)).is_ok())
+        .map(|p| {
+            if pattern.ends_with('/') && !p.ends_with('/') {
+                p + "/"
+            } else {
+                p
+            }
+        })
+        .collect();
+    results.sort();
+    results.dedup();
+    results
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() || dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+fn is_dir(cwd: &Path, path: &str) -> bool {
+    let dir = if path.is_empty() {
+        cwd.to_path_buf()
+    } else {
+        cwd.join(path)
+    };
+    dir.is_dir()
+}
+
+fn read_dir(cwd: &Path, path: &str) -> Vec<String> {
+    let dir = if path.is_empty() {
+        cwd.to_path_buf()
+    } else {
+        cwd.join(path)
+    };
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// Every non-hidden entry below `path` for `**`, without following symlinked directories
+fn walk(cwd: &Path, path: &str, out: &mut Vec<String>) {
+    let mut names = read_dir(cwd, path);
+    names.sort();
+    for name in names {
+        if name.starts_with('.') {
+            continue;
+        }
+        let child = join(path, &name);
+        let is_real_dir = fs::symlink_metadata(cwd.join(&child))
+            .map(|m| m.is_dir())
+            .unwrap_or(false);
+        out.push(child.clone());
+        if is_real_dir {
+            walk(cwd, &child, out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // A small tree under the temp dir, removed when dropped
+    struct TempTree(PathBuf);
+
+    impl TempTree {
+        fn new(name: &str, files: &[&str]) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "tiny-shell-glob-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&root);
+            for file in files {
+                let path = root.join(file);
+                if file.ends_with('/') {
+                    fs::create_dir_all(&path).unwrap();
+                } else {
+                    fs::create_dir_all(path.parent().unwrap()).unwrap();
+                    fs::write(&path, "").unwrap();
+                }
+            }
+            TempTree(root)
+        }
+    }
+
+    impl Drop for TempTree {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_matches_wildcards() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "main.rs.bak"));
+        assert!(matches("?.txt", "a.txt"));
+        assert!(!matches("?.txt", "ab.txt"));
+        assert!(matches("a*b*c", "aXXbYYc"));
+    }
+
+    #[test]
+    fn test_matches_classes() {
+        assert!(matches("[abc].txt", "b.txt"));
+        assert!(!matches("[abc].txt", "d.txt"));
+        assert!(matches("[a-z]1", "q1"));
+        assert!(matches("[!a-z]1", "Q1"));
+        assert!(!matches("[!a-z]1", "q1"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[x", "[x"));
+    }
+
+    #[test]
+    fn test_escaped_metacharacters_are_literal() {
+        assert!(matches("a\\*", "a*"));
+        assert!(!matches("a\\*", "ab"));
+        assert!(!has_glob_chars("a\\*b"));
+        assert_eq!(unescape("a\\*b\\\\"), "a*b\\");
+    }
+
+    #[test]
+    fn test_glob_sorted_and_hidden_excluded() {
+        let tree = TempTree::new("sorted", &["b.rs", "a.rs", ".hidden.rs", "c.txt"]);
+        assert_eq!(glob("*.rs", &tree.0), vec!["a.rs", "b.rs"]);
+        assert_eq!(glob(".*.rs", &tree.0), vec![".hidden.rs"]);
+        assert!(glob("*.xyz", &tree.0).is_empty());
+    }
+
+    #[test]
+    fn test_glob_directories() {
+        let tree = TempTree::new("dirs", &["src/main.rs", "src/lib.rs", "docs/", "top.rs"]);
+        assert_eq!(glob("src/*.rs", &tree.0), vec!["src/lib.rs", "src/main.rs"]);
+        assert_eq!(glob("*/", &tree.0), vec!["docs/", "src/"]);
+        assert_eq!(glob("*/main.rs", &tree.0), vec!["src/main.rs"]);
+    }
+
+    #[test]
+    fn test_glob_recursive() {
+        let tree = TempTree::new(
+            "recursive",
+            &["a.rs", "x/b.rs", "x/y/c.rs", "x/y/d.txt", ".git/e.rs"],
+        );
+        assert_eq!(glob("**/*.rs", &tree.0), vec!["a.rs", "x/b.rs", "x/y/c.rs"]);
+    }
+}
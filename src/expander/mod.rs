@@ -0,0 +1,9 @@
+mod arith;
+mod brace;
+#[allow(clippy::module_inception)]
+mod expander;
+mod glob;
+mod param;
+mod tilde;
+
+pub use expander::{ExpandError, Expander};
@@ -0,0 +1,426 @@
+use super::glob;
+
+// The text between `${` and `}`, split into the parameter name and what to do with it.
+// Words are kept unexpanded; the expander expands them only when they are used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamOp<'s> {
+    Value,  // ${name}
+    Length, // ${#name}
+    // ${name-word} ${name=word} ${name?word} ${name+word}; with `colon`, an empty value
+    // counts as unset too (${name:-word} ...)
+    Test {
+        op: char,
+        colon: bool,
+        word: &'s str,
+    },
+    RemovePrefix {
+        pattern: &'s str,
+        longest: bool,
+    }, // ${name#pattern} ${name##pattern}
+    RemoveSuffix {
+        pattern: &'s str,
+        longest: bool,
+    }, // ${name%pattern} ${name%%pattern}
+    Replace {
+        pattern: &'s str,
+        replacement: &'s str,
+        mode: Replace,
+    }, // ${name/pattern/replacement}
+    Substring {
+        offset: &'s str,
+        length: Option<&'s str>,
+    }, // ${name:offset:length}
+    Case {
+        upper: bool,
+        all: bool,
+    }, // ${name^} ${name^^} ${name,} ${name,,}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replace {
+    First,  // ${name/pattern/replacement}
+    All,    // ${name//pattern/replacement}
+    Prefix, // ${name/#pattern/replacement}
+    Suffix, // ${name/%pattern/replacement}
+}
+
+// Split `${...}`'s content; None for bad syntax (e.g. `${}`, `${1a}`, `${a!}`)
+pub fn parse(inner: &str) -> Option<(&str, ParamOp<'_>)> {
+    if let Some(name) = inner.strip_prefix('#')
+        && !name.is_empty()
+        && name_len(name) == name.len()
+    {
+        return Some((name, ParamOp::Length));
+    }
+    let len = name_len(inner);
+    if len == 0 {
+        return None;
+    }
+    let (name, rest) = inner.split_at(len);
+    let op = if rest.is_empty() {
+        ParamOp::Value
+    } else if let Some(word) = rest
+        .strip_prefix(':')
+        .filter(|w| w.starts_with(['-', '=', '?', '+']))
+    {
+        ParamOp::Test {
+            op: word.chars().next()?,
+            colon: true,
+            word: &word[1..],
+        }
+    } else if rest.starts_with(['-', '=', '?', '+']) {
+        ParamOp::Test {
+            op: rest.chars().next()?,
+            colon: false,
+            word: &rest[1..],
+        }
+    } else if let Some(pattern) = rest.strip_prefix("##") {
+        ParamOp::RemovePrefix {
+            pattern,
+            longest: true,
+        }
+    } else if let Some(pattern) = rest.strip_prefix('#') {
+        ParamOp::RemovePrefix {
+            pattern,
+            longest: false,
+        }
+    } else if let Some(pattern) = rest.strip_prefix("%%") {
+        ParamOp::RemoveSuffix {
+            pattern,
+            longest: true,
+        }
+    } else if let Some(pattern) = rest.strip_prefix('%') {
+        ParamOp::RemoveSuffix {
+            pattern,
+            longest: false,
+        }
+    } else if let Some(spec) = rest.strip_prefix('/') {
+        let (mode, spec) = match spec.chars().next() {
+            Some('/') => (Replace::All, &spec[1..]),
+            Some('#') => (Replace::Prefix, &spec[1..]),
+            Some('%') => (Replace::Suffix, &spec[1..]),
+            _ => (Replace::First, spec),
+        };
+        let (pattern, replacement) = match unquoted_slash(spec) {
+            Some(i) => (&spec[..i], &spec[i + 1..]),
+            None => (spec, ""),
+        };
+        ParamOp::Replace {
+            pattern,
+            replacement,
+            mode,
+        }
+    } else if let Some(spec) = rest.strip_prefix(':') {
+        match spec.split_once(':') {
+            Some((offset, length)) => ParamOp::Substring {
+                offset,
+                length: Some(length),
+            },
+            None => ParamOp::Substring {
+                offset: spec,
+                length: None,
+            },
+        }
+    } else {
+        match rest {
+            "^" => ParamOp::Case {
+                upper: true,
+                all: false,
+            },
+            "^^" => ParamOp::Case {
+                upper: true,
+                all: true,
+            },
+            "," => ParamOp::Case {
+                upper: false,
+                all: false,
+            },
+            ",," => ParamOp::Case {
+                upper: false,
+                all: true,
+            },
+            _ => return None,
+        }
+    };
+    Some((name, op))
+}
+
+// Length of the parameter name at the start: a variable name, digits, or one special character
+fn name_len(s: &str) -> usize {
+    match s.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => s
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(s.len()),
+        Some(c) if c.is_ascii_digit() => s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()),
+        Some('?' | '!' | '#' | '$' | '@' | '*' | '-') => 1,
+        _ => 0,
+    }
+}
+
+// Byte index of the first `/` that is not escaped or quoted
+fn unquoted_slash(s: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (c, quote) {
+            ('\\', q) if q != Some('\'') => {
+                chars.next();
+            }
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('/', None) => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+// Byte offsets of the char boundaries of `s`, including its end
+fn boundaries(s: &str) -> Vec<usize> {
+    s.char_indices().map(|(i, _)| i).chain([s.len()]).collect()
+}
+
+// `pattern` uses the glob syntax, with backslash-escaped literal characters
+pub fn remove_prefix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut ends = boundaries(value);
+    if longest {
+        ends.reverse();
+    }
+    match ends
+        .into_iter()
+        .find(|&end| glob::matches(pattern, &value[..end]))
+    {
+        Some(end) => value[end..].to_string(),
+        None => value.to_string(),
+    }
+}
+
+pub fn remove_suffix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut starts = boundaries(value);
+    if !longest {
+        starts.reverse();
+    }
+    match starts
+        .into_iter()
+        .find(|&start| glob::matches(pattern, &value[start..]))
+    {
+        Some(start) => value[..start].to_string(),
+        None => value.to_string(),
+    }
+}
+
+// Replace the longest match of `pattern` at the leftmost position (or every match)
+pub fn replace(value: &str, pattern: &str, replacement: &str, mode: Replace) -> String {
+    let bounds = boundaries(value);
+    let longest_at = |start: usize| {
+        bounds
+            .iter()
+            .rev()
+            .copied()
+            .filter(|&end| end >= start)
+            .find(|&end| glob::matches(pattern, &value[start..end]))
+    };
+    match mode {
+        Replace::Prefix => match longest_at(0) {
+            Some(end) => format!("{}{}", replacement, &value[end..]),
+            None => value.to_string(),
+        },
+        Replace::Suffix => match bounds
+            .iter()
+            .find(|&&start| glob::matches(pattern, &value[start..]))
+        {
+            Some(&start) => format!("{}{}", &value[..start], replacement),
+            None => value.to_string(),
+        },
+        Replace::First | Replace::All => {
+            let mut result = String::new();
+            let mut copied = 0;
+            let mut i = 0;
+            while i < bounds.len() - 1 {
+                let start = bounds[i];
+                // Empty matches are not replaced
+                match longest_at(start).filter(|&end| end > start) {
+                    Some(end) => {
+                        result.push_str(&value[copied..start]);
+                        result.push_str(replacement);
+                        copied = end;
+                        if mode == Replace::First {
+                            break;
+                        }
+                        i = bounds
+                            .iter()
+                            .position(|&b| b == end)
+                            .unwrap_or(bounds.len());
+                    }
+                    None => i += 1,
+                }
+            }
+            result.push_str(&value[copied..]);
+            result
+        }
+    }
+}
+
+// Characters from `offset` (from the end if negative), at most `length` of them; a
+// negative length leaves that many characters off the end
+pub fn substring(value: &str, offset: i64, length: Option<i64>) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    chars[slice(chars.len(), offset, length)].iter().collect()
+}
+
+// The items of `${@:offset:length}`; `items` starts with `$0`
+pub fn sublist(items: &[String], offset: i64, length: Option<i64>) -> Vec<String> {
+    items[slice(items.len(), offset, length)].to_vec()
+}
+
+// The part of `len` items that `offset` and `length` select
+fn slice(len: usize, offset: i64, length: Option<i64>) -> std::ops::Range<usize> {
+    let len = len as i64;
+    let start = if offset < 0 {
+        len.saturating_add(offset).max(0)
+    } else {
+        offset.min(len)
+    };
+    let end = match length {
+        Some(length) if length < 0 => len.saturating_add(length).max(start),
+        Some(length) => start.saturating_add(length).min(len),
+        None => len,
+    };
+    start as usize..end.max(start) as usize
+}
+
+pub fn change_case(value: &str, upper: bool, all: bool) -> String {
+    let convert = |c: char| -> String {
+        if upper {
+            c.to_uppercase().collect()
+        } else {
+            c.to_lowercase().collect()
+        }
+    };
+    let mut chars = value.chars();
+    match chars.next() {
+        Some(first) if !all => convert(first) + chars.as_str(),
+        Some(_) => value.chars().map(convert).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("HOME"), Some(("HOME", ParamOp::Value)));
+        assert_eq!(parse("#HOME"), Some(("HOME", ParamOp::Length)));
+        assert_eq!(parse("#"), Some(("#", ParamOp::Value)));
+        assert_eq!(
+            parse("x:-a b"),
+            Some((
+                "x",
+                ParamOp::Test {
+                    op: '-',
+                    colon: true,
+                    word: "a b"
+                }
+            ))
+        );
+        assert_eq!(
+            parse("x=1"),
+            Some((
+                "x",
+                ParamOp::Test {
+                    op: '=',
+                    colon: false,
+                    word: "1"
+                }
+            ))
+        );
+        assert_eq!(
+            parse("x##*/"),
+            Some((
+                "x",
+                ParamOp::RemovePrefix {
+                    pattern: "*/",
+                    longest: true
+                }
+            ))
+        );
+        assert_eq!(
+            parse("x%.*"),
+            Some((
+                "x",
+                ParamOp::RemoveSuffix {
+                    pattern: ".*",
+                    longest: false
+                }
+            ))
+        );
+        assert_eq!(
+            parse("x//'/'/\\/"),
+            Some((
+                "x",
+                ParamOp::Replace {
+                    pattern: "'/'",
+                    replacement: "\\/",
+                    mode: Replace::All
+                }
+            ))
+        );
+        assert_eq!(
+            parse("x: -3:2"),
+            Some((
+                "x",
+                ParamOp::Substring {
+                    offset: " -3",
+                    length: Some("2")
+                }
+            ))
+        );
+        assert_eq!(
+            parse("x^^"),
+            Some((
+                "x",
+                ParamOp::Case {
+                    upper: true,
+                    all: true
+                }
+            ))
+        );
+        assert_eq!(parse("1"), Some(("1", ParamOp::Value)));
+        for bad in ["", "1a", "a!", "x^^^", "-x-"] {
+            assert_eq!(parse(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_remove_and_replace() {
+        let path = "/usr/lib/file.tar.gz";
+        assert_eq!(remove_prefix(path, "*/", false), "usr/lib/file.tar.gz");
+        assert_eq!(remove_prefix(path, "*/", true), "file.tar.gz");
+        assert_eq!(remove_suffix(path, ".*", false), "/usr/lib/file.tar");
+        assert_eq!(remove_suffix(path, ".*", true), "/usr/lib/file");
+        assert_eq!(remove_suffix(path, "\\*", true), path);
+        assert_eq!(replace("a-b-c", "-", "+", Replace::First), "a+b-c");
+        assert_eq!(replace("a-b-c", "-", "+", Replace::All), "a+b+c");
+        assert_eq!(replace("aaa-b", "a*", "x", Replace::First), "x");
+        assert_eq!(replace("héllo", "l", "L", Replace::All), "héLLo");
+        assert_eq!(replace("abcabc", "abc", "", Replace::Suffix), "abc");
+        assert_eq!(replace("abcabc", "b", "", Replace::Prefix), "abcabc");
+    }
+
+    #[test]
+    fn test_substring_and_case() {
+        assert_eq!(substring("héllo", 1, Some(3)), "éll");
+        assert_eq!(substring("hello", -3, None), "llo");
+        assert_eq!(substring("hello", 1, Some(-1)), "ell");
+        assert_eq!(substring("hello", 9, Some(2)), "");
+        // Extreme offsets and lengths clamp instead of overflowing
+        assert_eq!(substring("hello", 1, Some(i64::MAX)), "ello");
+        assert_eq!(substring("hello", i64::MIN, Some(i64::MIN)), "");
+        assert_eq!(substring("hello", i64::MAX, None), "");
+        assert_eq!(change_case("hello world", true, false), "Hello world");
+        assert_eq!(change_case("Hello", false, true), "hello");
+        assert_eq!(change_case("éa", true, true), "ÉA");
+    }
+}
@@ -0,0 +1,43 @@
+use super::ExpandError;
+use std::ffi::{CStr, CString};
+
+// The home directory of `user` (the current user if None) from the passwd database,
+// or None if there is no such user
+pub fn home_dir(user: Option<&str>) -> Result<Option<String>, ExpandError> {
+    let failed = || ExpandError::TildeExpandFailed(user.unwrap_or_default().to_string());
+    let name = user.map(CString::new).transpose().map_err(|_| failed())?;
+    let mut buf: Vec<libc::c_char> = vec![0; 1024];
+    loop {
+        let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut found = std::ptr::null_mut();
+        let ret = unsafe {
+            match &name {
+                Some(name) => libc::getpwnam_r(
+                    name.as_ptr(),
+                    &mut entry,
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                    &mut found,
+                ),
+                None => libc::getpwuid_r(
+                    libc::getuid(),
+                    &mut entry,
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                    &mut found,
+                ),
+            }
+        };
+        match ret {
+            // The strings of the entry do not fit into the buffer
+            libc::ERANGE if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+            // Some systems report an unknown user as an error
+            0 | libc::ENOENT | libc::ESRCH if found.is_null() => return Ok(None),
+            0 => {
+                let dir = unsafe { CStr::from_ptr(entry.pw_dir) };
+                return Ok(Some(dir.to_string_lossy().into_owned()));
+            }
+            _ => return Err(failed()),
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use std::fs::{File, OpenOptions};
-use std::io::{BufReader, BufRead, Write};
+use std::io::{BufRead, BufReader, Write};
 
 pub struct HistoryManager {
     pub entries: Vec<String>,
@@ -20,11 +20,9 @@ impl HistoryManager {
         let mut entries = Vec::new();
         if let Ok(f) = file {
             let reader = BufReader::new(f);
-            for line in reader.lines() {
-                if let Ok(line) = line {
-                    if !line.trim().is_empty() {
-                        entries.push(line);
-                    }
+            for line in reader.lines().map_while(Result::ok) {
+                if !line.trim().is_empty() {
+                    entries.push(line);
                 }
             }
         }
@@ -43,7 +41,11 @@ impl HistoryManager {
     // Save history
     pub fn save(&self) -> std::io::Result<()> {
         if let Some(path) = &self.file_path {
-            let mut file = OpenOptions::new().create(true).write(true).truncate(true).open(path)?;
+            let mut file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(path)?;
             for line in &self.entries {
                 writeln!(file, "{}", line)?;
             }
@@ -58,7 +60,7 @@ impl HistoryManager {
             return;
         }
         // Do not add if it's the same as the previous entry
-        if self.entries.last().map_or(false, |last| last == trimmed) {
+        if self.entries.last().is_some_and(|last| last == trimmed) {
             return;
         }
         self.entries.push(trimmed.to_string());
@@ -83,6 +85,10 @@ impl HistoryManager {
         self.entries.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
     // Clear history
     pub fn clear(&mut self) {
         self.entries.clear();
@@ -93,4 +99,3 @@ impl HistoryManager {
         self.entries.last().map(|s| s.as_str())
     }
 }
-
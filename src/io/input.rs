@@ -1,4 +1,4 @@
-use std::io::{Write};
+use std::io::{BufRead, Write};
 
 pub struct InputHandler;
 
@@ -7,14 +7,44 @@ impl InputHandler {
         print!("{}", prompt);
         std::io::stdout().flush().unwrap();
 
-        let mut buf = String::new();
-        let bytes_read = std::io::stdin().read_line(&mut buf)?;
+        let mut buf = Vec::new();
+        let bytes_read = Self::read_raw_line(&mut buf)?;
         if bytes_read == 0 {
             // EOF (e.g., Ctrl-D)
             println!();
             return Ok(None);
         }
-        Ok(Some(buf.trim_end().to_string()))
+        // Only the line ending goes; trailing blanks matter in here-documents
+        Ok(Some(
+            String::from_utf8_lossy(&buf)
+                .trim_end_matches(['\n', '\r'])
+                .to_string(),
+        ))
     }
-}
 
+    // Unlike `BufRead::read_line`, a signal (Ctrl+C) is not retried but returned
+    // as `ErrorKind::Interrupted`, so the caller can discard the line.
+    fn read_raw_line(buf: &mut Vec<u8>) -> std::io::Result<usize> {
+        let stdin = std::io::stdin();
+        let mut reader = stdin.lock();
+        loop {
+            let (done, used) = {
+                let available = reader.fill_buf()?;
+                match available.iter().position(|&b| b == b'\n') {
+                    Some(i) => {
+                        buf.extend_from_slice(&available[..=i]);
+                        (true, i + 1)
+                    }
+                    None => {
+                        buf.extend_from_slice(available);
+                        (available.is_empty(), available.len())
+                    }
+                }
+            };
+            reader.consume(used);
+            if done {
+                return Ok(buf.len());
+            }
+        }
+    }
+}
@@ -2,4 +2,3 @@ mod input;
 mod output;
 
 pub use input::InputHandler;
-
@@ -1,33 +1,90 @@
-use std::fmt;
 use super::token::{Token, TokenKind};
+use super::word::{Word, WordSegment};
+use std::fmt;
+use std::rc::Rc;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum LexError {
     UnexpectedChar(char, usize),
     UnterminatedQuote(char, usize),
+    UnterminatedSubstitution(usize),
+    UnterminatedHeredoc(String, usize),
+    TrailingBackslash(usize),
+    UnterminatedArithmetic(usize),
+}
+
+impl LexError {
+    // The source text the error is about, as a byte range
+    pub fn span(&self) -> (usize, usize) {
+        match self {
+            LexError::UnexpectedChar(c, pos) => (*pos, pos + c.len_utf8()),
+            LexError::UnterminatedQuote(_, pos)
+            | LexError::UnterminatedSubstitution(pos)
+            | LexError::TrailingBackslash(pos) => (*pos, pos + 1),
+            LexError::UnterminatedArithmetic(pos) => (*pos, pos + 2),
+            LexError::UnterminatedHeredoc(delimiter, pos) => (*pos, pos + delimiter.len()),
+        }
+    }
+
+    // Whether more input could complete the command (the REPL then reads another line)
+    pub fn is_incomplete(&self) -> bool {
+        !matches!(self, LexError::UnexpectedChar(..))
+    }
 }
 
 impl fmt::Display for LexError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            LexError::UnexpectedChar(c, pos) => write!(f, "Unexpected character '{}' at position {}", c, pos),
-            LexError::UnterminatedQuote(c, q) => write!(f, "Unterminated quote '{}' starting at position {}", c, q),
+            LexError::UnexpectedChar(c, pos) => {
+                write!(f, "Unexpected character '{}' at position {}", c, pos)
+            }
+            LexError::UnterminatedQuote(c, q) => {
+                write!(f, "Unterminated quote '{}' starting at position {}", c, q)
+            }
+            LexError::UnterminatedSubstitution(pos) => {
+                write!(f, "Unterminated substitution starting at position {}", pos)
+            }
+            LexError::UnterminatedHeredoc(delim, pos) => write!(
+                f,
+                "Here-document '{}' at position {} is not terminated",
+                delim, pos
+            ),
+            LexError::TrailingBackslash(pos) => {
+                write!(f, "Backslash at end of input (position {})", pos)
+            }
+            LexError::UnterminatedArithmetic(pos) => {
+                write!(f, "Unterminated '((' starting at position {}", pos)
+            }
         }
     }
 }
 
-pub struct Lexer<'a> {
-    input: &'a str,
-    chars: std::str::Chars<'a>,
-    pos: usize,
+// Positions in tokens and errors are byte offsets into the input
+pub struct Lexer {
+    chars: Rc<[char]>,
+    offsets: Vec<usize>,    // Byte offset of each char, plus the input length
+    pos: usize,             // Index into `chars`
+    command_position: bool, // Whether the next word may be a reserved word
+    pending_heredoc: Option<bool>, // After `<<` (true for `<<-`): the next word is a delimiter
+    heredoc_body: Option<Token>, // Emitted right after the delimiter
+    heredoc_skip: Option<(usize, usize)>, // (newline, end): bodies to skip after that newline
 }
 
-impl<'a> Lexer<'a> {
-    pub fn new(input: &'a str) -> Self {
+impl Lexer {
+    pub fn new(input: &str) -> Self {
+        let offsets = input
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain(std::iter::once(input.len()))
+            .collect();
         Lexer {
-            input,
-            chars: input.chars(),
+            chars: input.chars().collect(),
+            offsets,
             pos: 0,
+            command_position: true,
+            pending_heredoc: None,
+            heredoc_body: None,
+            heredoc_skip: None,
         }
     }
 
@@ -35,7 +92,7 @@ impl<'a> Lexer<'a> {
         let mut tokens = Vec::new();
         while let Some(token) = self.next_token()? {
             tokens.push(token);
-            if tokens.last().map_or(false, |t| t.kind == TokenKind::Eof) {
+            if tokens.last().is_some_and(|t| t.kind == TokenKind::Eof) {
                 break;
             }
         }
@@ -43,7 +100,59 @@ impl<'a> Lexer<'a> {
     }
 
     pub fn next_token(&mut self) -> Result<Option<Token>, LexError> {
-        let chars: Vec<char> = self.input.chars().collect();
+        match self.next_char_token() {
+            Ok(token) => Ok(token.map(|mut tok| {
+                tok.span = (self.offsets[tok.span.0], self.offsets[tok.span.1]);
+                tok
+            })),
+            Err(e) => Err(self.byte_error(e)),
+        }
+    }
+
+    // The same error with its char index turned into a byte offset
+    fn byte_error(&self, error: LexError) -> LexError {
+        let offset = |pos: usize| self.offsets[pos.min(self.chars.len())];
+        match error {
+            LexError::UnexpectedChar(c, pos) => LexError::UnexpectedChar(c, offset(pos)),
+            LexError::UnterminatedQuote(c, pos) => LexError::UnterminatedQuote(c, offset(pos)),
+            LexError::UnterminatedSubstitution(pos) => {
+                LexError::UnterminatedSubstitution(offset(pos))
+            }
+            LexError::UnterminatedHeredoc(delimiter, pos) => {
+                LexError::UnterminatedHeredoc(delimiter, offset(pos))
+            }
+            LexError::TrailingBackslash(pos) => LexError::TrailingBackslash(offset(pos)),
+            LexError::UnterminatedArithmetic(pos) => LexError::UnterminatedArithmetic(offset(pos)),
+        }
+    }
+
+    // Like `next_token`, with char indices for positions
+    fn next_char_token(&mut self) -> Result<Option<Token>, LexError> {
+        if let Some(body) = self.heredoc_body.take() {
+            return Ok(Some(body));
+        }
+        let pending_heredoc = self.pending_heredoc.take();
+        let mut token = self.scan_token()?;
+        if let Some(strip_tabs) = pending_heredoc
+            && let Some(tok) = &token
+            && tok.kind == TokenKind::Word
+        {
+            self.heredoc_body = Some(self.read_heredoc(tok, strip_tabs)?);
+        }
+        if let Some(tok) = token.as_mut() {
+            if tok.kind == TokenKind::Word
+                && self.command_position
+                && let Some(keyword) = TokenKind::keyword(&tok.lexeme)
+            {
+                tok.kind = keyword;
+            }
+            self.command_position = tok.kind.starts_command();
+        }
+        Ok(token)
+    }
+
+    fn scan_token(&mut self) -> Result<Option<Token>, LexError> {
+        let chars = Rc::clone(&self.chars);
         let mut buf = String::new();
         let mut token_start = self.pos;
 
@@ -51,7 +160,7 @@ impl<'a> Lexer<'a> {
             let ch = chars[self.pos];
 
             match ch {
-                ' ' | '\t' | '\n' => {
+                ' ' | '\t' => {
                     if !buf.is_empty() {
                         let token = Token {
                             kind: TokenKind::Word,
@@ -64,6 +173,31 @@ impl<'a> Lexer<'a> {
                     }
                     self.pos += 1;
                 }
+                '\n' => {
+                    if !buf.is_empty() {
+                        let token = Token {
+                            kind: TokenKind::Word,
+                            lexeme: buf.clone(),
+                            span: (token_start, self.pos),
+                        };
+                        buf.clear();
+                        return Ok(Some(token));
+                    }
+                    let token = Token {
+                        kind: TokenKind::Newline,
+                        lexeme: "\n".to_string(),
+                        span: (self.pos, self.pos + 1),
+                    };
+                    self.pos += 1;
+                    // Here-document bodies follow the line that started them
+                    if let Some((newline, end)) = self.heredoc_skip
+                        && newline == token.span.0
+                    {
+                        self.pos = end;
+                        self.heredoc_skip = None;
+                    }
+                    return Ok(Some(token));
+                }
                 '|' => {
                     if !buf.is_empty() {
                         let token = Token {
@@ -104,6 +238,15 @@ impl<'a> Lexer<'a> {
                         // Do not consume '&' (process it in the next loop)
                         return Ok(Some(token));
                     }
+                    if chars.get(self.pos + 1) == Some(&'>') {
+                        let token = Token {
+                            kind: TokenKind::RedirectOutErr,
+                            lexeme: "&>".to_string(),
+                            span: (self.pos, self.pos + 2),
+                        };
+                        self.pos += 2;
+                        return Ok(Some(token));
+                    }
                     if self.pos + 1 < chars.len() && chars[self.pos + 1] == '&' {
                         let token = Token {
                             kind: TokenKind::And,
@@ -114,7 +257,7 @@ impl<'a> Lexer<'a> {
                         return Ok(Some(token));
                     } else {
                         let token = Token {
-                            kind: TokenKind::NotImplemented,
+                            kind: TokenKind::Amp,
                             lexeme: "&".to_string(),
                             span: (self.pos, self.pos + 1),
                         };
@@ -122,26 +265,17 @@ impl<'a> Lexer<'a> {
                         return Ok(Some(token));
                     }
                 }
-                '>' => {
-                    if !buf.is_empty() {
-                        let token = Token {
-                            kind: TokenKind::Word,
-                            lexeme: buf.clone(),
-                            span: (token_start, self.pos),
-                        };
-                        buf.clear();
-                        return Ok(Some(token));
-                    }
-                    let token = Token {
-                        kind: TokenKind::RedirectOut,
-                        lexeme: ">".to_string(),
-                        span: (self.pos, self.pos + 1),
-                    };
-                    self.pos += 1;
-                    return Ok(Some(token));
+                '>' | '<' if buf.is_empty() && starts_process_substitution(&chars, self.pos) => {
+                    // `<(...)` and `>(...)` are words; the expander runs them
+                    token_start = self.pos;
+                    let end = substitution_end(&chars, self.pos)?;
+                    buf.extend(&chars[self.pos..end]);
+                    self.pos = end;
                 }
-                '<' => {
-                    if !buf.is_empty() {
+                '>' | '<' => {
+                    // A word of digits right before the operator is the fd number (`2>`)
+                    let fd_prefix = !buf.is_empty() && buf.chars().all(|c| c.is_ascii_digit());
+                    if !buf.is_empty() && !fd_prefix {
                         let token = Token {
                             kind: TokenKind::Word,
                             lexeme: buf.clone(),
@@ -150,13 +284,33 @@ impl<'a> Lexer<'a> {
                         buf.clear();
                         return Ok(Some(token));
                     }
-                    let token = Token {
-                        kind: TokenKind::RedirectIn,
-                        lexeme: "<".to_string(),
-                        span: (self.pos, self.pos + 1),
+                    let start = if fd_prefix { token_start } else { self.pos };
+                    let (kind, len) = match (ch, chars.get(self.pos + 1)) {
+                        ('>', Some('>')) => (TokenKind::RedirectAppend, 2),
+                        ('>', Some('|')) => (TokenKind::RedirectClobber, 2),
+                        ('>', Some('&')) => (TokenKind::RedirectDupOut, 2),
+                        ('>', _) => (TokenKind::RedirectOut, 1),
+                        ('<', Some('<')) => match chars.get(self.pos + 2) {
+                            Some('<') => (TokenKind::RedirectHereString, 3),
+                            Some('-') => (TokenKind::RedirectHeredocStrip, 3),
+                            _ => (TokenKind::RedirectHeredoc, 2),
+                        },
+                        ('<', Some('>')) => (TokenKind::RedirectReadWrite, 2),
+                        ('<', Some('&')) => (TokenKind::RedirectDupIn, 2),
+                        _ => (TokenKind::RedirectIn, 1),
                     };
-                    self.pos += 1;
-                    return Ok(Some(token));
+                    buf.extend(&chars[self.pos..self.pos + len]);
+                    self.pos += len;
+                    match kind {
+                        TokenKind::RedirectHeredoc => self.pending_heredoc = Some(false),
+                        TokenKind::RedirectHeredocStrip => self.pending_heredoc = Some(true),
+                        _ => {}
+                    }
+                    return Ok(Some(Token {
+                        kind,
+                        lexeme: std::mem::take(&mut buf),
+                        span: (start, self.pos),
+                    }));
                 }
                 ';' => {
                     if !buf.is_empty() {
@@ -186,6 +340,18 @@ impl<'a> Lexer<'a> {
                         buf.clear();
                         return Ok(Some(token));
                     }
+                    if self.command_position
+                        && chars.get(self.pos + 1) == Some(&'(')
+                        && let Some(end) = arithmetic_end(&chars, self.pos)?
+                    {
+                        let token = Token {
+                            kind: TokenKind::Arithmetic,
+                            lexeme: chars[self.pos..end].iter().collect(),
+                            span: (self.pos, end),
+                        };
+                        self.pos = end;
+                        return Ok(Some(token));
+                    }
                     let token = Token {
                         kind: TokenKind::LParen,
                         lexeme: "(".to_string(),
@@ -212,46 +378,81 @@ impl<'a> Lexer<'a> {
                     self.pos += 1;
                     return Ok(Some(token));
                 }
-                '\'' => {
-                    self.pos += 1; // Skip the starting quote
+                '\'' | '"' => {
+                    // Quoted parts join the surrounding word and keep their quotes,
+                    // so that the expander can tell them apart (see `Word`)
+                    if buf.is_empty() {
+                        token_start = self.pos;
+                    }
                     let start = self.pos;
-                    while self.pos < chars.len() {
-                        if chars[self.pos] == '\'' {
-                            let quoted = self.input[start..self.pos].to_string();
-                            let span = (start, self.pos);
-                            self.pos += 1; // Consume the closing quote
-                            return Ok(Some(Token {
-                                kind: TokenKind::Word,
-                                lexeme: quoted,
-                                span,
-                            }));
+                    buf.push(ch);
+                    self.pos += 1;
+                    loop {
+                        if self.pos >= chars.len() {
+                            return Err(LexError::UnterminatedQuote(ch, start));
                         }
+                        let c = chars[self.pos];
+                        if ch == '"' && starts_substitution(&chars, self.pos) {
+                            let end = substitution_end(&chars, self.pos)?;
+                            buf.extend(&chars[self.pos..end]);
+                            self.pos = end;
+                            continue;
+                        }
+                        if ch == '"' && c == '\\' {
+                            // The expander interprets `\$`, `\"` etc.; `\` newline joins lines
+                            if chars.get(self.pos + 1) != Some(&'\n') {
+                                buf.extend(
+                                    chars
+                                        .get(self.pos..self.pos + 2)
+                                        .unwrap_or(&chars[self.pos..]),
+                                );
+                            }
+                            self.pos += 2;
+                            continue;
+                        }
+                        buf.push(c);
                         self.pos += 1;
+                        if c == ch {
+                            break;
+                        }
                     }
-                    return Err(LexError::UnterminatedQuote('\'', start - 1));
                 }
-                '"' => {
-                    self.pos += 1; // Skip the starting quote
-                    let start = self.pos;
-                    while self.pos < chars.len() {
-                        if chars[self.pos] == '"' {
-                            let quoted = self.input[start..self.pos].to_string();
-                            let span = (start, self.pos); // only contents
-                            self.pos += 1; // Consume the closing quote
-                            return Ok(Some(Token {
-                                kind: TokenKind::Word,
-                                lexeme: quoted,
-                                span,
-                            }));
-                        }
+                '\\' => {
+                    if self.pos + 1 == chars.len() {
+                        // A line continuation whose next line has not been read yet
+                        return Err(LexError::TrailingBackslash(self.pos));
+                    }
+                    if chars.get(self.pos + 1) == Some(&'\n') {
+                        // Line continuation: the backslash and the newline disappear
+                        self.pos += 2;
+                        continue;
+                    }
+                    // Keep the escaped character in the word (with its backslash, for the
+                    // expander), so that e.g. `\|` or `\ ` do not end it
+                    if buf.is_empty() {
+                        token_start = self.pos;
+                    }
+                    let end = (self.pos + 2).min(chars.len());
+                    buf.extend(&chars[self.pos..end]);
+                    self.pos = end;
+                }
+                '#' if buf.is_empty() => {
+                    // A comment runs to the end of the line; the newline itself is still a token
+                    while self.pos < chars.len() && chars[self.pos] != '\n' {
                         self.pos += 1;
                     }
-                    return Err(LexError::UnterminatedQuote('"', start - 1));
                 }
                 _ => {
                     if buf.is_empty() {
                         token_start = self.pos;
                     }
+                    if starts_substitution(&chars, self.pos) {
+                        // `$(...)` and `` `...` `` stay in the word; the expander runs them
+                        let end = substitution_end(&chars, self.pos)?;
+                        buf.extend(&chars[self.pos..end]);
+                        self.pos = end;
+                        continue;
+                    }
                     buf.push(ch);
                     self.pos += 1;
                 }
@@ -280,22 +481,201 @@ impl<'a> Lexer<'a> {
         Ok(None)
     }
 
+    // Read the body of a here-document. It starts on the line after the current one (or
+    // after the previous body started on that line) and ends at a line holding only the delimiter.
+    fn read_heredoc(
+        &mut self,
+        delimiter_token: &Token,
+        strip_tabs: bool,
+    ) -> Result<Token, LexError> {
+        let chars = Rc::clone(&self.chars);
+        let delimiter = heredoc_delimiter(&delimiter_token.lexeme);
+        let unterminated =
+            |delimiter| LexError::UnterminatedHeredoc(delimiter, delimiter_token.span.0);
+        let (newline, start) = match self.heredoc_skip {
+            Some(skip) => skip,
+            None => match chars[self.pos..].iter().position(|&c| c == '\n') {
+                Some(i) => (self.pos + i, self.pos + i + 1),
+                None => return Err(unterminated(delimiter)),
+            },
+        };
+
+        let mut body = String::new();
+        let mut line_start = start;
+        while line_start < chars.len() {
+            let line_end = chars[line_start..]
+                .iter()
+                .position(|&c| c == '\n')
+                .map_or(chars.len(), |i| line_start + i);
+            let mut line: String = chars[line_start..line_end].iter().collect();
+            if strip_tabs {
+                line = line.trim_start_matches('\t').to_string();
+            }
+            let next = (line_end + 1).min(chars.len());
+            if line == delimiter {
+                self.heredoc_skip = Some((newline, next));
+                return Ok(Token {
+                    kind: TokenKind::HeredocBody,
+                    lexeme: body,
+                    span: (start, line_start),
+                });
+            }
+            body.push_str(&line);
+            body.push('\n');
+            line_start = next;
+        }
+        Err(unterminated(delimiter))
+    }
+
     pub fn tokenize_all(&mut self) -> Result<Vec<Token>, LexError> {
         let mut tokens = Vec::new();
-        loop {
-            match self.next_token()? {
-                Some(token) => {
-                    let is_eof = token.kind == TokenKind::Eof;
-                    tokens.push(token);
-                    if is_eof {
-                        break;
+        while let Some(token) = self.next_token()? {
+            let is_eof = token.kind == TokenKind::Eof;
+            tokens.push(token);
+            if is_eof {
+                break;
+            }
+        }
+        Ok(tokens)
+    }
+}
+
+// The delimiter a here-document ends with: the word after `<<` with its quotes removed
+fn heredoc_delimiter(raw: &str) -> String {
+    Word::parse(raw)
+        .segments
+        .into_iter()
+        .map(|segment| match segment {
+            WordSegment::Unquoted(text) => {
+                let mut result = String::new();
+                let mut chars = text.chars();
+                while let Some(c) = chars.next() {
+                    result.push(if c == '\\' {
+                        chars.next().unwrap_or(c)
+                    } else {
+                        c
+                    });
+                }
+                result
+            }
+            WordSegment::SingleQuoted(text) | WordSegment::DoubleQuoted(text) => text,
+        })
+        .collect()
+}
+
+pub(super) fn starts_substitution(chars: &[char], pos: usize) -> bool {
+    chars[pos] == '`' || (chars[pos] == '$' && matches!(chars.get(pos + 1), Some('(' | '{')))
+}
+
+// `<(` or `>(`, outside quotes
+pub fn starts_process_substitution(chars: &[char], pos: usize) -> bool {
+    matches!(chars[pos], '<' | '>') && chars.get(pos + 1) == Some(&'(')
+}
+
+// Index just past the `${...}` starting at `start`. Nested substitutions, escapes and
+// double-quoted text are skipped; single quotes are ordinary characters here, as they are
+// inside `"${...}"`.
+fn parameter_end(chars: &[char], start: usize) -> Result<usize, LexError> {
+    let mut i = start + 2;
+    while i < chars.len() {
+        match chars[i] {
+            '}' => return Ok(i + 1),
+            '\\' => i += 2,
+            '"' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    if starts_substitution(chars, i) {
+                        i = substitution_end(chars, i)?;
+                        continue;
                     }
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
                 }
-                None => break,
+                i += 1;
             }
+            _ if starts_substitution(chars, i) => i = substitution_end(chars, i)?,
+            _ => i += 1,
         }
-        Ok(tokens)
     }
+    Err(LexError::UnterminatedSubstitution(start))
+}
+
+// End of the `((expression))` starting at `start`, or None when the parentheses do not
+// close as `))` (e.g. `((cd /tmp) && ls)`, nested subshells)
+fn arithmetic_end(chars: &[char], start: usize) -> Result<Option<usize>, LexError> {
+    let mut depth = 0;
+    let mut i = start + 2;
+    while i < chars.len() {
+        match chars[i] {
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            ')' => return Ok((chars.get(i + 1) == Some(&')')).then_some(i + 2)),
+            _ => {}
+        }
+        i += 1;
+    }
+    Err(LexError::UnterminatedArithmetic(start))
+}
+
+// Index just past the `$(...)`, `${...}`, `` `...` ``, `<(...)` or `>(...)` starting at `start`.
+// Parentheses nest, and quoted text inside is skipped so that `$(echo ")")` works.
+pub fn substitution_end(chars: &[char], start: usize) -> Result<usize, LexError> {
+    if chars.get(start + 1) == Some(&'{') {
+        return parameter_end(chars, start);
+    }
+    let mut i = start + 1;
+    if chars[start] == '`' {
+        while i < chars.len() {
+            match chars[i] {
+                '\\' => i += 2,
+                '`' => return Ok(i + 1),
+                _ => i += 1,
+            }
+        }
+        return Err(LexError::UnterminatedSubstitution(start));
+    }
+
+    let mut depth = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(i + 1);
+                }
+            }
+            '\\' => i += 1,
+            '`' => {
+                i = substitution_end(chars, i)?;
+                continue;
+            }
+            '\'' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '\'' {
+                    i += 1;
+                }
+            }
+            '"' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    if starts_substitution(chars, i) {
+                        i = substitution_end(chars, i)?;
+                        continue;
+                    }
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    Err(LexError::UnterminatedSubstitution(start))
 }
 
 #[cfg(test)]
@@ -354,7 +734,91 @@ mod tests {
         );
     }
 
-        #[test]
+    // Spans and error positions are byte offsets, also after multi-byte characters
+    #[test]
+    fn test_spans_are_byte_offsets() {
+        let tokens = Lexer::new("echo héllo > ö").tokenize_all().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                token(TokenKind::Word, "echo", (0, 4)),
+                token(TokenKind::Word, "héllo", (5, 11)),
+                token(TokenKind::RedirectOut, ">", (12, 13)),
+                token(TokenKind::Word, "ö", (14, 16)),
+                token(TokenKind::Eof, "", (16, 16)),
+            ]
+        );
+        let error = Lexer::new("echo ü 'x").tokenize_all().unwrap_err();
+        assert_eq!(error, LexError::UnterminatedQuote('\'', 8));
+        assert_eq!(error.span(), (8, 9));
+    }
+
+    #[test]
+    fn test_tokenize_redirects() {
+        let input = "ls 2>e >>a 3<&- &>b x2>y";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize_all().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                token(TokenKind::Word, "ls", (0, 2)),
+                token(TokenKind::RedirectOut, "2>", (3, 5)),
+                token(TokenKind::Word, "e", (5, 6)),
+                token(TokenKind::RedirectAppend, ">>", (7, 9)),
+                token(TokenKind::Word, "a", (9, 10)),
+                token(TokenKind::RedirectDupIn, "3<&", (11, 14)),
+                token(TokenKind::Word, "-", (14, 15)),
+                token(TokenKind::RedirectOutErr, "&>", (16, 18)),
+                token(TokenKind::Word, "b", (18, 19)),
+                token(TokenKind::Word, "x2", (20, 22)),
+                token(TokenKind::RedirectOut, ">", (22, 23)),
+                token(TokenKind::Word, "y", (23, 24)),
+                token(TokenKind::Eof, "", (24, 24)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_heredocs() {
+        let input = "cat <<A <<-'B'; x\nbody $y\nA\n\tinner\n\tB\ntr <<<w";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize_all().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                token(TokenKind::Word, "cat", (0, 3)),
+                token(TokenKind::RedirectHeredoc, "<<", (4, 6)),
+                token(TokenKind::Word, "A", (6, 7)),
+                token(TokenKind::HeredocBody, "body $y\n", (18, 26)),
+                token(TokenKind::RedirectHeredocStrip, "<<-", (8, 11)),
+                token(TokenKind::Word, "'B'", (11, 14)),
+                token(TokenKind::HeredocBody, "inner\n", (28, 35)),
+                token(TokenKind::Semicolon, ";", (14, 15)),
+                token(TokenKind::Word, "x", (16, 17)),
+                token(TokenKind::Newline, "\n", (17, 18)),
+                token(TokenKind::Word, "tr", (38, 40)),
+                token(TokenKind::RedirectHereString, "<<<", (41, 44)),
+                token(TokenKind::Word, "w", (44, 45)),
+                token(TokenKind::Eof, "", (45, 45)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_heredoc() {
+        let mut lexer = Lexer::new("cat <<EOF\nline");
+        assert_eq!(
+            lexer.tokenize_all(),
+            Err(LexError::UnterminatedHeredoc("EOF".to_string(), 6))
+        );
+        let mut lexer = Lexer::new("cat <<EOF");
+        assert_eq!(
+            lexer.tokenize_all(),
+            Err(LexError::UnterminatedHeredoc("EOF".to_string(), 6))
+        );
+    }
+
+    #[test]
     fn test_single_quoted_word() {
         let input = "ls 'foo bar'";
         let mut lexer = Lexer::new(input);
@@ -363,7 +827,7 @@ mod tests {
             tokens,
             vec![
                 token(TokenKind::Word, "ls", (0, 2)),
-                token(TokenKind::Word, "foo bar", (4, 11)),
+                token(TokenKind::Word, "'foo bar'", (3, 12)),
                 token(TokenKind::Eof, "", (12, 12)),
             ]
         );
@@ -378,7 +842,7 @@ mod tests {
             tokens,
             vec![
                 token(TokenKind::Word, "ls", (0, 2)),
-                token(TokenKind::Word, "foo bar", (4, 11)),
+                token(TokenKind::Word, "\"foo bar\"", (3, 12)),
                 token(TokenKind::Eof, "", (12, 12)),
             ]
         );
@@ -393,14 +857,30 @@ mod tests {
             tokens,
             vec![
                 token(TokenKind::Word, "echo", (0, 4)),
-                token(TokenKind::Word, "foo", (6, 9)),
-                token(TokenKind::Word, "bar baz", (12, 19)),
+                token(TokenKind::Word, "'foo'", (5, 10)),
+                token(TokenKind::Word, "\"bar baz\"", (11, 20)),
                 token(TokenKind::Word, "qux", (21, 24)),
                 token(TokenKind::Eof, "", (24, 24)),
             ]
         );
     }
 
+    #[test]
+    fn test_adjacent_quotes_join_one_word() {
+        let input = "echo foo\"bar\"'baz' \"if\"";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize_all().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                token(TokenKind::Word, "echo", (0, 4)),
+                token(TokenKind::Word, "foo\"bar\"'baz'", (5, 18)),
+                token(TokenKind::Word, "\"if\"", (19, 23)),
+                token(TokenKind::Eof, "", (23, 23)),
+            ]
+        );
+    }
+
     #[test]
     fn test_unterminated_single_quote() {
         let input = "echo 'foo";
@@ -439,7 +919,7 @@ mod tests {
                 token(TokenKind::Word, "-l", (3, 5)),
                 token(TokenKind::Pipe, "|", (6, 7)),
                 token(TokenKind::Word, "grep", (8, 12)),
-                token(TokenKind::Word, "foo bar", (14, 21)),
+                token(TokenKind::Word, "'foo bar'", (13, 22)),
                 token(TokenKind::And, "&&", (23, 25)),
                 token(TokenKind::Word, "echo", (26, 30)),
                 token(TokenKind::Word, "done", (31, 35)),
@@ -453,12 +933,190 @@ mod tests {
         let input = "";
         let mut lexer = Lexer::new(input);
         let tokens = lexer.tokenize_all().unwrap();
+        assert_eq!(tokens, vec![token(TokenKind::Eof, "", (0, 0)),]);
+    }
+
+    #[test]
+    fn test_tokenize_background() {
+        let input = "sleep 1& echo ok";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize_all().unwrap();
         assert_eq!(
             tokens,
             vec![
-                token(TokenKind::Eof, "", (0, 0)),
+                token(TokenKind::Word, "sleep", (0, 5)),
+                token(TokenKind::Word, "1", (6, 7)),
+                token(TokenKind::Amp, "&", (7, 8)),
+                token(TokenKind::Word, "echo", (9, 13)),
+                token(TokenKind::Word, "ok", (14, 16)),
+                token(TokenKind::Eof, "", (16, 16)),
             ]
         );
     }
-}
 
+    #[test]
+    fn test_tokenize_keywords_in_command_position() {
+        let input = "if true; then echo if; fi";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize_all().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                token(TokenKind::If, "if", (0, 2)),
+                token(TokenKind::Word, "true", (3, 7)),
+                token(TokenKind::Semicolon, ";", (7, 8)),
+                token(TokenKind::Then, "then", (9, 13)),
+                token(TokenKind::Word, "echo", (14, 18)),
+                token(TokenKind::Word, "if", (19, 21)),
+                token(TokenKind::Semicolon, ";", (21, 22)),
+                token(TokenKind::Fi, "fi", (23, 25)),
+                token(TokenKind::Eof, "", (25, 25)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_newline() {
+        let input = "for x in a\ndo echo $x\ndone";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize_all().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                token(TokenKind::For, "for", (0, 3)),
+                token(TokenKind::Word, "x", (4, 5)),
+                token(TokenKind::Word, "in", (6, 8)),
+                token(TokenKind::Word, "a", (9, 10)),
+                token(TokenKind::Newline, "\n", (10, 11)),
+                token(TokenKind::Do, "do", (11, 13)),
+                token(TokenKind::Word, "echo", (14, 18)),
+                token(TokenKind::Word, "$x", (19, 21)),
+                token(TokenKind::Newline, "\n", (21, 22)),
+                token(TokenKind::Done, "done", (22, 26)),
+                token(TokenKind::Eof, "", (26, 26)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_command_substitution_stays_in_word() {
+        let input = "echo a$(echo \"(b)\" $(echo c))d `echo e` x";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize_all().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                token(TokenKind::Word, "echo", (0, 4)),
+                token(TokenKind::Word, "a$(echo \"(b)\" $(echo c))d", (5, 30)),
+                token(TokenKind::Word, "`echo e`", (31, 39)),
+                token(TokenKind::Word, "x", (40, 41)),
+                token(TokenKind::Eof, "", (41, 41)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_substitution() {
+        let mut lexer = Lexer::new("echo $(echo a");
+        assert_eq!(
+            lexer.tokenize_all(),
+            Err(LexError::UnterminatedSubstitution(5))
+        );
+        let mut lexer = Lexer::new("echo `echo a");
+        assert_eq!(
+            lexer.tokenize_all(),
+            Err(LexError::UnterminatedSubstitution(5))
+        );
+        let mut lexer = Lexer::new("echo ${x:-a b");
+        assert_eq!(
+            lexer.tokenize_all(),
+            Err(LexError::UnterminatedSubstitution(5))
+        );
+    }
+
+    #[test]
+    fn test_parameter_expansion_stays_in_word() {
+        // A `}` in quotes or in a nested substitution does not close it
+        let tokens = Lexer::new("echo ${x:-a b} \"${y:-\"}\"$(echo })}\"")
+            .tokenize_all()
+            .unwrap();
+        assert_eq!(tokens[1].lexeme, "${x:-a b}");
+        assert_eq!(tokens[2].lexeme, "\"${y:-\"}\"$(echo })}\"");
+        assert_eq!(tokens[3].kind, TokenKind::Eof);
+    }
+
+    #[test]
+    fn test_process_substitution() {
+        let tokens = Lexer::new("diff <(sort a) >(tee \")\")x < <(ls) 2>(x)")
+            .tokenize_all()
+            .unwrap();
+        assert_eq!(tokens[1], token(TokenKind::Word, "<(sort a)", (5, 14)));
+        assert_eq!(tokens[2].lexeme, ">(tee \")\")x");
+        assert_eq!(tokens[3].kind, TokenKind::RedirectIn);
+        assert_eq!(tokens[4].lexeme, "<(ls)");
+        // After an fd number it is a redirect followed by `(`
+        assert_eq!(tokens[5].kind, TokenKind::RedirectOut);
+        assert_eq!(tokens[6].kind, TokenKind::LParen);
+    }
+
+    #[test]
+    fn test_tokenize_escapes() {
+        let input = "echo a\\ b \\|c \"x\\\"y\" 'e\\'";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize_all().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                token(TokenKind::Word, "echo", (0, 4)),
+                token(TokenKind::Word, "a\\ b", (5, 9)),
+                token(TokenKind::Word, "\\|c", (10, 13)),
+                token(TokenKind::Word, "\"x\\\"y\"", (14, 20)),
+                token(TokenKind::Word, "'e\\'", (21, 25)),
+                token(TokenKind::Eof, "", (25, 25)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_comments() {
+        let input = "ls # list | wc\necho a#b #c";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize_all().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                token(TokenKind::Word, "ls", (0, 2)),
+                token(TokenKind::Newline, "\n", (14, 15)),
+                token(TokenKind::Word, "echo", (15, 19)),
+                token(TokenKind::Word, "a#b", (20, 23)),
+                token(TokenKind::Eof, "", (26, 26)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_continuation() {
+        let input = "echo ab\\\ncd \\\n\"e\\\nf\"";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize_all().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                token(TokenKind::Word, "echo", (0, 4)),
+                token(TokenKind::Word, "abcd", (5, 11)),
+                token(TokenKind::Word, "\"ef\"", (14, 20)),
+                token(TokenKind::Eof, "", (20, 20)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_incomplete_input_errors() {
+        let error = |src: &str| Lexer::new(src).tokenize_all().unwrap_err();
+        assert_eq!(error("echo a \\"), LexError::TrailingBackslash(7));
+        assert!(error("echo a \\").is_incomplete());
+        assert!(error("echo 'a").is_incomplete());
+        assert!(error("echo $(ls").is_incomplete());
+        assert!(Lexer::new("echo a\\\\").tokenize_all().is_ok());
+    }
+}
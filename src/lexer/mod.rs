@@ -1,6 +1,8 @@
-mod token;
+#[allow(clippy::module_inception)]
 mod lexer;
+mod token;
+mod word;
 
+pub use lexer::{LexError, Lexer, starts_process_substitution, substitution_end};
 pub use token::{Token, TokenKind};
-pub use lexer::{Lexer, LexError};
-
+pub use word::{Word, WordSegment};
@@ -1,28 +1,48 @@
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TokenKind {
-    Word,              // Command name or variable name
-    String,            // Quoted string
-    Number,            // Number
-    Assign,            // =
-    Pipe,              // |
-    And,               // &&
-    Or,                // ||
-    RedirectIn,        // <
-    RedirectOut,       // >
-    RedirectAppend,    // >>
-    RedirectErr,       // 2>
-    Semicolon,         // ;
-    Amp,               // &
-    LParen,            // (
-    RParen,            // )
-    LBrace,            // {
-    RBrace,            // }
-    Dollar,            // $
-    DollarBrace,       // ${ (variable expansion)
-    Backtick,          // `
-    SubstitutionStart, // $(
-    SubstitutionEnd,   // )
-    If, Then, Else, Fi, For, While, Do, Done, // Keywords
+    Word,   // Command name or variable name
+    String, // Quoted string
+    Number, // Number
+    Assign, // =
+    Pipe,   // |
+    And,    // &&
+    Or,     // ||
+    // Redirect operators; the lexeme may start with an fd number (e.g. `2>`)
+    RedirectIn,           // <
+    RedirectOut,          // >
+    RedirectAppend,       // >>
+    RedirectClobber,      // >|
+    RedirectReadWrite,    // <>
+    RedirectDupIn,        // <&
+    RedirectDupOut,       // >&
+    RedirectOutErr,       // &>
+    RedirectHeredoc,      // <<
+    RedirectHeredocStrip, // <<-
+    RedirectHereString,   // <<<
+    HeredocBody,          // Lines following the command up to the delimiter (content only)
+    Semicolon,            // ;
+    Newline,              // \n
+    Amp,                  // &
+    LParen,               // (
+    RParen,               // )
+    LBrace,               // {
+    RBrace,               // }
+    Dollar,               // $
+    DollarBrace,          // ${ (variable expansion)
+    Backtick,             // `
+    SubstitutionStart,    // $(
+    SubstitutionEnd,      // )
+    Arithmetic,           // ((expression)) in command position
+    If,
+    Then,
+    Elif,
+    Else,
+    Fi,
+    For,
+    While,
+    Until,
+    Do,
+    Done, // Keywords
     Eof,
     NotImplemented,
 }
@@ -30,7 +50,46 @@ pub enum TokenKind {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Token {
     pub kind: TokenKind,
-    pub lexeme: String,   // Original string
+    pub lexeme: String,       // Original string
     pub span: (usize, usize), // Position info [start, end)
 }
 
+impl TokenKind {
+    // Reserved words are only recognized in command position (see Lexer::next_token)
+    pub fn keyword(word: &str) -> Option<TokenKind> {
+        match word {
+            "if" => Some(TokenKind::If),
+            "then" => Some(TokenKind::Then),
+            "elif" => Some(TokenKind::Elif),
+            "else" => Some(TokenKind::Else),
+            "fi" => Some(TokenKind::Fi),
+            "for" => Some(TokenKind::For),
+            "while" => Some(TokenKind::While),
+            "until" => Some(TokenKind::Until),
+            "do" => Some(TokenKind::Do),
+            "done" => Some(TokenKind::Done),
+            _ => None,
+        }
+    }
+
+    // Whether the token after this one starts a new command
+    pub fn starts_command(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::Pipe
+                | TokenKind::And
+                | TokenKind::Or
+                | TokenKind::Semicolon
+                | TokenKind::Amp
+                | TokenKind::Newline
+                | TokenKind::LParen
+                | TokenKind::If
+                | TokenKind::Then
+                | TokenKind::Elif
+                | TokenKind::Else
+                | TokenKind::While
+                | TokenKind::Until
+                | TokenKind::Do
+        )
+    }
+}
@@ -0,0 +1,149 @@
+use super::lexer::{starts_process_substitution, starts_substitution, substitution_end};
+
+// One part of a shell word, as written in the source (quotes removed)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WordSegment {
+    Unquoted(String),     // Subject to every expansion
+    SingleQuoted(String), // Taken literally
+    DoubleQuoted(String), // `$` and `` ` `` expand, but without splitting or globbing
+}
+
+// A word split into its quoted and unquoted parts, e.g. foo"bar"'baz'.
+// Tokens and AST nodes keep the raw word text; this recovers its structure for expansion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Word {
+    pub segments: Vec<WordSegment>,
+}
+
+impl Word {
+    pub fn parse(raw: &str) -> Self {
+        let chars: Vec<char> = raw.chars().collect();
+        let mut segments = Vec::new();
+        let mut unquoted = String::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            match chars[i] {
+                quote @ ('\'' | '"') => {
+                    if !unquoted.is_empty() {
+                        segments.push(WordSegment::Unquoted(std::mem::take(&mut unquoted)));
+                    }
+                    let end = quote_end(&chars, i);
+                    let text: String = chars[i + 1..end].iter().collect();
+                    segments.push(if quote == '\'' {
+                        WordSegment::SingleQuoted(text)
+                    } else {
+                        WordSegment::DoubleQuoted(text)
+                    });
+                    i = end + 1;
+                }
+                '\\' => {
+                    // Keep the escape for the expander, but never start a quote with `\'`
+                    unquoted.extend(chars.get(i..i + 2).unwrap_or(&chars[i..]));
+                    i += 2;
+                }
+                _ if starts_substitution(&chars, i) || starts_process_substitution(&chars, i) => {
+                    let end = substitution_end(&chars, i).unwrap_or(chars.len());
+                    unquoted.extend(&chars[i..end]);
+                    i = end;
+                }
+                c => {
+                    unquoted.push(c);
+                    i += 1;
+                }
+            }
+        }
+        if !unquoted.is_empty() {
+            segments.push(WordSegment::Unquoted(unquoted));
+        }
+        Word { segments }
+    }
+
+    // Whether each character of `raw` is outside quotes, escapes and substitutions, so
+    // that it keeps a special meaning (such as `{`, `,` and `}` for brace expansion)
+    pub fn active_chars(raw: &str) -> Vec<bool> {
+        let chars: Vec<char> = raw.chars().collect();
+        let mut active = vec![false; chars.len()];
+        let mut i = 0;
+        while i < chars.len() {
+            i = match chars[i] {
+                '\'' | '"' => quote_end(&chars, i) + 1,
+                '\\' => i + 2,
+                _ if starts_substitution(&chars, i) || starts_process_substitution(&chars, i) => {
+                    substitution_end(&chars, i).unwrap_or(chars.len())
+                }
+                _ => {
+                    active[i] = true;
+                    i + 1
+                }
+            };
+        }
+        active
+    }
+}
+
+// Index of the quote closing the one at `start` (or the end of input if unterminated)
+fn quote_end(chars: &[char], start: usize) -> usize {
+    let mut i = start + 1;
+    while i < chars.len() && chars[i] != chars[start] {
+        if chars[start] == '"' {
+            if starts_substitution(chars, i) {
+                i = substitution_end(chars, i).unwrap_or(chars.len());
+                continue;
+            }
+            if chars[i] == '\\' {
+                i += 1;
+            }
+        }
+        i += 1;
+    }
+    i.min(chars.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adjacent_segments() {
+        let word = Word::parse("foo\"bar $x\"'baz'");
+        assert_eq!(
+            word.segments,
+            vec![
+                WordSegment::Unquoted("foo".to_string()),
+                WordSegment::DoubleQuoted("bar $x".to_string()),
+                WordSegment::SingleQuoted("baz".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quotes_inside_substitution_and_escapes() {
+        let word = Word::parse("a$(echo 'b')\\'\"$(echo \")\")\"");
+        assert_eq!(
+            word.segments,
+            vec![
+                WordSegment::Unquoted("a$(echo 'b')\\'".to_string()),
+                WordSegment::DoubleQuoted("$(echo \")\")".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_active_chars() {
+        let active: String = Word::active_chars("a'b'\\c\"d\"${e}f")
+            .into_iter()
+            .map(|active| if active { '+' } else { '-' })
+            .collect();
+        assert_eq!(active, "+------------+");
+    }
+
+    #[test]
+    fn test_empty_quotes() {
+        assert_eq!(
+            Word::parse("''").segments,
+            vec![WordSegment::SingleQuoted(String::new())]
+        );
+        assert!(Word::parse("").segments.is_empty());
+    }
+}
@@ -1,12 +1,12 @@
-pub mod repl;
-pub mod lexer;
-pub mod parser;
 pub mod ast;
-pub mod expander;
-pub mod executor;
+pub mod config;
+pub mod diagnostic;
 pub mod environment;
-pub mod history;
 pub mod error;
+pub mod executor;
+pub mod expander;
+pub mod history;
 pub mod io;
-pub mod config;
-
+pub mod lexer;
+pub mod parser;
+pub mod repl;
@@ -1,6 +1,10 @@
 fn main() {
     use tiny_shell_rs::repl::Repl;
 
-    Repl::run();
+    // `tiny-shell-rs FILE [ARG...]` runs a script; without arguments the shell is interactive
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.split_first() {
+        Some((path, args)) => std::process::exit(Repl::run_script(path, args)),
+        None => Repl::run(),
+    }
 }
-
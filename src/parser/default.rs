@@ -1,6 +1,6 @@
-use crate::parser::{Parser, ParseError};
-use crate::ast::{AstNode, CommandNode};
+use crate::ast::{AstNode, CommandNode, CompoundNode, RedirectKind};
 use crate::lexer::{Token, TokenKind};
+use crate::parser::{ParseError, Parser};
 
 pub struct DefaultParser<'a> {
     tokens: &'a [Token],
@@ -26,49 +26,113 @@ impl<'a> DefaultParser<'a> {
         match self.next() {
             Some(tok) if matches!(tok.kind, TokenKind::Word) => Ok(tok.lexeme.clone()),
             Some(t) => Err(ParseError::UnexpectedToken {
-                found: format!("{:?}", t.kind),
+                found: describe(t),
                 expected: vec!["Word".to_string()],
-                pos: self.pos,
+                span: t.span,
             }),
             None => Err(ParseError::EmptyInput),
         }
     }
+    fn expect(&mut self, pat: &TokenKind) -> Result<(), ParseError> {
+        if self.consume(pat) {
+            return Ok(());
+        }
+        Err(self.unexpected(vec![format!("{:?}", pat)]))
+    }
     fn consume(&mut self, pat: &TokenKind) -> bool {
-        if let Some(tok) = self.tokens.get(self.pos) {
-            if &tok.kind == pat {
-                self.pos += 1;
-                return true;
-            }
+        if self
+            .tokens
+            .get(self.pos)
+            .is_some_and(|tok| &tok.kind == pat)
+        {
+            self.pos += 1;
+            return true;
         }
         false
     }
+    fn skip_newlines(&mut self) {
+        while self.consume(&TokenKind::Newline) {}
+    }
+    fn unexpected(&self, expected: Vec<String>) -> ParseError {
+        match self.peek() {
+            Some(tok) if tok.kind != TokenKind::Eof => ParseError::UnexpectedToken {
+                found: describe(tok),
+                expected,
+                span: tok.span,
+            },
+            _ => ParseError::UnexpectedEof,
+        }
+    }
 }
 
 // Top-down recursive descent parser
 impl<'a> Parser for DefaultParser<'a> {
     fn parse(&mut self) -> Result<AstNode, ParseError> {
-        if self.tokens.is_empty() {
+        self.skip_newlines();
+        if self.peek().is_none_or(|tok| tok.kind == TokenKind::Eof) {
             return Err(ParseError::EmptyInput);
         }
-        self.parse_sequence()
+        let node = self.parse_sequence()?;
+        if self.peek().is_some_and(|tok| tok.kind != TokenKind::Eof) {
+            return Err(self.unexpected(vec!["Eof".to_string()]));
+        }
+        Ok(node)
     }
 }
 
 impl<'a> DefaultParser<'a> {
     fn parse_sequence(&mut self) -> Result<AstNode, ParseError> {
-        let mut node = self.parse_or()?;
-        while self.consume(&TokenKind::Semicolon) {
-            let rhs = self.parse_or()?;
-            let seq = vec![node, rhs];
-            node = AstNode::Sequence(seq);
+        let mut nodes = self.parse_list()?;
+        if nodes.len() == 1 {
+            Ok(nodes.remove(0))
+        } else {
+            Ok(AstNode::Sequence(nodes))
         }
-        Ok(node)
+    }
+
+    // Commands separated by `;`, `&` or newlines, up to a closing keyword or parenthesis
+    fn parse_list(&mut self) -> Result<Vec<AstNode>, ParseError> {
+        let mut nodes = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.at_list_end() {
+                break;
+            }
+            let node = self.parse_or()?;
+            if self.consume(&TokenKind::Amp) {
+                nodes.push(AstNode::Background(Box::new(node)));
+                continue;
+            }
+            nodes.push(node);
+            if !self.consume(&TokenKind::Semicolon) && !self.consume(&TokenKind::Newline) {
+                break;
+            }
+        }
+        if nodes.is_empty() {
+            return Err(self.unexpected(vec!["Word".to_string()]));
+        }
+        Ok(nodes)
+    }
+
+    fn at_list_end(&self) -> bool {
+        matches!(
+            self.peek().map(|tok| &tok.kind),
+            None | Some(TokenKind::Eof)
+                | Some(TokenKind::RParen)
+                | Some(TokenKind::Then)
+                | Some(TokenKind::Elif)
+                | Some(TokenKind::Else)
+                | Some(TokenKind::Fi)
+                | Some(TokenKind::Do)
+                | Some(TokenKind::Done)
+        )
     }
 
     fn parse_or(&mut self) -> Result<AstNode, ParseError> {
         let mut node = self.parse_and()?;
 
         while self.consume(&TokenKind::Or) {
+            self.skip_newlines();
             let rhs = self.parse_and()?;
             node = AstNode::Or(Box::new(node), Box::new(rhs));
         }
@@ -79,6 +143,7 @@ impl<'a> DefaultParser<'a> {
         let mut node = self.parse_pipeline()?;
 
         while self.consume(&TokenKind::And) {
+            self.skip_newlines();
             let rhs = self.parse_pipeline()?;
             node = AstNode::And(Box::new(node), Box::new(rhs));
         }
@@ -90,80 +155,304 @@ impl<'a> DefaultParser<'a> {
         let mut nodes = vec![self.parse_command_like()?];
         // Connected by pipes
         while self.consume(&TokenKind::Pipe) {
+            self.skip_newlines();
             let rhs = self.parse_command_like()?;
             nodes.push(rhs);
         }
-        // Add a redirect to the entire pipe
+        // Redirects belong to the stages, not to the whole pipeline
         if nodes.len() == 1 {
-            return self.parse_with_redirect(nodes.remove(0));
+            Ok(nodes.remove(0))
         } else {
-            return self.parse_with_redirect(AstNode::Pipeline(nodes))
+            Ok(AstNode::Pipeline(nodes))
         }
     }
 
-    // build "pipe elements" such as commands and subshells
+    // build "pipe elements" such as commands and subshells, with their redirects
     fn parse_command_like(&mut self) -> Result<AstNode, ParseError> {
-        if self.consume(&TokenKind::LParen) {
+        let node = match self.peek().map(|tok| &tok.kind) {
+            Some(
+                TokenKind::LParen
+                | TokenKind::If
+                | TokenKind::While
+                | TokenKind::Until
+                | TokenKind::For
+                | TokenKind::Arithmetic,
+            ) => self.parse_compound()?,
+            _ => return self.parse_simple_command(),
+        };
+        self.parse_with_redirect(node)
+    }
+
+    fn parse_compound(&mut self) -> Result<AstNode, ParseError> {
+        if let Some(open) = self
+            .peek()
+            .filter(|tok| tok.kind == TokenKind::LParen)
+            .map(|tok| tok.span)
+        {
+            self.pos += 1;
             let node = self.parse_sequence()?;
             if !self.consume(&TokenKind::RParen) {
-                return Err(ParseError::UnmatchedParen {
-                    pos: self.pos,
-                });
+                if self.peek().is_none_or(|tok| tok.kind == TokenKind::Eof) {
+                    return Err(ParseError::UnexpectedEof);
+                }
+                return Err(ParseError::UnmatchedParen { span: open });
             }
             Ok(AstNode::Subshell(Box::new(node)))
+        } else if self.consume(&TokenKind::If) {
+            Ok(AstNode::Compound(self.parse_if()?))
+        } else if self.consume(&TokenKind::While) {
+            let (cond, body) = self.parse_loop()?;
+            Ok(AstNode::Compound(CompoundNode::While { cond, body }))
+        } else if self.consume(&TokenKind::Until) {
+            let (cond, body) = self.parse_loop()?;
+            Ok(AstNode::Compound(CompoundNode::Until { cond, body }))
+        } else if self.consume(&TokenKind::For) {
+            Ok(AstNode::Compound(self.parse_for()?))
+        } else if let Some(tok) = self.peek().filter(|tok| tok.kind == TokenKind::Arithmetic) {
+            // The lexeme is `((expr))`
+            let expr = tok.lexeme[2..tok.lexeme.len() - 2].to_string();
+            self.pos += 1;
+            Ok(AstNode::Compound(CompoundNode::Arithmetic(expr)))
+        } else {
+            Err(self.unexpected(vec!["Compound".to_string()]))
+        }
+    }
+
+    // Words and redirects may be mixed in any order (`> out echo hi`, `echo hi > out more`)
+    fn parse_simple_command(&mut self) -> Result<AstNode, ParseError> {
+        let mut args = Vec::new();
+        let mut spans = Vec::new();
+        let mut redirects = Vec::new();
+        while let Some(tok) = self.peek() {
+            if let TokenKind::Word = &tok.kind {
+                args.push(tok.lexeme.clone());
+                spans.push(tok.span);
+                self.pos += 1;
+            } else if let Some(kind) = redirect_kind(tok) {
+                redirects.push(self.parse_redirect(kind)?);
+            } else {
+                break;
+            }
+        }
+        if args.is_empty() && !redirects.is_empty() {
+            // Redirects without a command (`> file`) are not supported
+            let end = self
+                .tokens
+                .last()
+                .map_or((0, 0), |tok| (tok.span.1, tok.span.1));
+            return Err(ParseError::UnexpectedToken {
+                found: self.peek().map_or("end of input".to_string(), describe),
+                expected: vec!["Word".to_string()],
+                span: self.peek().map_or(end, |tok| tok.span),
+            });
+        }
+        if args.is_empty() {
+            // At the end of the input this is e.g. `ls |` or `a &&`, waiting for a command
+            return Err(self.unexpected(vec!["Word".to_string()]));
+        }
+        let node = AstNode::Command(CommandNode {
+            name: args[0].clone(),
+            args: args[1..].to_vec(),
+            kind: crate::ast::CommandKind::Simple,
+            spans,
+        });
+        Ok(wrap_redirects(node, redirects))
+    }
+
+    // if COND; then LIST; [elif COND; then LIST;]... [else LIST;] fi
+    // `elif` is represented as a nested `If` in the else branch.
+    fn parse_if(&mut self) -> Result<CompoundNode, ParseError> {
+        let cond = Box::new(self.parse_sequence()?);
+        self.expect(&TokenKind::Then)?;
+        let then_branch = self.parse_list()?;
+        let else_branch = if self.consume(&TokenKind::Elif) {
+            // The nested `If` consumes the closing `fi`
+            return Ok(CompoundNode::If {
+                cond,
+                then_branch,
+                else_branch: Some(vec![AstNode::Compound(self.parse_if()?)]),
+            });
+        } else if self.consume(&TokenKind::Else) {
+            Some(self.parse_list()?)
         } else {
-            // Command alone
-            let mut args = Vec::new();
+            None
+        };
+        self.expect(&TokenKind::Fi)?;
+        Ok(CompoundNode::If {
+            cond,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    // while/until COND; do LIST; done
+    fn parse_loop(&mut self) -> Result<(Box<AstNode>, Vec<AstNode>), ParseError> {
+        let cond = Box::new(self.parse_sequence()?);
+        let body = self.parse_do_group()?;
+        Ok((cond, body))
+    }
+
+    // for NAME [in WORD...]; do LIST; done
+    fn parse_for(&mut self) -> Result<CompoundNode, ParseError> {
+        let var = self.expect_word()?;
+        if !is_name(&var) {
+            return Err(ParseError::UnexpectedToken {
+                found: var,
+                expected: vec!["Name".to_string()],
+                span: self.tokens[self.pos - 1].span,
+            });
+        }
+        self.skip_newlines();
+        let (items, spans) = if self
+            .peek()
+            .is_some_and(|tok| tok.kind == TokenKind::Word && tok.lexeme == "in")
+        {
+            self.pos += 1;
+            let mut items = Vec::new();
+            let mut spans = Vec::new();
             while let Some(tok) = self.peek() {
-                if let TokenKind::Word = &tok.kind {
-                    args.push(tok.lexeme.clone());
-                    self.pos += 1;
-                } else {
+                if tok.kind != TokenKind::Word {
                     break;
                 }
+                items.push(tok.lexeme.clone());
+                spans.push(tok.span);
+                self.pos += 1;
             }
-            if args.is_empty() {
-                return Err(ParseError::EmptyInput);
+            if !self.consume(&TokenKind::Semicolon) && !self.consume(&TokenKind::Newline) {
+                return Err(self.unexpected(vec!["Semicolon".to_string(), "Newline".to_string()]));
             }
-            Ok(AstNode::Command(CommandNode {
-                name: args[0].clone(),
-                args: args[1..].to_vec(),
-                kind: crate::ast::CommandKind::Simple,
-            }))
+            (items, spans)
+        } else {
+            // Without `in`, iterate over the positional parameters
+            self.consume(&TokenKind::Semicolon);
+            (vec!["\"$@\"".to_string()], Vec::new())
+        };
+        let body = self.parse_do_group()?;
+        Ok(CompoundNode::For {
+            var,
+            items,
+            spans,
+            body,
+        })
+    }
+
+    fn parse_do_group(&mut self) -> Result<Vec<AstNode>, ParseError> {
+        self.skip_newlines();
+        self.expect(&TokenKind::Do)?;
+        let body = self.parse_list()?;
+        self.expect(&TokenKind::Done)?;
+        Ok(body)
+    }
+
+    // Add redirects after a compound command or subshell
+    fn parse_with_redirect(&mut self, node: AstNode) -> Result<AstNode, ParseError> {
+        let mut redirects = Vec::new();
+        while let Some(kind) = self.peek().and_then(redirect_kind) {
+            redirects.push(self.parse_redirect(kind)?);
         }
+        Ok(wrap_redirects(node, redirects))
     }
 
-    // Add a redirect after any node
-    fn parse_with_redirect(&mut self, mut node: AstNode) -> Result<AstNode, ParseError> {
-        loop {
-            if self.consume(&TokenKind::RedirectOut) {
-                let filename = self.expect_word()?;
-                node = AstNode::Redirect {
-                    node: Box::new(node),
-                    kind: crate::ast::RedirectKind::Out,
-                    file: filename,
+    // The operator (already recognized as `kind`), its word and the word's span
+    fn parse_redirect(&mut self, kind: RedirectKind) -> Result<Redirect, ParseError> {
+        self.pos += 1;
+        let word = self.expect_word()?;
+        let span = self.tokens[self.pos - 1].span;
+        match kind {
+            // The lexer puts the body right after the delimiter
+            RedirectKind::Heredoc { fd, strip_tabs, .. } => {
+                let (body, span) = match self.peek() {
+                    Some(tok) if tok.kind == TokenKind::HeredocBody => {
+                        (tok.lexeme.clone(), tok.span)
+                    }
+                    _ => return Err(self.unexpected(vec!["HeredocBody".to_string()])),
                 };
-            } else if self.consume(&TokenKind::RedirectIn) {
-                let filename = self.expect_word()?;
-                node = AstNode::Redirect {
-                    node: Box::new(node),
-                    kind: crate::ast::RedirectKind::In,
-                    file: filename,
-                };
-            } else {
-                break;
+                self.pos += 1;
+                Ok((
+                    RedirectKind::Heredoc {
+                        fd,
+                        delimiter: word,
+                        strip_tabs,
+                    },
+                    body,
+                    span,
+                ))
             }
+            kind => Ok((kind, word, span)),
         }
-        Ok(node)
     }
 }
 
+// A redirect's kind, its word (or here-document body) and the word's span
+type Redirect = (RedirectKind, String, (usize, usize));
+
+// Redirects apply left to right (`> out 2>&1`), so the first one becomes the outermost node
+fn wrap_redirects(mut node: AstNode, redirects: Vec<Redirect>) -> AstNode {
+    for (kind, file, span) in redirects.into_iter().rev() {
+        node = AstNode::Redirect {
+            node: Box::new(node),
+            kind,
+            file,
+            span: Some(span),
+        };
+    }
+    node
+}
+
+// The redirect a token starts, with its fd number (`2>`) or the operator's default
+fn redirect_kind(tok: &Token) -> Option<RedirectKind> {
+    let digits: String = tok
+        .lexeme
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    let fd = |default| digits.parse().unwrap_or(default);
+    match tok.kind {
+        TokenKind::RedirectIn => Some(RedirectKind::In(fd(0))),
+        TokenKind::RedirectOut => Some(RedirectKind::Out(fd(1))),
+        TokenKind::RedirectAppend => Some(RedirectKind::Append(fd(1))),
+        TokenKind::RedirectClobber => Some(RedirectKind::Clobber(fd(1))),
+        TokenKind::RedirectReadWrite => Some(RedirectKind::ReadWrite(fd(0))),
+        TokenKind::RedirectDupIn => Some(RedirectKind::DupIn(fd(0))),
+        TokenKind::RedirectDupOut => Some(RedirectKind::DupOut(fd(1))),
+        TokenKind::RedirectOutErr => Some(RedirectKind::OutErr),
+        // The delimiter is filled in by the parser
+        TokenKind::RedirectHeredoc | TokenKind::RedirectHeredocStrip => {
+            Some(RedirectKind::Heredoc {
+                fd: fd(0),
+                delimiter: String::new(),
+                strip_tabs: tok.kind == TokenKind::RedirectHeredocStrip,
+            })
+        }
+        TokenKind::RedirectHereString => Some(RedirectKind::HereString(fd(0))),
+        _ => None,
+    }
+}
+
+// How a token is shown in error messages
+fn describe(tok: &Token) -> String {
+    match tok.kind {
+        TokenKind::Eof => "end of input".to_string(),
+        TokenKind::Newline => "newline".to_string(),
+        _ => tok.lexeme.clone(),
+    }
+}
+
+fn is_name(word: &str) -> bool {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ast::{AstNode, CommandKind, CommandNode, CompoundNode, RedirectKind};
     use crate::lexer::Lexer;
-    use crate::ast::{AstNode, RedirectKind, CommandNode, CommandKind};
 
+    // The AST without the source spans of its words (see test_word_spans)
     fn lex_and_parse(src: &str) -> AstNode {
         let mut lexer = Lexer::new(src);
         let tokens = lexer.tokenize_all();
@@ -174,7 +463,61 @@ mod tests {
                 panic!("Failed to tokenize input");
             }
         };
-        parser.parse().unwrap()
+        without_spans(parser.parse().unwrap())
+    }
+
+    fn without_spans(node: AstNode) -> AstNode {
+        let list = |nodes: Vec<AstNode>| nodes.into_iter().map(without_spans).collect::<Vec<_>>();
+        let boxed = |node: Box<AstNode>| Box::new(without_spans(*node));
+        match node {
+            AstNode::Command(cmd) => AstNode::Command(CommandNode {
+                spans: Vec::new(),
+                ..cmd
+            }),
+            AstNode::Pipeline(nodes) => AstNode::Pipeline(list(nodes)),
+            AstNode::Redirect {
+                node, kind, file, ..
+            } => AstNode::Redirect {
+                node: boxed(node),
+                kind,
+                file,
+                span: None,
+            },
+            AstNode::Sequence(nodes) => AstNode::Sequence(list(nodes)),
+            AstNode::And(left, right) => AstNode::And(boxed(left), boxed(right)),
+            AstNode::Or(left, right) => AstNode::Or(boxed(left), boxed(right)),
+            AstNode::Subshell(node) => AstNode::Subshell(boxed(node)),
+            AstNode::Background(node) => AstNode::Background(boxed(node)),
+            AstNode::Compound(compound) => AstNode::Compound(match compound {
+                CompoundNode::Group(nodes) => CompoundNode::Group(list(nodes)),
+                CompoundNode::If {
+                    cond,
+                    then_branch,
+                    else_branch,
+                } => CompoundNode::If {
+                    cond: boxed(cond),
+                    then_branch: list(then_branch),
+                    else_branch: else_branch.map(list),
+                },
+                CompoundNode::While { cond, body } => CompoundNode::While {
+                    cond: boxed(cond),
+                    body: list(body),
+                },
+                CompoundNode::Until { cond, body } => CompoundNode::Until {
+                    cond: boxed(cond),
+                    body: list(body),
+                },
+                CompoundNode::For {
+                    var, items, body, ..
+                } => CompoundNode::For {
+                    var,
+                    items,
+                    spans: Vec::new(),
+                    body: list(body),
+                },
+                CompoundNode::Arithmetic(expr) => CompoundNode::Arithmetic(expr),
+            }),
+        }
     }
 
     // Parsing empty input (ParseError::EmptyInput)
@@ -192,6 +535,46 @@ mod tests {
         assert!(matches!(parser.parse(), Err(ParseError::EmptyInput)));
     }
 
+    // Input that more lines could complete (ParseError::UnexpectedEof)
+    #[test]
+    fn test_incomplete_input() {
+        let parse =
+            |src: &str| DefaultParser::new(&Lexer::new(src).tokenize_all().unwrap()).parse();
+        for src in [
+            "ls |",
+            "a &&",
+            "a ||\n",
+            "(echo x",
+            "if true; then",
+            "while true\ndo echo",
+            "for i in a b",
+        ] {
+            assert!(
+                matches!(parse(src), Err(ParseError::UnexpectedEof)),
+                "{}",
+                src
+            );
+        }
+        // Real errors stay errors and point at the offending token
+        assert_eq!(
+            parse("echo a; fi"),
+            Err(ParseError::UnexpectedToken {
+                found: "fi".to_string(),
+                expected: vec!["Eof".to_string()],
+                span: (8, 10),
+            })
+        );
+        assert_eq!(parse("(echo x; fi)").unwrap_err().span(), Some((0, 1)));
+        assert!(matches!(
+            parse("echo )"),
+            Err(ParseError::UnexpectedToken { .. })
+        ));
+        assert!(matches!(
+            parse("ls >"),
+            Err(ParseError::UnexpectedToken { .. })
+        ));
+    }
+
     // Simple command (e.g., echo hello)
     #[test]
     fn test_simple_command() {
@@ -202,6 +585,7 @@ mod tests {
                 name: "echo".to_string(),
                 args: vec!["hello".to_string()],
                 kind: CommandKind::Simple,
+                spans: Vec::new(),
             })
         );
     }
@@ -216,6 +600,7 @@ mod tests {
                 name: "ls".to_string(),
                 args: vec!["-l".to_string(), "/tmp".to_string()],
                 kind: CommandKind::Simple,
+                spans: Vec::new(),
             })
         );
     }
@@ -231,11 +616,13 @@ mod tests {
                     name: "ls".to_string(),
                     args: vec![],
                     kind: CommandKind::Simple,
+                    spans: Vec::new(),
                 }),
                 AstNode::Command(CommandNode {
                     name: "pwd".to_string(),
                     args: vec![],
                     kind: CommandKind::Simple,
+                    spans: Vec::new(),
                 })
             ])
         );
@@ -253,17 +640,20 @@ mod tests {
                         name: "true".to_string(),
                         args: vec![],
                         kind: CommandKind::Simple,
+                        spans: Vec::new(),
                     })),
                     Box::new(AstNode::Command(CommandNode {
                         name: "false".to_string(),
                         args: vec![],
                         kind: CommandKind::Simple,
+                        spans: Vec::new(),
                     }))
                 )),
                 Box::new(AstNode::Command(CommandNode {
                     name: "true".to_string(),
                     args: vec![],
                     kind: CommandKind::Simple,
+                    spans: Vec::new(),
                 }))
             )
         );
@@ -280,11 +670,13 @@ mod tests {
                     name: "ls".to_string(),
                     args: vec![],
                     kind: CommandKind::Simple,
+                    spans: Vec::new(),
                 }),
                 AstNode::Command(CommandNode {
                     name: "grep".to_string(),
                     args: vec!["foo".to_string()],
                     kind: CommandKind::Simple,
+                    spans: Vec::new(),
                 }),
             ])
         );
@@ -301,9 +693,11 @@ mod tests {
                     name: "echo".to_string(),
                     args: vec!["foo".to_string()],
                     kind: CommandKind::Simple,
+                    spans: Vec::new(),
                 })),
-                kind: RedirectKind::Out,
+                kind: RedirectKind::Out(1),
                 file: "out.txt".to_string(),
+                span: None,
             }
         );
 
@@ -315,9 +709,11 @@ mod tests {
                     name: "cat".to_string(),
                     args: vec![],
                     kind: CommandKind::Simple,
+                    spans: Vec::new(),
                 })),
-                kind: RedirectKind::In,
+                kind: RedirectKind::In(0),
                 file: "in.txt".to_string(),
+                span: None,
             }
         );
     }
@@ -328,20 +724,20 @@ mod tests {
         let ast = lex_and_parse("(echo foo; ls)");
         assert_eq!(
             ast,
-            AstNode::Subshell(
-                Box::new(AstNode::Sequence(vec![
-                    AstNode::Command(CommandNode {
-                        name: "echo".to_string(),
-                        args: vec!["foo".to_string()],
-                        kind: CommandKind::Simple,
-                    }),
-                    AstNode::Command(CommandNode {
-                        name: "ls".to_string(),
-                        args: vec![],
-                        kind: CommandKind::Simple,
-                    })
-                ])
-            ))
+            AstNode::Subshell(Box::new(AstNode::Sequence(vec![
+                AstNode::Command(CommandNode {
+                    name: "echo".to_string(),
+                    args: vec!["foo".to_string()],
+                    kind: CommandKind::Simple,
+                    spans: Vec::new(),
+                }),
+                AstNode::Command(CommandNode {
+                    name: "ls".to_string(),
+                    args: vec![],
+                    kind: CommandKind::Simple,
+                    spans: Vec::new(),
+                })
+            ])))
         );
     }
 
@@ -352,28 +748,30 @@ mod tests {
         assert_eq!(
             ast,
             AstNode::And(
-                Box::new(AstNode::Subshell(
-                    Box::new(AstNode::Pipeline(vec![
-                        AstNode::Command(CommandNode {
-                            name: "ls".to_string(),
-                            args: vec![],
-                            kind: CommandKind::Simple,
-                        }),
-                        AstNode::Command(CommandNode {
-                            name: "grep".to_string(),
-                            args: vec!["foo".to_string()],
-                            kind: CommandKind::Simple,
-                        })
-                    ])
-                ))),
+                Box::new(AstNode::Subshell(Box::new(AstNode::Pipeline(vec![
+                    AstNode::Command(CommandNode {
+                        name: "ls".to_string(),
+                        args: vec![],
+                        kind: CommandKind::Simple,
+                        spans: Vec::new(),
+                    }),
+                    AstNode::Command(CommandNode {
+                        name: "grep".to_string(),
+                        args: vec!["foo".to_string()],
+                        kind: CommandKind::Simple,
+                        spans: Vec::new(),
+                    })
+                ])))),
                 Box::new(AstNode::Redirect {
                     node: Box::new(AstNode::Command(CommandNode {
                         name: "echo".to_string(),
                         args: vec!["ok".to_string()],
                         kind: CommandKind::Simple,
+                        spans: Vec::new(),
                     })),
-                    kind: RedirectKind::Out,
+                    kind: RedirectKind::Out(1),
                     file: "result.txt".to_string(),
+                    span: None,
                 })
             )
         );
@@ -417,7 +815,7 @@ mod tests {
     //     assert!(matches!(ast, Err(ParseError::EmptyInput)));
     // }
 
-    // Multiple redirections (e.g., echo foo > out.txt < in.txt)
+    // Multiple redirections (e.g., echo foo > out.txt < in.txt); the first one is outermost
     #[test]
     fn test_multiple_redirections() {
         let ast = lex_and_parse("echo foo > out.txt < in.txt");
@@ -429,14 +827,134 @@ mod tests {
                         name: "echo".to_string(),
                         args: vec!["foo".to_string()],
                         kind: CommandKind::Simple,
+                        spans: Vec::new(),
                     })),
-                    kind: RedirectKind::Out,
-                    file: "out.txt".to_string(),
+                    kind: RedirectKind::In(0),
+                    file: "in.txt".to_string(),
+                    span: None,
                 }),
-                kind: RedirectKind::In,
-                file: "in.txt".to_string(),
+                kind: RedirectKind::Out(1),
+                file: "out.txt".to_string(),
+                span: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_fd_redirections() {
+        let ast = lex_and_parse("cmd 2>>err.log 3<>rw >|out 2>&1 0<&- &>all");
+        assert_eq!(
+            ast.to_string(),
+            "cmd 2>> err.log 3<> rw >| out 2>&1 <&- &> all"
+        );
+
+        let mut node = &ast;
+        let mut kinds = Vec::new();
+        while let AstNode::Redirect {
+            node: inner, kind, ..
+        } = node
+        {
+            kinds.push(kind.clone());
+            node = inner;
+        }
+        assert_eq!(
+            kinds,
+            vec![
+                RedirectKind::Append(2),
+                RedirectKind::ReadWrite(3),
+                RedirectKind::Clobber(1),
+                RedirectKind::DupOut(2),
+                RedirectKind::DupIn(0),
+                RedirectKind::OutErr,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_redirects_belong_to_pipeline_stages() {
+        let ast = lex_and_parse("cat < in | sort > out");
+        match &ast {
+            AstNode::Pipeline(stages) => {
+                assert!(
+                    matches!(&stages[0], AstNode::Redirect { kind: RedirectKind::In(0), file, .. } if file == "in")
+                );
+                assert!(
+                    matches!(&stages[1], AstNode::Redirect { kind: RedirectKind::Out(1), file, .. } if file == "out")
+                );
             }
+            other => panic!("expected a pipeline, got {:?}", other),
+        }
+        assert_eq!(ast.to_string(), "cat < in | sort > out");
+    }
+
+    #[test]
+    fn test_redirects_between_words() {
+        assert_eq!(lex_and_parse("> out echo hi").to_string(), "echo hi > out");
+        assert_eq!(
+            lex_and_parse("echo hi 2>&1 > out more").to_string(),
+            "echo hi more 2>&1 > out"
         );
+        assert_eq!(lex_and_parse("(ls) > out").to_string(), "(ls) > out");
+        assert_eq!(
+            lex_and_parse("cat <(ls) < <(ls)").to_string(),
+            "cat <(ls) < <(ls)"
+        );
+        assert!(matches!(
+            DefaultParser::new(&Lexer::new("> out").tokenize_all().unwrap()).parse(),
+            Err(ParseError::UnexpectedToken { .. })
+        ));
+    }
+
+    #[test]
+    fn test_heredoc_redirections() {
+        let ast = lex_and_parse("cat <<'EOF' 3<<<word\nhello $x\nEOF\n");
+        assert_eq!(ast.to_string(), "cat <<'EOF' 3<<< word");
+        match ast {
+            AstNode::Redirect {
+                node, kind, file, ..
+            } => {
+                assert!(kind.is_quoted_heredoc());
+                assert_eq!(file, "hello $x\n");
+                assert!(matches!(
+                    *node,
+                    AstNode::Redirect {
+                        kind: RedirectKind::HereString(3),
+                        ..
+                    }
+                ));
+            }
+            other => panic!("expected a redirect, got {:?}", other),
+        }
+    }
+
+    // Byte ranges of the words in the source, for locating expansion errors
+    #[test]
+    fn test_word_spans() {
+        let parse = |src: &str| {
+            DefaultParser::new(&Lexer::new(src).tokenize_all().unwrap())
+                .parse()
+                .unwrap()
+        };
+        match parse("é \"a b\" x > out") {
+            AstNode::Redirect { node, span, .. } => {
+                assert_eq!(span, Some((13, 16)));
+                assert!(
+                    matches!(*node, AstNode::Command(cmd) if cmd.spans == [(0, 2), (3, 8), (9, 10)])
+                );
+            }
+            other => panic!("expected a redirect, got {:?}", other),
+        }
+        match parse("for f in a \"b\"; do :; done") {
+            AstNode::Compound(CompoundNode::For { spans, .. }) => {
+                assert_eq!(spans, [(9, 10), (11, 14)])
+            }
+            other => panic!("expected a for loop, got {:?}", other),
+        }
+        // The span of a here-document is that of its body
+        match parse("cat <<EOF\nhello $x\nEOF\n") {
+            AstNode::Redirect { span, .. } => assert_eq!(span, Some((10, 19))),
+            other => panic!("expected a redirect, got {:?}", other),
+        }
     }
 
     // Complex syntax combinations (e.g., (ls | grep foo) && echo ok > result.txt)
@@ -453,7 +971,7 @@ mod tests {
     //                     args: vec!["-l".to_string()],
     //                     kind: CommandKind::Simple,
     //                 })),
-    //                 kind: RedirectKind::Out,
+    //                 kind: RedirectKind::Out(1),
     //                 file: "ls.txt".to_string(),
     //             }),
     //             Box::new(AstNode::Pipeline(
@@ -463,7 +981,7 @@ mod tests {
     //                         args: vec!["ls.txt".to_string()],
     //                         kind: CommandKind::Simple,
     //                     })),
-    //                     kind: RedirectKind::In,
+    //                     kind: RedirectKind::In(0),
     //                     file: "".to_string(), // Input redirection does not require a file
     //                 }),
     //                 Box::new(AstNode::Pipeline(
@@ -478,7 +996,7 @@ mod tests {
     //                             args: vec![],
     //                             kind: CommandKind::Simple,
     //                         })),
-    //                         kind: RedirectKind::Out,
+    //                         kind: RedirectKind::Out(1),
     //                         file: "output.txt".to_string(),
     //                     }),
     //                 )),
@@ -486,4 +1004,154 @@ mod tests {
     //         )
     //     );
     // }
+
+    fn cmd(name: &str, args: &[&str]) -> AstNode {
+        AstNode::Command(CommandNode {
+            name: name.to_string(),
+            args: args.iter().map(|s| s.to_string()).collect(),
+            kind: CommandKind::Simple,
+            spans: Vec::new(),
+        })
+    }
+
+    // Background execution (e.g., sleep 1 & ls | wc &)
+    #[test]
+    fn test_background() {
+        let ast = lex_and_parse("sleep 1 & ls | wc &");
+        assert_eq!(
+            ast,
+            AstNode::Sequence(vec![
+                AstNode::Background(Box::new(cmd("sleep", &["1"]))),
+                AstNode::Background(Box::new(AstNode::Pipeline(vec![
+                    cmd("ls", &[]),
+                    cmd("wc", &[])
+                ]))),
+            ])
+        );
+
+        let ast = lex_and_parse("true && sleep 1 &");
+        assert_eq!(
+            ast,
+            AstNode::Background(Box::new(AstNode::And(
+                Box::new(cmd("true", &[])),
+                Box::new(cmd("sleep", &["1"])),
+            )))
+        );
+    }
+
+    // if/elif/else (e.g., if a; then b; elif c; then d; else e; fi)
+    #[test]
+    fn test_if_elif_else() {
+        let ast = lex_and_parse("if a; then b; elif c; then d; else e; fi");
+        assert_eq!(
+            ast,
+            AstNode::Compound(CompoundNode::If {
+                cond: Box::new(cmd("a", &[])),
+                then_branch: vec![cmd("b", &[])],
+                else_branch: Some(vec![AstNode::Compound(CompoundNode::If {
+                    cond: Box::new(cmd("c", &[])),
+                    then_branch: vec![cmd("d", &[])],
+                    else_branch: Some(vec![cmd("e", &[])]),
+                })]),
+            })
+        );
+    }
+
+    // while/until loops spanning several lines
+    #[test]
+    fn test_while_and_until() {
+        let ast = lex_and_parse("while test -f lock\ndo\n  sleep 1; echo wait\ndone");
+        assert_eq!(
+            ast,
+            AstNode::Compound(CompoundNode::While {
+                cond: Box::new(cmd("test", &["-f", "lock"])),
+                body: vec![cmd("sleep", &["1"]), cmd("echo", &["wait"])],
+            })
+        );
+
+        let ast = lex_and_parse("until false; do echo x; done > out.txt");
+        assert_eq!(
+            ast,
+            AstNode::Redirect {
+                node: Box::new(AstNode::Compound(CompoundNode::Until {
+                    cond: Box::new(cmd("false", &[])),
+                    body: vec![cmd("echo", &["x"])],
+                })),
+                kind: RedirectKind::Out(1),
+                file: "out.txt".to_string(),
+                span: None,
+            }
+        );
+    }
+
+    // for NAME in WORDS (keywords are plain words outside command position)
+    #[test]
+    fn test_for_loop() {
+        let ast = lex_and_parse("for f in a done c; do echo $f; done && echo ok");
+        assert_eq!(
+            ast,
+            AstNode::And(
+                Box::new(AstNode::Compound(CompoundNode::For {
+                    var: "f".to_string(),
+                    items: vec!["a".to_string(), "done".to_string(), "c".to_string()],
+                    spans: Vec::new(),
+                    body: vec![cmd("echo", &["$f"])],
+                })),
+                Box::new(cmd("echo", &["ok"])),
+            )
+        );
+    }
+
+    // ((expr)) in command position; elsewhere `((` stays nested subshells
+    #[test]
+    fn test_arithmetic_command() {
+        let ast = lex_and_parse("while (( i < 3 )); do (( i++ )); done");
+        assert_eq!(
+            ast,
+            AstNode::Compound(CompoundNode::While {
+                cond: Box::new(AstNode::Compound(CompoundNode::Arithmetic(
+                    " i < 3 ".to_string()
+                ))),
+                body: vec![AstNode::Compound(CompoundNode::Arithmetic(
+                    " i++ ".to_string()
+                ))],
+            })
+        );
+        assert_eq!(
+            lex_and_parse("((cd /tmp) && ls)").to_string(),
+            "((cd /tmp) && ls)"
+        );
+        assert_eq!(
+            lex_and_parse("echo $((1 + 2))"),
+            cmd("echo", &["$((1 + 2))"])
+        );
+        assert!(matches!(
+            Lexer::new("(( 1 + (2 )").tokenize_all(),
+            Err(crate::lexer::LexError::UnterminatedArithmetic(0))
+        ));
+    }
+
+    #[test]
+    fn test_unterminated_compound() {
+        let mut lexer = Lexer::new("if true; then echo x");
+        let tokens = lexer.tokenize_all().unwrap();
+        let mut parser = DefaultParser::new(&tokens);
+        assert!(matches!(parser.parse(), Err(ParseError::UnexpectedEof)));
+
+        let mut lexer = Lexer::new("while true; do; done");
+        let tokens = lexer.tokenize_all().unwrap();
+        let mut parser = DefaultParser::new(&tokens);
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::UnexpectedToken { .. })
+        ));
+
+        let mut lexer = Lexer::new("echo a; fi");
+        let tokens = lexer.tokenize_all().unwrap();
+        let mut parser = DefaultParser::new(&tokens);
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::UnexpectedToken { .. })
+        ));
+    }
 }
@@ -1,6 +1,6 @@
 mod default;
+#[allow(clippy::module_inception)]
 mod parser;
 
 pub use default::DefaultParser;
-pub use parser::{Parser, ParseError};
-
+pub use parser::{ParseError, Parser};
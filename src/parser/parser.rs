@@ -1,5 +1,5 @@
+use crate::ast::AstNode;
 use std::fmt;
-use crate::ast::{AstNode};
 
 pub trait Parser {
     fn parse(&mut self) -> Result<AstNode, ParseError>;
@@ -8,31 +8,55 @@ pub trait Parser {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ParseError {
     UnexpectedEof,
+    // Spans are byte ranges of the offending token in the source
     UnexpectedToken {
         found: String,
         expected: Vec<String>,
-        pos: usize,
+        span: (usize, usize),
     },
     UnmatchedParen {
-        pos: usize,
+        span: (usize, usize),
     },
     UnclosedQuote {
-        pos: usize,
+        span: (usize, usize),
         quote: char,
     },
     EmptyInput,
 }
+
+impl ParseError {
+    pub fn span(&self) -> Option<(usize, usize)> {
+        match self {
+            ParseError::UnexpectedToken { span, .. }
+            | ParseError::UnmatchedParen { span }
+            | ParseError::UnclosedQuote { span, .. } => Some(*span),
+            ParseError::UnexpectedEof | ParseError::EmptyInput => None,
+        }
+    }
+}
+
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ParseError::UnexpectedEof => write!(f, "Unexpected end of input"),
-            ParseError::UnexpectedToken { found, expected, pos } => {
-                write!(f, "Unexpected token '{}' at position {}. Expected: {:?}", found, pos, expected)
+            ParseError::UnexpectedToken {
+                found,
+                expected,
+                span,
+            } => {
+                write!(
+                    f,
+                    "Unexpected token '{}' at position {}. Expected: {:?}",
+                    found, span.0, expected
+                )
+            }
+            ParseError::UnmatchedParen { span } => {
+                write!(f, "Unmatched parenthesis at position {}", span.0)
+            }
+            ParseError::UnclosedQuote { span, quote } => {
+                write!(f, "Unclosed quote '{}' at position {}", quote, span.0)
             }
-            ParseError::UnmatchedParen { pos } => write!(f, "Unmatched parenthesis at position {}", pos),
-            ParseError::UnclosedQuote { pos, quote } => write!(f, "Unclosed quote '{}' at position {}", quote, pos),
             ParseError::EmptyInput => write!(f, "Input is empty"),
         }
     }
 }
-
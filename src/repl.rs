@@ -1,47 +1,59 @@
-use std::cell::RefCell;
-use std::rc::Rc;
-use crate::lexer::Lexer;
-use crate::parser::{ Parser, DefaultParser };
-use crate::expander::Expander;
+use crate::ast::AstNode;
+use crate::config::{Config, ConfigLoader, ExecutorType};
+use crate::diagnostic::Diagnostic;
 use crate::environment::Environment;
-use crate::io::InputHandler;
-use crate::executor::{
-    Executor,
-    ExecOutcome,
-    RecursiveExecutor,
-    FlattenExecutor,
-};
 use crate::executor::builtin::{
-    BuiltinManager,
-    HistoryCommand,
+    BgCommand, BuiltinManager, DisownCommand, FgCommand, HistoryCommand, JobsCommand,
+};
+use crate::executor::{
+    ExecError, ExecOutcome, Executor, FlattenExecutor, JobTable, RecursiveExecutor, SignalHandler,
+    TrapHandler,
 };
 use crate::history::HistoryManager;
-use crate::config::{ ConfigLoader, ExecutorType };
+use crate::io::InputHandler;
+use crate::lexer::Lexer;
+use crate::parser::{DefaultParser, ParseError, Parser};
+use std::cell::RefCell;
+use std::rc::Rc;
 
 pub struct Repl;
 
 impl Repl {
     pub fn run() {
-        let config = match ConfigLoader::load_from_file("./.tinyshrc") {
-            Ok(cfg) => cfg,
-            Err(e) => {
-                eprintln!("Failed to load config: {}", e);
-                ConfigLoader::default_config()
-            }
-        };
-
+        let config = Repl::load_config();
         let mut env = Environment::new();
         let history_mgr = Rc::new(RefCell::new(
-            HistoryManager::load(config.history_file.as_str(), config.history_max).unwrap()
+            HistoryManager::load(config.history_file.as_str(), config.history_max).unwrap(),
         ));
-        let mut builtin_mgr = BuiltinManager::new();
-        builtin_mgr.register(Box::new(HistoryCommand { history: Rc::clone(&history_mgr) }));
+        let jobs = Rc::new(RefCell::new(JobTable::new()));
+        let builtin_mgr = Repl::builtins(&history_mgr, &jobs);
+
+        // Job control: own process group and terminal when interactive
+        SignalHandler::handle_signals();
+
+        let mut executor = Repl::executor(&config, &builtin_mgr, &jobs);
 
         loop {
-            let line = match InputHandler::read_line(config.prompt.as_str()) {
-                Ok(l) => l,
+            // Report background jobs that finished since the last prompt
+            for (job, marker) in jobs.borrow_mut().reap() {
+                eprintln!("{}", job.describe(marker));
+            }
+
+            let line = match Repl::read_command(&config.prompt, &config.continuation_prompt) {
+                Ok(line) => line,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {
+                    // Ctrl+C (or a trapped signal) at the prompt: drop the line and start over
+                    SignalHandler::clear_interrupt();
+                    println!();
+                    env.set_last_status(128 + libc::SIGINT);
+                    if let Ok(Some(_)) = TrapHandler::run_pending(executor.as_mut(), &mut env) {
+                        break;
+                    }
+                    continue;
+                }
                 Err(_) => break,
             };
+            SignalHandler::clear_interrupt();
 
             {
                 let mut history = history_mgr.borrow_mut();
@@ -60,48 +72,231 @@ impl Repl {
                 }
             };
 
+            let source = line.as_deref().unwrap_or("");
             let mut parser = match tokens {
                 Ok(ref toks) => DefaultParser::new(toks),
                 Err(ref e) => {
-                    eprintln!("{}", e);
+                    eprint!("{}", Diagnostic::from_lex(e).render(source, None));
+                    env.set_last_status(2);
                     continue;
                 }
             };
-            let ast = parser.parse();
-
-            let cwd = std::env::current_dir().unwrap();
-            let expander = Expander::new(&env, cwd);
-            let expanded = match ast {
-                Ok(ast) => match expander.expand(ast) {
-                    Ok(expanded_ast) => expanded_ast,
-                    Err(e) => {
-                        eprintln!("{}", e);
-                        continue;
-                    }
-                }
+            // Words are expanded by the executor right before each command runs,
+            // so that loop bodies observe variables set by earlier commands.
+            let ast = match parser.parse() {
+                Ok(ast) => ast,
+                Err(ParseError::EmptyInput) => continue, // e.g. only a comment
                 Err(e) => {
-                    eprintln!("{}", e);
+                    eprint!(
+                        "{}",
+                        Diagnostic::from_parse(&e, source).render(source, None)
+                    );
+                    env.set_last_status(2);
                     continue;
                 }
             };
 
-            let mut executor: Box<dyn Executor> = match config.executor_type {
-                ExecutorType::Recursive => Box::new(RecursiveExecutor::new(&builtin_mgr)),
-                _ => Box::new(FlattenExecutor::new(&builtin_mgr)),
-            };
-            match executor.exec(&expanded, &mut env) {
+            let mut result = executor.exec(&ast, &mut env);
+            if SignalHandler::clear_interrupt() {
+                // Move past the echoed ^C
+                println!();
+            }
+            // Signals that arrived during the last command of the line
+            if let Ok(ExecOutcome::Code(_)) = result
+                && let Ok(Some(code)) = TrapHandler::run_pending(executor.as_mut(), &mut env)
+            {
+                result = Ok(ExecOutcome::Exit(code));
+            }
+            match result {
                 Ok(ExecOutcome::Code(_)) => continue,
                 Ok(ExecOutcome::Exit(_)) => break,
+                Err(ExecError::Interrupted) => {
+                    env.set_last_status(128 + libc::SIGINT);
+                    continue;
+                }
+                Err(ExecError::Expand(e)) => {
+                    eprint!(
+                        "{}",
+                        Diagnostic::from_expand(&e, source).render(source, None)
+                    );
+                    env.set_last_status(1);
+                    continue;
+                }
                 Err(e) => {
                     eprintln!("execution error: {}", e);
+                    env.set_last_status(1);
                     continue;
                 }
             }
         }
 
+        if let Err(e) = TrapHandler::run_exit(executor.as_mut(), &mut env) {
+            eprintln!("execution error: {}", e);
+        }
         Repl::cleanup(&history_mgr);
     }
 
+    // Run a script file without prompts or history, with `args` as $1, $2, ... Syntax errors
+    // are reported as file:line:column before anything runs. Returns the exit status for the process.
+    pub fn run_script(path: &str, args: &[String]) -> i32 {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("tiny-shell: {}: {}", path, e);
+                return 127;
+            }
+        };
+        let tokens = match Lexer::new(&source).tokenize_all() {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                eprint!("{}", Diagnostic::from_lex(&e).render(&source, Some(path)));
+                return 2;
+            }
+        };
+        let commands = match DefaultParser::new(&tokens).parse() {
+            Ok(AstNode::Sequence(commands)) => commands,
+            Ok(ast) => vec![ast],
+            Err(ParseError::EmptyInput) => Vec::new(),
+            Err(e) => {
+                eprint!(
+                    "{}",
+                    Diagnostic::from_parse(&e, &source).render(&source, Some(path))
+                );
+                return 2;
+            }
+        };
+
+        let config = Repl::load_config();
+        let mut env = Environment::new();
+        env.set_script_name(path);
+        env.set_positional(args.to_vec());
+        // Scripts do not record history
+        let history_mgr = Rc::new(RefCell::new(HistoryManager {
+            entries: Vec::new(),
+            max_len: config.history_max,
+            file_path: None,
+        }));
+        let jobs = Rc::new(RefCell::new(JobTable::new()));
+        let builtin_mgr = Repl::builtins(&history_mgr, &jobs);
+        SignalHandler::handle_signals();
+        let mut executor = Repl::executor(&config, &builtin_mgr, &jobs);
+
+        // Top-level commands run one at a time, like lines typed at the prompt
+        let mut exit = None;
+        for command in &commands {
+            let mut result = executor.exec(command, &mut env);
+            // As at the prompt, Ctrl+C abandons only the command it interrupted
+            SignalHandler::clear_interrupt();
+            if let Ok(ExecOutcome::Code(_)) = result
+                && let Ok(Some(code)) = TrapHandler::run_pending(executor.as_mut(), &mut env)
+            {
+                result = Ok(ExecOutcome::Exit(code));
+            }
+            match result {
+                Ok(ExecOutcome::Code(_)) => {}
+                Ok(ExecOutcome::Exit(code)) => {
+                    exit = Some(code);
+                    break;
+                }
+                Err(ExecError::Interrupted) => env.set_last_status(128 + libc::SIGINT),
+                Err(ExecError::Expand(e)) => {
+                    eprint!(
+                        "{}",
+                        Diagnostic::from_expand(&e, &source).render(&source, Some(path))
+                    );
+                    env.set_last_status(1);
+                }
+                Err(e) => {
+                    eprintln!("execution error: {}", e);
+                    env.set_last_status(1);
+                }
+            }
+        }
+
+        match TrapHandler::run_exit(executor.as_mut(), &mut env) {
+            Ok(Some(code)) => code,
+            Ok(None) => exit.unwrap_or(env.last_status()),
+            Err(e) => {
+                eprintln!("execution error: {}", e);
+                exit.unwrap_or(env.last_status())
+            }
+        }
+    }
+
+    fn load_config() -> Config {
+        match ConfigLoader::load_from_file("./.tinyshrc") {
+            Ok(cfg) => cfg,
+            Err(e) => {
+                eprintln!("Failed to load config: {}", e);
+                ConfigLoader::default_config()
+            }
+        }
+    }
+
+    fn builtins(
+        history: &Rc<RefCell<HistoryManager>>,
+        jobs: &Rc<RefCell<JobTable>>,
+    ) -> BuiltinManager {
+        let mut builtin_mgr = BuiltinManager::new();
+        builtin_mgr.register(Box::new(HistoryCommand {
+            history: Rc::clone(history),
+        }));
+        builtin_mgr.register(Box::new(JobsCommand {
+            jobs: Rc::clone(jobs),
+        }));
+        builtin_mgr.register(Box::new(FgCommand {
+            jobs: Rc::clone(jobs),
+        }));
+        builtin_mgr.register(Box::new(BgCommand {
+            jobs: Rc::clone(jobs),
+        }));
+        builtin_mgr.register(Box::new(DisownCommand {
+            jobs: Rc::clone(jobs),
+        }));
+        builtin_mgr
+    }
+
+    fn executor<'a>(
+        config: &Config,
+        builtin_mgr: &'a BuiltinManager,
+        jobs: &Rc<RefCell<JobTable>>,
+    ) -> Box<dyn Executor + 'a> {
+        match config.executor_type {
+            ExecutorType::Recursive => {
+                Box::new(RecursiveExecutor::new(builtin_mgr, Rc::clone(jobs)))
+            }
+            _ => Box::new(FlattenExecutor::new(builtin_mgr, Rc::clone(jobs))),
+        }
+    }
+
+    // Read lines until they form a complete command (closed quotes, here-documents,
+    // blocks and pipelines), showing the continuation prompt for the later ones
+    fn read_command(prompt: &str, continuation_prompt: &str) -> std::io::Result<Option<String>> {
+        let Some(mut line) = InputHandler::read_line(prompt)? else {
+            return Ok(None);
+        };
+        while Repl::needs_more_input(&line) {
+            match InputHandler::read_line(continuation_prompt)? {
+                Some(more) => {
+                    line.push('\n');
+                    line.push_str(&more);
+                }
+                None => break, // EOF: the lexer or parser reports what is missing
+            }
+        }
+        Ok(Some(line))
+    }
+
+    fn needs_more_input(input: &str) -> bool {
+        match Lexer::new(input).tokenize_all() {
+            Ok(tokens) => matches!(
+                DefaultParser::new(&tokens).parse(),
+                Err(ParseError::UnexpectedEof)
+            ),
+            Err(e) => e.is_incomplete(),
+        }
+    }
+
     fn cleanup(history_mgr: &Rc<RefCell<HistoryManager>>) {
         println!("Exiting shell...");
         let history = history_mgr.borrow();
@@ -110,22 +305,3 @@ impl Repl {
         }
     }
 }
-
-// fn read_logical_line(prompt: &ShellPrompt) -> std::io::Result<String> {
-//     let mut lines = String::new();
-//
-//     loop {
-//         prompt.show_prompt(); // change to `> `
-//         let mut line = prompt.read_line()?;
-//         if line.trim_end().ends_with('\\') {
-//             // Remove `\` before newline and concatenate
-//             line = line.trim_end().trim_end_matches('\\').to_string();
-//             lines.push_str(&line);
-//             // Add a space or a line break to the end
-//         } else {
-//             lines.push_str(&line);
-//             break;
-//         }
-//     }
-//     Ok(lines)
-// }